@@ -33,7 +33,16 @@ pub struct ModuleDef {
 #[derive(Debug)]
 pub struct IngestStmt {}
 #[derive(Debug)]
-pub struct GenStmt {}
+pub struct GenStmt {
+    pub name: String,
+    // None until the parser understands the generator's body
+    pub body: Option<GenExpr>,
+}
+
+#[derive(Debug, Clone)]
+pub enum GenExpr {
+    Regex(String),
+}
 #[derive(Debug)]
 pub struct TypeStmt {}
 #[derive(Debug)]
@@ -1,4 +1,5 @@
 use logos::Lexer;
+use oracle::common::OracleError;
 use std::ops::Range;
 use thiserror::Error;
 
@@ -14,4 +15,6 @@ pub enum MimikoError {
     ParserUnexpectedEndSequence { range: Range<usize> },
     #[error("KERNEL | Parser error: {0}")]
     ParserError(String),
+    #[error(transparent)]
+    OracleError(#[from] OracleError),
 }
@@ -0,0 +1,143 @@
+use std::ops::Range;
+
+use oracle::common::OracleError;
+use oracle::engine::{Oracle, Token};
+use oracle::parallel::Executor;
+use tseger::cache::DfaCache;
+use tseger::dfa::Dfa;
+
+use crate::ast::*;
+use crate::common::MimikoError;
+
+/*
+* Evaluation of the parsed program, generators whose bodies the parser
+* understands become Oracles:
+*
+* - `regex "..."!` compiles the pattern with tseger once per run and hands
+*   the automaton to the Oracle as a single block; with a cache directory the
+*   compiled automaton is stored there and reused by later runs
+* - rows() runs on every core, each row seeded from the seed, the generator's
+*   name and the row's index, so the output doesn't depend on the thread count
+*/
+
+pub struct Generator {
+    pub name: String,
+    // Compiled once, every rayon job clones it
+    automaton: Dfa,
+}
+
+impl Generator {
+    pub fn rows(&self, seed: u64, rows: Range<u64>) -> Result<Vec<String>, MimikoError> {
+        let automaton = &self.automaton;
+        Ok(Executor::new(seed, &self.name).run_parallel(move || build_oracle(automaton), rows)?)
+    }
+}
+
+/// Bad patterns are reported here rather than on the first row.
+pub fn generators(
+    program: &ProgramAST,
+    cache: Option<&DfaCache>,
+) -> Result<Vec<Generator>, MimikoError> {
+    program
+        .statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            StmtDecl::GenStmtDecl(GenStmt {
+                name,
+                body: Some(body),
+            }) => Some((name, body)),
+            _ => None,
+        })
+        .map(|(name, body)| {
+            let automaton = compile(body, cache)?;
+            build_oracle(&automaton)?;
+            Ok(Generator {
+                name: name.clone(),
+                automaton,
+            })
+        })
+        .collect()
+}
+
+fn compile(body: &GenExpr, cache: Option<&DfaCache>) -> Result<Dfa, OracleError> {
+    match (body, cache) {
+        (GenExpr::Regex(pattern), Some(cache)) => Ok(cache.compile(pattern)?),
+        (GenExpr::Regex(pattern), None) => Ok(Dfa::compile(pattern)?),
+    }
+}
+
+fn build_oracle(automaton: &Dfa) -> Result<Oracle, OracleError> {
+    Oracle::new(&[Token::Automaton(automaton.clone())])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use logos::Logos;
+
+    use super::*;
+    use crate::lexer;
+    use crate::parser;
+
+    fn parse(src: &str) -> ProgramAST {
+        parser::Parser {}
+            .parse(&mut lexer::Token::lexer(src))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_regex_generators_run_through_the_oracle() {
+        let program = parse(
+            r#"
+            gen zip_code ( regex "[1-9][0-9]{4}"! ) -> str;
+            gen other ( rand_int |> 3 ) -> int;
+            "#,
+        );
+        let built = generators(&program, None).unwrap();
+        assert_eq!(built.len(), 1);
+        assert_eq!(built[0].name, "zip_code");
+
+        let rows = built[0].rows(3, 0..50).unwrap();
+        let zip = Dfa::compile("[1-9][0-9]{4}").unwrap();
+        assert!(rows.iter().all(|value| zip.is_match(value)), "{:?}", rows);
+
+        // Sharded over all cores or row by row on this thread, same values
+        let mut oracle = build_oracle(&built[0].automaton).unwrap();
+        let sequential = Executor::new(3, "zip_code")
+            .run(&mut oracle, 0..50)
+            .unwrap();
+        assert_eq!(rows, sequential);
+
+        let program = parse(r#"gen broken ( regex "[1-"! ) -> str;"#);
+        assert!(matches!(
+            generators(&program, None),
+            Err(MimikoError::OracleError(OracleError::TsegerError(_)))
+        ));
+    }
+
+    #[test]
+    fn test_compiled_patterns_are_cached_between_runs() {
+        let dir = env::temp_dir().join(format!("kernel-cache-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = DfaCache::new(&dir);
+        let program = parse(r#"gen plate ( regex "[A-Z]{2}-[0-9]{3}"! ) -> str;"#);
+
+        let first = generators(&program, Some(&cache)).unwrap();
+        let key = DfaCache::key("tseger", "[A-Z]{2}-[0-9]{3}");
+        assert_eq!(
+            cache.load(&key).unwrap().as_ref(),
+            Some(&first[0].automaton)
+        );
+
+        // A later run reads the entry instead of compiling
+        let second = generators(&program, Some(&cache)).unwrap();
+        assert_eq!(
+            second[0].rows(9, 0..20).unwrap(),
+            first[0].rows(9, 0..20).unwrap()
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
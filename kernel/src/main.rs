@@ -1,24 +1,55 @@
 mod ast;
 mod cli;
 mod common;
+mod eval;
 mod lexer;
 mod parser;
 
+use std::env;
 use std::fs;
 
 use logos::Logos;
 use parser::Parser;
+use tseger::cache::DfaCache;
 
 fn main() -> Result<(), ()> {
     let src = fs::read_to_string("docs/syntax-sketches/v1.mim").expect("shit happens");
     let mut lex = lexer::Token::lexer(src.as_str());
     let parser = Parser {};
 
-    match parser.parse(&mut lex) {
-        Ok(prog) => println!("{prog:?}"),
-        Err(e) => eprintln!("{}", e.to_string()),
+    let prog = match parser.parse(&mut lex) {
+        Ok(prog) => prog,
+        Err(e) => {
+            eprintln!("{}", e.to_string());
+            return Err(());
+        }
+    };
+    println!("{prog:?}");
+
+    // MIMIKO_SEED=<u64> replays an earlier run, the seed used is logged either way
+    let seed: u64 = match env::var("MIMIKO_SEED") {
+        Ok(text) => match text.parse() {
+            Ok(seed) => seed,
+            Err(e) => {
+                eprintln!("MIMIKO_SEED={text:?} isn't a seed: {e}");
+                return Err(());
+            }
+        },
+        Err(_) => rand::random(),
+    };
+    eprintln!("seed: {seed}");
+    let cache = DfaCache::default_dir().map(DfaCache::new);
+    match eval::generators(&prog, cache.as_ref()) {
+        Ok(generators) => {
+            for generator in generators {
+                match generator.rows(seed, 0..5) {
+                    Ok(rows) => println!("{}: {}", generator.name, rows.join(", ")),
+                    Err(e) => eprintln!("{e}"),
+                }
+            }
+        }
+        Err(e) => eprintln!("{e}"),
     }
-    //program.eval();
 
     Ok(())
 }
@@ -186,13 +186,43 @@ impl Parser {
     }
 
     fn parse_generator_statement(&self, lexer: &mut Lexer<Token>) -> ParserResult<StmtDecl> {
+        let name = match lexer.next() {
+            Some(Ok(Token::Identifier(id))) => id,
+            Some(_) => {
+                return Err(MimikoError::ParserUnexpectedToken {
+                    range: lexer.span(),
+                    token: lexer.slice().to_owned(),
+                });
+            }
+            None => {
+                return Err(MimikoError::ParserUnexpectedEndSequence {
+                    range: lexer.span(),
+                });
+            }
+        };
+
+        let mut tokens: Vec<Token> = Vec::new();
         while let Some(Ok(tok)) = lexer.next() {
             if tok == Token::EndStmt {
                 break;
             }
+            tokens.push(tok);
         }
 
-        Ok(StmtDecl::GenStmtDecl(GenStmt {}))
+        // Only `( regex "..."! )` so far, other bodies are skipped
+        let body = match tokens.as_slice() {
+            [
+                Token::LtParen,
+                Token::Regex,
+                Token::String(pattern),
+                Token::Exec,
+                Token::RtParen,
+                ..,
+            ] => Some(GenExpr::Regex(pattern.clone())),
+            _ => None,
+        };
+
+        Ok(StmtDecl::GenStmtDecl(GenStmt { name, body }))
     }
 
     fn parse_type_statement(&self, lexer: &mut Lexer<Token>) -> ParserResult<StmtDecl> {
@@ -0,0 +1,125 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use oracle::engine::{Oracle, Token};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use tseger::dfa::Dfa;
+use tseger::generate::Sampler;
+use tseger::parser::Parser;
+
+/*
+* The old emission against exec() and fill_batch() on the same chains, 1024
+* values per iteration:
+*
+* - vec_char is the emission before blocks wrote into a caller-provided
+*   buffer, rebuilt here: every block collects a Vec<char>, turns it into a
+*   String and the value is collected from those
+* - exec hands out a new String per value but writes the blocks into it
+* - fill_batch reuses the batch's strings, after the first iteration nothing
+*   is allocated at all
+*/
+
+const BATCH: usize = 1024;
+// Same cap the Oracle puts on unbounded regex blocks
+const MAX_LENGTH: usize = 32;
+
+enum OldBlock {
+    SingleChar(char),
+    Range { from: char, to: char },
+    Regex(Sampler),
+}
+
+fn old_chain(tokens: &[Token]) -> Vec<OldBlock> {
+    let regex = |dfa: Dfa| {
+        let max_len = dfa.longest().unwrap_or(MAX_LENGTH);
+        OldBlock::Regex(Sampler::new(&dfa, max_len).unwrap())
+    };
+
+    tokens
+        .iter()
+        .map(|token| match token {
+            Token::SingleChar(c) => OldBlock::SingleChar(*c),
+            Token::Range { from, to } => OldBlock::Range {
+                from: *from,
+                to: *to,
+            },
+            Token::Regex(ast) => regex(Dfa::from_ast(ast).unwrap()),
+            Token::Automaton(dfa) => regex(dfa.clone()),
+        })
+        .collect()
+}
+
+fn old_emission(chain: &[OldBlock], rng: &mut ChaCha8Rng) -> String {
+    chain
+        .iter()
+        .map(|block| {
+            let chars: Vec<char> = match block {
+                OldBlock::SingleChar(c) => vec![*c],
+                OldBlock::Range { from, to } => vec![rng.random_range(*from..*to)],
+                OldBlock::Regex(sampler) => sampler.sample(rng).chars().collect(),
+            };
+            chars.iter().collect::<String>()
+        })
+        .collect()
+}
+
+fn chains() -> Vec<(&'static str, Vec<Token>)> {
+    let regex = |pattern| Token::Regex(Parser::new().parse(pattern).unwrap());
+
+    vec![
+        (
+            "ranges",
+            (0..16)
+                .map(|_| Token::Range { from: 'a', to: '{' })
+                .collect(),
+        ),
+        (
+            "regex",
+            vec![
+                regex("[A-Z][a-z]{2,10}"),
+                Token::SingleChar('.'),
+                regex("[a-z]{3,12}[0-9]{0,4}"),
+                regex("@(example|mail)\\.(com|org)"),
+            ],
+        ),
+    ]
+}
+
+fn emission(c: &mut Criterion) {
+    let mut group = c.benchmark_group("emission");
+    group.throughput(Throughput::Elements(BATCH as u64));
+
+    for (name, tokens) in chains() {
+        let mut oracle = Oracle::new(&tokens).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        let old = old_chain(&tokens);
+        group.bench_function(BenchmarkId::new("vec_char", name), |b| {
+            b.iter(|| {
+                (0..BATCH)
+                    .map(|_| old_emission(&old, &mut rng))
+                    .collect::<Vec<String>>()
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("exec", name), |b| {
+            b.iter(|| {
+                (0..BATCH)
+                    .map(|_| oracle.exec(&mut rng).unwrap())
+                    .collect::<Vec<String>>()
+            })
+        });
+
+        let mut batch = Vec::new();
+        group.bench_function(BenchmarkId::new("fill_batch", name), |b| {
+            b.iter(|| {
+                oracle.fill_batch(&mut rng, &mut batch, BATCH).unwrap();
+                batch.len()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, emission);
+criterion_main!(benches);
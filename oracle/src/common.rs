@@ -0,0 +1,28 @@
+use thiserror::Error;
+use tseger::common::TsegerError;
+
+#[derive(Error, Debug)]
+pub enum OracleError {
+    #[error("ORACLE | Block {index} ({block}) can't produce any value: {reason}")]
+    EmptyBlock {
+        index: usize,
+        block: String,
+        reason: String,
+    },
+    #[error("ORACLE | Constraint error: {0}")]
+    ConstraintError(String),
+    #[error("ORACLE | Rejection error: {0}")]
+    RejectionError(String),
+    #[error("ORACLE | Distribution error: {0}")]
+    DistributionError(String),
+    #[error("ORACLE | Record error: {0}")]
+    RecordError(String),
+    #[error("ORACLE | Uniqueness error: {0}")]
+    UniquenessError(String),
+    #[error("ORACLE | Solver error: {0}")]
+    SolverError(String),
+    #[error("ORACLE | Markov error: {0}")]
+    MarkovError(String),
+    #[error(transparent)]
+    TsegerError(#[from] TsegerError),
+}
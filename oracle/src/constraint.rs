@@ -0,0 +1,149 @@
+use std::fmt;
+
+use tseger::dfa::Dfa;
+use tseger::parser::RegexAST;
+
+use crate::common::OracleError;
+
+/*
+* Constraints narrowing what an Oracle produces:
+*
+* - regex, length, prefix, suffix and forbidden substrings are regular, they
+*   have an automaton that gets intersected with the Oracle's own (pushdown),
+*   so matching values are sampled directly and nothing is thrown away
+* - predicates are arbitrary Rust code, values failing them are rejected and
+*   generated again
+*/
+
+pub enum Constraint {
+    Regex {
+        pattern: String,
+        dfa: Dfa,
+    },
+    Length {
+        min: usize,
+        max: Option<usize>,
+    },
+    Prefix(String),
+    Suffix(String),
+    Forbidden(String),
+    Predicate {
+        name: String,
+        test: Box<dyn Fn(&str) -> bool>,
+    },
+}
+
+impl Constraint {
+    pub fn regex(pattern: &str) -> Result<Self, OracleError> {
+        Ok(Self::Regex {
+            pattern: pattern.to_string(),
+            dfa: Dfa::compile(pattern)?,
+        })
+    }
+
+    /// Named Rust predicate, the name shows up in errors.
+    pub fn predicate(name: &str, test: impl Fn(&str) -> bool + 'static) -> Self {
+        Self::Predicate {
+            name: name.to_string(),
+            test: Box::new(test),
+        }
+    }
+
+    /// Automaton of the values the constraint lets through, None for predicates.
+    pub fn automaton(&self) -> Result<Option<Dfa>, OracleError> {
+        let any = || RegexAST::Star(Box::new(any_char()));
+
+        let dfa = match self {
+            Self::Regex { dfa, .. } => dfa.clone(),
+            Self::Length { min, max } => Dfa::length_range(*min, *max)?,
+            Self::Prefix(prefix) => Dfa::from_ast(&RegexAST::Concat(vec![literal(prefix), any()]))?,
+            Self::Suffix(suffix) => Dfa::from_ast(&RegexAST::Concat(vec![any(), literal(suffix)]))?,
+            Self::Forbidden(substring) => {
+                Dfa::from_ast(&RegexAST::Concat(vec![any(), literal(substring), any()]))?
+                    .complement()
+            }
+            Self::Predicate { .. } => return Ok(None),
+        };
+
+        Ok(Some(dfa))
+    }
+
+    pub fn accepts(&self, value: &str) -> bool {
+        match self {
+            Self::Regex { dfa, .. } => dfa.is_match(value),
+            Self::Length { min, max } => {
+                let len = value.chars().count();
+                len >= *min && max.is_none_or(|max| len <= max)
+            }
+            Self::Prefix(prefix) => value.starts_with(prefix.as_str()),
+            Self::Suffix(suffix) => value.ends_with(suffix.as_str()),
+            Self::Forbidden(substring) => !value.contains(substring.as_str()),
+            Self::Predicate { test, .. } => test(value),
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Regex { pattern, .. } => write!(f, "regex {:?}", pattern),
+            Self::Length {
+                min,
+                max: Some(max),
+            } => write!(f, "length {}..={}", min, max),
+            Self::Length { min, max: None } => write!(f, "length {}..", min),
+            Self::Prefix(prefix) => write!(f, "prefix {:?}", prefix),
+            Self::Suffix(suffix) => write!(f, "suffix {:?}", suffix),
+            Self::Forbidden(substring) => write!(f, "forbidden {:?}", substring),
+            Self::Predicate { name, .. } => write!(f, "predicate {}", name),
+        }
+    }
+}
+
+fn literal(text: &str) -> RegexAST {
+    RegexAST::Concat(text.chars().map(RegexAST::Literal).collect())
+}
+
+// `.` leaves out newlines, a negated empty class doesn't
+fn any_char() -> RegexAST {
+    RegexAST::BracketExpression {
+        negated: true,
+        items: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_automata_agree_with_checks() {
+        let constraints = [
+            Constraint::regex("[a-c]+").unwrap(),
+            Constraint::Length {
+                min: 2,
+                max: Some(3),
+            },
+            Constraint::Prefix("ab".to_string()),
+            Constraint::Suffix("\n".to_string()),
+            Constraint::Forbidden("aa".to_string()),
+        ];
+
+        for constraint in &constraints {
+            let dfa = constraint.automaton().unwrap().unwrap();
+            for value in ["", "ab", "abc", "aab", "ab\n", "c\nab", "baaa"] {
+                assert_eq!(
+                    dfa.is_match(value),
+                    constraint.accepts(value),
+                    "{} on {:?}",
+                    constraint,
+                    value
+                );
+            }
+        }
+
+        let even = Constraint::predicate("even", |v| v.len() % 2 == 0);
+        assert!(even.automaton().unwrap().is_none());
+        assert_eq!(even.to_string(), "predicate even");
+    }
+}
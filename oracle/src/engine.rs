@@ -1,74 +1,431 @@
-use std::{cell::RefCell, rc::Rc};
+use std::fmt;
 
 use rand::prelude::*;
+use tseger::dfa::Dfa;
+use tseger::generate::Sampler;
+use tseger::generator::Cardinality;
+use tseger::generator::Generator;
+use tseger::parser::BracketExpressionItem;
+use tseger::parser::RegexAST;
+
+use crate::common::OracleError;
+use crate::constraint::Constraint;
+use crate::unique::{SeenSet, Uniqueness};
+
+/*
+* The Oracle starts from a base, a chain of blocks or any Generator, and
+* narrows it down with constraints:
+*
+* - a chain has an automaton, regular constraints are intersected into it as
+*   they are added and values are sampled from the result, so they never fail
+* - predicates, and every constraint on a Generator base, are checked after
+*   the fact, failing values are thrown away and generated again, at most
+*   `retry_budget` times per value
+* - a block that can't produce anything, an empty range or a regex without
+*   matches short enough, is reported when the chain is built, naming the block
+* - a constraint that leaves no values at all is reported when it is added,
+*   naming the constraints applied before it
+* - uniqueness is checked last, a repeated value is rejected like one failing a
+*   predicate, and asking for more unique values than the base has fails early
+* - blocks write straight into the caller's buffer and a rejected value is cut
+*   off it again, so fill_batch() allocates nothing once the batch's strings
+*   have grown, only exec() hands out a fresh String per value
+*/
+
+const DEFAULT_RETRY_BUDGET: usize = 1000;
+// Longest value of a regex block whose matches can get arbitrarily long
+const DEFAULT_MAX_LENGTH: usize = 32;
 
 pub struct Oracle {
-    chain: Vec<Box<Block>>,
+    source: Source,
+    // Constraints that couldn't be pushed into an automaton, checked in order
+    filters: Vec<Constraint>,
+    // Constraints already intersected into the automaton, for error messages
+    pushed: Vec<String>,
+    unique: Option<SeenSet>,
+    retry_budget: usize,
+    // Per filter, values it rejected during the current exec
+    rejected: Vec<usize>,
+}
+
+enum Source {
+    Chain(Vec<Block>),
+    Automaton {
+        dfa: Dfa,
+        sampler: Box<Sampler>,
+        max_len: usize,
+    },
+    Generator(Box<dyn Generator>),
 }
 
 trait Emittable {
-    fn emit(&self) -> Option<Vec<char>>;
+    /// Appends to `out`, blocks that can't produce anything are refused when built.
+    fn emit(&self, rng: &mut dyn RngCore, out: &mut String);
 }
 
-enum Token {
+pub enum Token {
     SingleChar(char),
     Range { from: char, to: char },
+    Regex(RegexAST),
+    Automaton(Dfa),
 }
 
 struct Block {
     kind: BlockKind,
-    rng: RefCell<ThreadRng>,
 }
 
 impl Block {
     pub fn new(kind: BlockKind) -> Self {
-        Self {
-            kind,
-            rng: RefCell::new(rand::rng()),
-        }
+        Self { kind }
     }
 }
 
 enum BlockKind {
-    SingleCharBlock(char),
-    RangeBlock { from: char, to: char },
+    SingleChar(char),
+    Range {
+        from: char,
+        to: char,
+    },
+    Regex {
+        dfa: Dfa,
+        sampler: Box<Sampler>,
+        max_len: usize,
+    },
 }
 
-impl Emittable for Block {
-    fn emit(&self) -> Option<Vec<char>> {
-        match self.kind {
-            BlockKind::SingleCharBlock(c) => Some(vec![c]),
-            BlockKind::RangeBlock { from, to } => {
-                Some(vec![self.rng.borrow_mut().random_range(from..to)])
+impl Block {
+    // Err is the reason the block can't produce anything
+    fn regex(dfa: Dfa, max_length: usize) -> Result<Self, String> {
+        if dfa.is_empty() {
+            return Err("it matches nothing".to_string());
+        }
+
+        let max_len = dfa.longest().unwrap_or(max_length);
+        let sampler = Sampler::new(&dfa, max_len)
+            .map_err(|_| format!("none of its matches is at most {} characters long", max_len))?;
+
+        Ok(Self::new(BlockKind::Regex {
+            sampler: Box::new(sampler),
+            dfa,
+            max_len,
+        }))
+    }
+
+    fn max_len(&self) -> usize {
+        match &self.kind {
+            BlockKind::SingleChar(_) | BlockKind::Range { .. } => 1,
+            BlockKind::Regex { max_len, .. } => *max_len,
+        }
+    }
+
+    // One character for single chars and ranges, ranges exclude `to` like random_range does
+    fn dfa(&self) -> Result<Dfa, OracleError> {
+        let ast = match &self.kind {
+            BlockKind::SingleChar(c) => RegexAST::Literal(*c),
+            BlockKind::Range { from, to } => RegexAST::BracketExpression {
+                negated: false,
+                items: (*from..*to)
+                    .next_back()
+                    .map(|last| BracketExpressionItem::Range(*from, last))
+                    .into_iter()
+                    .collect(),
+            },
+            BlockKind::Regex { dfa, .. } => return Ok(dfa.clone()),
+        };
+
+        Ok(Dfa::from_ast(&ast)?)
+    }
+
+    fn cardinality(&self) -> Cardinality {
+        match &self.kind {
+            BlockKind::SingleChar(_) => Cardinality::Finite(1),
+            BlockKind::Range { from, to } => {
+                Cardinality::Finite(u128::from((*to as u32).saturating_sub(*from as u32)))
             }
+            BlockKind::Regex { sampler, .. } => sampler
+                .count()
+                .map_or(Cardinality::Infinite, Cardinality::Finite),
+        }
+    }
+}
+
+impl Emittable for Block {
+    fn emit(&self, rng: &mut dyn RngCore, out: &mut String) {
+        match &self.kind {
+            BlockKind::SingleChar(c) => out.push(*c),
+            BlockKind::Range { from, to } => out.push(rng.random_range(*from..*to)),
+            BlockKind::Regex { sampler, .. } => sampler.sample_into(rng, out),
         }
     }
 }
 
 impl Oracle {
-    pub fn new(tokens: &Vec<Token>) -> Self {
+    pub fn new(tokens: &[Token]) -> Result<Self, OracleError> {
+        Self::with_max_length(tokens, DEFAULT_MAX_LENGTH)
+    }
+
+    /// Values of unbounded regex blocks are cut at `max_length` characters.
+    pub fn with_max_length(tokens: &[Token], max_length: usize) -> Result<Self, OracleError> {
+        Ok(Self::from_source(Source::Chain(build_chain(
+            tokens, max_length,
+        )?)))
+    }
+
+    /// Any generator as the base, its constraints can only be checked by rejection.
+    pub fn from_generator(generator: Box<dyn Generator>) -> Self {
+        Self::from_source(Source::Generator(generator))
+    }
+
+    fn from_source(source: Source) -> Self {
         Self {
-            chain: build_chain(tokens),
+            source,
+            filters: Vec::new(),
+            pushed: Vec::new(),
+            unique: None,
+            retry_budget: DEFAULT_RETRY_BUDGET,
+            rejected: Vec::new(),
+        }
+    }
+
+    pub fn constrain(mut self, constraint: Constraint) -> Result<Self, OracleError> {
+        let allowed = match self.source {
+            Source::Generator(_) => None,
+            _ => constraint.automaton()?,
+        };
+        let Some(allowed) = allowed else {
+            self.filters.push(constraint);
+            self.rejected.push(0);
+            return Ok(self);
+        };
+
+        let (dfa, max_len) = match &self.source {
+            Source::Chain(chain) => {
+                let parts = chain
+                    .iter()
+                    .map(|block| block.dfa())
+                    .collect::<Result<Vec<Dfa>, OracleError>>()?;
+                (
+                    Dfa::concat(&parts)?,
+                    chain.iter().map(|b| b.max_len()).sum(),
+                )
+            }
+            Source::Automaton { dfa, max_len, .. } => (dfa.clone(), *max_len),
+            Source::Generator(_) => unreachable!("generators have no automaton"),
+        };
+
+        let narrowed = dfa.intersect(&allowed)?;
+        if narrowed.is_empty() {
+            let context = match self.pushed.is_empty() {
+                true => "the base".to_string(),
+                false => self.pushed.join(", "),
+            };
+            return Err(OracleError::ConstraintError(format!(
+                "{} can't be satisfied together with {}",
+                constraint, context
+            )));
         }
+
+        self.source = Source::Automaton {
+            sampler: Box::new(Sampler::new(&narrowed, max_len)?),
+            dfa: narrowed,
+            max_len,
+        };
+        self.pushed.push(constraint.to_string());
+        Ok(self)
     }
 
-    pub fn exec(&self) -> String {
-        self.chain
+    /// Every value differs from the ones before it, `count` of them are going to
+    /// be asked for. Cardinality is an upper bound, so this only fails early when
+    /// `count` is certainly out of reach.
+    pub fn unique(mut self, backing: Uniqueness, count: u128) -> Result<Self, OracleError> {
+        if let Cardinality::Finite(available) = self.cardinality()
+            && available < count
+        {
+            return Err(OracleError::ConstraintError(format!(
+                "{} unique values were requested, at most {} exist",
+                count, available
+            )));
+        }
+
+        let expected = usize::try_from(count).unwrap_or(usize::MAX);
+        self.unique = Some(SeenSet::new(&backing, expected)?);
+        Ok(self)
+    }
+
+    /// Whether unique() was set, such Oracles keep state between values.
+    pub fn is_unique(&self) -> bool {
+        self.unique.is_some()
+    }
+
+    /// Attempts per value before rejection sampling gives up.
+    pub fn retry_budget(mut self, attempts: usize) -> Self {
+        self.retry_budget = attempts.max(1);
+        self
+    }
+
+    /// Same RNG state, same output, e.g. with a seeded `ChaCha8Rng`.
+    pub fn exec(&mut self, rng: &mut dyn RngCore) -> Result<String, OracleError> {
+        let mut value = String::new();
+        self.exec_into(rng, &mut value)?;
+        Ok(value)
+    }
+
+    /// exec appending to `out`, which is left as it was on errors.
+    pub fn exec_into(
+        &mut self,
+        rng: &mut dyn RngCore,
+        out: &mut String,
+    ) -> Result<(), OracleError> {
+        let start = out.len();
+        self.rejected.fill(0);
+        let mut duplicates = 0;
+
+        for _ in 0..self.retry_budget {
+            out.truncate(start);
+            if let Err(e) = self.source.next_into(rng, out) {
+                out.truncate(start);
+                return Err(e);
+            }
+
+            let value = &out[start..];
+            match self.filters.iter().position(|f| !f.accepts(value)) {
+                Some(i) => self.rejected[i] += 1,
+                None => {
+                    let repeated = match self.unique.as_mut().map(|seen| seen.insert(value)) {
+                        Some(Ok(new)) => !new,
+                        Some(Err(e)) => {
+                            out.truncate(start);
+                            return Err(e);
+                        }
+                        None => false,
+                    };
+                    if !repeated {
+                        return Ok(());
+                    }
+                    duplicates += 1;
+                }
+            }
+        }
+        out.truncate(start);
+
+        let (worst, count) = self
+            .filters
             .iter()
-            .map(|ch| match ch.emit() {
-                Some(v) => v.iter().map(|el| *el).collect::<String>(),
-                _ => todo!("not implemented"),
-            })
-            .collect()
+            .map(|f| f.to_string())
+            .zip(self.rejected.iter().copied())
+            .chain(
+                self.unique
+                    .iter()
+                    .map(|_| ("uniqueness".to_string(), duplicates)),
+            )
+            .max_by_key(|(_, count)| *count)
+            .expect("only filters and uniqueness reject values");
+        Err(OracleError::RejectionError(format!(
+            "No value passed the constraints in {} attempts, {} rejected {} of them",
+            self.retry_budget, worst, count
+        )))
+    }
+
+    /// Leaves `n` values in `batch`, overwriting the strings already there so
+    /// their buffers are reused from one batch to the next. On errors `batch`
+    /// is cut down to the values filled before the one that failed.
+    pub fn fill_batch(
+        &mut self,
+        rng: &mut dyn RngCore,
+        batch: &mut Vec<String>,
+        n: usize,
+    ) -> Result<(), OracleError> {
+        batch.resize_with(n, String::new);
+        for i in 0..n {
+            batch[i].clear();
+            if let Err(e) = self.exec_into(rng, &mut batch[i]) {
+                batch.truncate(i);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Source {
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> Result<(), OracleError> {
+        match self {
+            Self::Chain(chain) => {
+                chain.iter().for_each(|block| block.emit(rng, out));
+                Ok(())
+            }
+            Self::Automaton { sampler, .. } => {
+                sampler.sample_into(rng, out);
+                Ok(())
+            }
+            Self::Generator(generator) => match generator.next_into(rng, out) {
+                true => Ok(()),
+                false => Err(OracleError::RejectionError(
+                    "Base generator ran out of values".to_string(),
+                )),
+            },
+        }
     }
 }
 
-fn build_chain(tokens: &Vec<Token>) -> Vec<Box<Block>> {
+impl Generator for Oracle {
+    /// A value that exhausts the retry budget ends the stream.
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        self.exec_into(rng, out).is_ok()
+    }
+
+    // An upper bound, filters only ever lower it
+    fn cardinality(&self) -> Cardinality {
+        match &self.source {
+            Source::Chain(chain) => chain
+                .iter()
+                .map(|block| block.cardinality())
+                .fold(Cardinality::Finite(1), Cardinality::times),
+            Source::Automaton { sampler, .. } => sampler
+                .count()
+                .map_or(Cardinality::Infinite, Cardinality::Finite),
+            Source::Generator(generator) => generator.cardinality(),
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SingleChar(c) => write!(f, "{:?}", c),
+            Self::Range { from, to } => write!(f, "{:?}..{:?}", from, to),
+            Self::Regex(ast) => write!(f, "regex {:?}", ast.to_string()),
+            Self::Automaton(dfa) => write!(f, "automaton of {} states", dfa.states().len()),
+        }
+    }
+}
+
+fn build_chain(tokens: &[Token], max_length: usize) -> Result<Vec<Block>, OracleError> {
     tokens
         .iter()
-        .map(|token| match *token {
-            Token::SingleChar(c) => Box::new(Block::new(BlockKind::SingleCharBlock(c))),
-            Token::Range { from, to } => Box::new(Block::new(BlockKind::RangeBlock { from, to })),
+        .enumerate()
+        .map(|(index, token)| {
+            let empty = |reason: String| OracleError::EmptyBlock {
+                index,
+                block: token.to_string(),
+                reason,
+            };
+
+            let block = match token {
+                Token::SingleChar(c) => Block::new(BlockKind::SingleChar(*c)),
+                Token::Range { from, to } if from >= to => {
+                    return Err(empty("the range is empty, `to` is excluded".to_string()));
+                }
+                Token::Range { from, to } => Block::new(BlockKind::Range {
+                    from: *from,
+                    to: *to,
+                }),
+                Token::Regex(ast) => {
+                    Block::regex(Dfa::from_ast(ast)?, max_length).map_err(empty)?
+                }
+                Token::Automaton(dfa) => Block::regex(dfa.clone(), max_length).map_err(empty)?,
+            };
+            Ok(block)
         })
         .collect()
 }
@@ -79,16 +436,286 @@ pub fn add(left: u64, right: u64) -> u64 {
 
 #[cfg(test)]
 mod tests {
+    use rand_chacha::ChaCha8Rng;
+    use tseger::generator::Choice;
+    use tseger::parser::Parser;
+
     use super::*;
 
+    fn abcd(len: usize) -> Oracle {
+        Oracle::new(
+            &(0..len)
+                .map(|_| Token::Range { from: 'a', to: 'e' })
+                .collect::<Vec<_>>(),
+        )
+        .unwrap()
+    }
+
     #[test]
     fn it_works() {
-        let oracle = Oracle::new(&vec![
+        let mut oracle = Oracle::new(&[
             Token::SingleChar('a'),
             Token::SingleChar('b'),
             Token::Range { from: 'a', to: 'z' },
-        ]);
+        ])
+        .unwrap();
+
+        let output = oracle.exec(&mut ChaCha8Rng::seed_from_u64(7)).unwrap();
+        assert!(output.starts_with("ab"));
+        assert_eq!(
+            output,
+            oracle.exec(&mut ChaCha8Rng::seed_from_u64(7)).unwrap()
+        );
+        assert_eq!(oracle.cardinality(), Cardinality::Finite(25));
+    }
+
+    #[test]
+    fn test_regular_constraints_are_pushed_down() {
+        let mut oracle = abcd(5)
+            .constrain(Constraint::Prefix("b".to_string()))
+            .unwrap()
+            .constrain(Constraint::Forbidden("dd".to_string()))
+            .unwrap()
+            .constrain(Constraint::regex("[a-c]+d?").unwrap())
+            .unwrap()
+            // Nothing is ever rejected, so a single attempt is enough
+            .retry_budget(1);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+
+        for _ in 0..200 {
+            let value = oracle.exec(&mut rng).unwrap();
+            assert_eq!(value.len(), 5);
+            assert!(value.starts_with('b'), "{}", value);
+            assert!(value[..4].chars().all(|c| ('a'..='c').contains(&c)));
+        }
+        // b, three of a-c and a-d at the end
+        assert_eq!(oracle.cardinality(), Cardinality::Finite(27 * 4));
+    }
+
+    #[test]
+    fn test_regex_blocks() {
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let mut oracle = Oracle::new(&[
+            Token::Regex(Parser::new().parse("[A-Z][a-z]+").unwrap()),
+            Token::SingleChar('-'),
+            Token::Automaton(Dfa::compile("[0-9]{2,3}").unwrap()),
+        ])
+        .unwrap();
+        let whole = Dfa::compile("[A-Z][a-z]+-[0-9]{2,3}").unwrap();
+
+        for _ in 0..50 {
+            assert!(whole.is_match(&oracle.exec(&mut rng).unwrap()));
+        }
+        assert_eq!(oracle.cardinality(), Cardinality::Infinite);
+
+        // Constraints see the automaton of the whole chain
+        let mut oracle = oracle
+            .constrain(Constraint::Length {
+                min: 5,
+                max: Some(5),
+            })
+            .unwrap()
+            .retry_budget(1);
+        for _ in 0..20 {
+            let value = oracle.exec(&mut rng).unwrap();
+            assert!(whole.is_match(&value) && value.len() == 5, "{}", value);
+        }
+        // The shortest values only, two letters, the dash and two digits
+        assert_eq!(oracle.cardinality(), Cardinality::Finite(26 * 26 * 100));
+    }
+
+    #[test]
+    fn test_empty_blocks_are_reported_when_built() {
+        let parse = |pattern| Token::Regex(Parser::new().parse(pattern).unwrap());
+        let nothing = Dfa::compile("a")
+            .unwrap()
+            .intersect(&Dfa::compile("b").unwrap())
+            .unwrap();
+
+        let cases = [
+            (
+                Token::Range { from: 'x', to: 'x' },
+                "Block 1 ('x'..'x') can't produce any value: the range is empty",
+            ),
+            (
+                parse("a{40,}"),
+                "Block 1 (regex \"a{40,}\") can't produce any value: none of its matches is at most 32",
+            ),
+            (
+                Token::Automaton(nothing),
+                "can't produce any value: it matches nothing",
+            ),
+        ];
+
+        for (token, expected) in cases {
+            let err = Oracle::new(&[Token::SingleChar('a'), token]).err().unwrap();
+            assert!(matches!(err, OracleError::EmptyBlock { index: 1, .. }));
+            assert!(err.to_string().contains(expected), "{}", err);
+        }
+
+        // A longer cap lets the unbounded regex through
+        assert!(Oracle::with_max_length(&[parse("a{40,}")], 50).is_ok());
+    }
+
+    #[test]
+    fn test_unsatisfiable_constraints_are_reported() {
+        let err = abcd(3)
+            .constrain(Constraint::Prefix("ab".to_string()))
+            .unwrap()
+            .constrain(Constraint::Suffix("zz".to_string()))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            err.contains("suffix \"zz\" can't be satisfied together with prefix \"ab\""),
+            "{}",
+            err
+        );
+
+        let err = abcd(3)
+            .constrain(Constraint::Length { min: 4, max: None })
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("with the base"), "{}", err);
+    }
+
+    #[test]
+    fn test_fill_batch_matches_exec() {
+        let tokens = vec![
+            Token::Regex(Parser::new().parse("[a-z]{2,6}").unwrap()),
+            Token::SingleChar('@'),
+            Token::Range { from: '0', to: ':' },
+        ];
+        let mut oracle = Oracle::new(&tokens)
+            .unwrap()
+            .constrain(Constraint::predicate("no x", |v| !v.contains('x')))
+            .unwrap();
+
+        let mut rng = ChaCha8Rng::seed_from_u64(21);
+        let expected: Vec<String> = (0..64).map(|_| oracle.exec(&mut rng).unwrap()).collect();
+
+        let mut rng = ChaCha8Rng::seed_from_u64(21);
+        let mut batch = vec!["left over".to_string(); 100];
+        oracle.fill_batch(&mut rng, &mut batch, 32).unwrap();
+        let capacities: Vec<usize> = batch.iter().map(|v| v.capacity()).collect();
+        let first = batch.clone();
+        oracle.fill_batch(&mut rng, &mut batch, 32).unwrap();
+
+        assert_eq!([first, batch.clone()].concat(), expected);
+        // The second batch fits in the buffers of the first one or grows them
+        assert!(
+            batch
+                .iter()
+                .zip(&capacities)
+                .all(|(v, &c)| v.capacity() >= c)
+        );
+
+        // Values appended by exec_into leave what's already in the buffer alone
+        let mut out = "id: ".to_string();
+        oracle.exec_into(&mut rng, &mut out).unwrap();
+        assert!(out.starts_with("id: ") && out.len() > 6);
+    }
+
+    #[test]
+    fn test_unique_values() {
+        let mut rng = ChaCha8Rng::seed_from_u64(9);
+
+        let err = abcd(2).unique(Uniqueness::Exact, 17).err().unwrap();
+        assert!(err.to_string().contains("at most 16 exist"), "{}", err);
+
+        for backing in [
+            Uniqueness::Exact,
+            Uniqueness::Spilled {
+                memory_limit: 8,
+                dir: None,
+            },
+        ] {
+            let mut oracle = abcd(2).unique(backing, 16).unwrap();
+            let mut values: Vec<String> = (0..16).map(|_| oracle.exec(&mut rng).unwrap()).collect();
+            values.sort();
+            values.dedup();
+            assert_eq!(values.len(), 16);
+
+            let err = oracle.exec(&mut rng).unwrap_err().to_string();
+            assert!(err.contains("uniqueness rejected 1000"), "{}", err);
+        }
+
+        // The stream ends once every value was handed out
+        let values: Vec<String> = abcd(1)
+            .unique(Uniqueness::Exact, 2)
+            .unwrap()
+            .iter(&mut rng)
+            .collect();
+        assert_eq!(values.len(), 4);
+
+        // Approximate may drop a few new values, but never repeats one
+        let mut oracle = abcd(4)
+            .unique(
+                Uniqueness::Approximate {
+                    false_positive_rate: 0.01,
+                },
+                100,
+            )
+            .unwrap();
+        let mut values: Vec<String> = (0..100).map(|_| oracle.exec(&mut rng).unwrap()).collect();
+        values.sort();
+        values.dedup();
+        assert_eq!(values.len(), 100);
+    }
+
+    #[test]
+    fn test_errors_leave_buffers_alone() {
+        let mut rng = ChaCha8Rng::seed_from_u64(12);
+
+        // Only 4 values exist, the batch holds them and none of the old strings
+        let mut oracle = abcd(1).unique(Uniqueness::Exact, 4).unwrap();
+        let mut batch = vec!["old".to_string(); 6];
+        assert!(oracle.fill_batch(&mut rng, &mut batch, 6).is_err());
+        assert_eq!(batch.len(), 4);
+        assert!(batch.iter().all(|v| v.len() == 1 && v != "old"));
+
+        // The spill directory is gone, the second value fails to spill
+        let dir = tempfile::tempdir().unwrap();
+        let mut oracle = abcd(4)
+            .unique(
+                Uniqueness::Spilled {
+                    memory_limit: 1,
+                    dir: Some(dir.path().to_path_buf()),
+                },
+                10,
+            )
+            .unwrap();
+        std::fs::remove_dir_all(dir.path()).unwrap();
+        let mut out = "id: ".to_string();
+        oracle.exec_into(&mut rng, &mut out).unwrap();
+        out.truncate(4);
+        assert!(oracle.exec_into(&mut rng, &mut out).is_err());
+        assert_eq!(out, "id: ");
+    }
+
+    #[test]
+    fn test_predicates_reject_with_a_budget() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+
+        let mut oracle = Oracle::from_generator(Box::new(Choice::new(["a", "bb", "ccc", "dd"])))
+            .constrain(Constraint::predicate("odd length", |v| v.len() % 2 == 1))
+            .unwrap()
+            .constrain(Constraint::Forbidden("a".to_string()))
+            .unwrap();
+        for _ in 0..20 {
+            assert_eq!(oracle.exec(&mut rng).unwrap(), "ccc");
+        }
 
-        println!("ORACLE SAID: {}", oracle.exec());
+        let mut never = abcd(2)
+            .constrain(Constraint::predicate("palindrome of 3", |v| v.len() == 3))
+            .unwrap()
+            .retry_budget(50);
+        let err = never.exec(&mut rng).unwrap_err().to_string();
+        assert!(
+            err.contains("in 50 attempts, predicate palindrome of 3 rejected 50"),
+            "{}",
+            err
+        );
+        assert!(never.next_string(&mut rng).is_none());
     }
 }
@@ -1 +1,9 @@
-mod engine;
+pub mod common;
+pub mod constraint;
+pub mod engine;
+pub mod markov;
+pub mod numeric;
+pub mod parallel;
+pub mod record;
+pub mod solver;
+pub mod unique;
@@ -0,0 +1,515 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use rand::{Rng, RngCore};
+use tseger::generator::{Cardinality, Generator};
+
+use crate::common::OracleError;
+
+/*
+* n-gram Markov models trained on ingested text, for values that read like
+* the corpus rather than like lorem ipsum:
+*
+* - a word model learns sentences, split after `.`, `!` and `?`, punctuation
+*   stays on its word; a char model learns words, split on whitespace, so one
+*   trained on a word list makes up words that sound like the list
+* - the next token is drawn by how often it followed the last `order` tokens
+*   in the corpus, sentences start and end at a boundary token, id 0
+* - Text strings sentences together, too short sentences are drawn again and
+*   long ones are cut at the maximum length
+* - to_bytes() is the same layout as Dfa::to_bytes(), little endian u32 words
+*   after a header, so trained models can be stored and reused
+*/
+
+const MAGIC: &[u8] = b"ORMKV";
+pub const FORMAT_VERSION: u32 = 1;
+const BOUNDARY: u32 = 0;
+const MAX_ORDER: usize = 16;
+const MAX_REDRAWS: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+    Char,
+    Word,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Markov {
+    level: Level,
+    order: usize,
+    // tokens[0] is the boundary
+    tokens: Vec<String>,
+    // Last `order` tokens -> next tokens sorted by id, with cumulative counts
+    transitions: HashMap<Vec<u32>, Vec<(u32, u32)>>,
+}
+
+/// Generator of sentences, or of words for a char model.
+pub struct Text {
+    model: Arc<Markov>,
+    length: (usize, usize),
+    sentences: (usize, usize),
+    context: Vec<u32>,
+}
+
+impl Markov {
+    /// Every entry of `corpus` is split into sentences, or words for Level::Char.
+    pub fn train<S: AsRef<str>>(
+        level: Level,
+        order: usize,
+        corpus: impl IntoIterator<Item = S>,
+    ) -> Result<Self, OracleError> {
+        if order == 0 || order > MAX_ORDER {
+            return Err(markov_error(format!(
+                "The order has to be between 1 and {}, not {}",
+                MAX_ORDER, order
+            )));
+        }
+
+        let mut ids: HashMap<String, u32> = HashMap::new();
+        let mut tokens = vec![String::new()];
+        let mut counts: HashMap<Vec<u32>, BTreeMap<u32, u32>> = HashMap::new();
+        let mut context = Vec::with_capacity(order);
+
+        for text in corpus {
+            for sentence in split(level, text.as_ref()) {
+                context.clear();
+                context.resize(order, BOUNDARY);
+
+                for token in sentence {
+                    let id = *ids.entry(token.to_string()).or_insert_with(|| {
+                        tokens.push(token.to_string());
+                        tokens.len() as u32 - 1
+                    });
+                    *counts
+                        .entry(context.clone())
+                        .or_default()
+                        .entry(id)
+                        .or_default() += 1;
+                    shift(&mut context, id);
+                }
+                *counts
+                    .entry(context.clone())
+                    .or_default()
+                    .entry(BOUNDARY)
+                    .or_default() += 1;
+            }
+        }
+
+        if counts.is_empty() {
+            return Err(markov_error(
+                "The corpus has no text to learn from".to_string(),
+            ));
+        }
+
+        let transitions = counts
+            .into_iter()
+            .map(|(context, next)| {
+                let mut total = 0;
+                let cumulative = next
+                    .into_iter()
+                    .map(|(id, count)| {
+                        total += count;
+                        (id, total)
+                    })
+                    .collect();
+                (context, cumulative)
+            })
+            .collect();
+
+        Ok(Self {
+            level,
+            order,
+            tokens,
+            transitions,
+        })
+    }
+
+    /// The whole file is one corpus entry.
+    pub fn train_file(level: Level, order: usize, path: &Path) -> Result<Self, OracleError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| markov_error(format!("Can't read {}: {}", path.display(), e)))?;
+        Self::train(level, order, [text])
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Sentences of 1 to 64 tokens, one per value.
+    pub fn text(self) -> Text {
+        Text {
+            model: Arc::new(self),
+            length: (1, 64),
+            sentences: (1, 1),
+            context: Vec::new(),
+        }
+    }
+
+    // Appends one sentence of at most `max` tokens, false if it ended before `min`
+    fn sentence(
+        &self,
+        rng: &mut dyn RngCore,
+        (min, max): (usize, usize),
+        context: &mut Vec<u32>,
+        out: &mut String,
+    ) -> bool {
+        context.clear();
+        context.resize(self.order, BOUNDARY);
+
+        for length in 0..max {
+            let Some(next) = self.transitions.get(context.as_slice()) else {
+                return false;
+            };
+
+            // Boundaries sort first, skipping their counts keeps short sentences going
+            let total = next[next.len() - 1].1;
+            let low = match next[0] {
+                (BOUNDARY, count) if length < min => count,
+                _ => 0,
+            };
+            if low == total {
+                return length >= min;
+            }
+            let drawn = rng.random_range(low..total);
+            let id = next[next.partition_point(|&(_, count)| count <= drawn)].0;
+            if id == BOUNDARY {
+                return true;
+            }
+
+            if length > 0 && self.level == Level::Word {
+                out.push(' ');
+            }
+            out.push_str(&self.tokens[id as usize]);
+            shift(context, id);
+        }
+
+        true
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        let mut push = |n: u32| out.extend_from_slice(&n.to_le_bytes());
+
+        push(FORMAT_VERSION);
+        push(match self.level {
+            Level::Char => 0,
+            Level::Word => 1,
+        });
+        push(self.order as u32);
+        push(self.tokens.len() as u32 - 1);
+        for token in &self.tokens[1..] {
+            push(token.chars().count() as u32);
+            token.chars().for_each(|c| push(c as u32));
+        }
+
+        // Sorted, equal models give equal bytes
+        let mut contexts: Vec<_> = self.transitions.iter().collect();
+        contexts.sort();
+        push(contexts.len() as u32);
+        for (context, next) in contexts {
+            context.iter().for_each(|&id| push(id));
+            push(next.len() as u32);
+            for &(id, count) in next {
+                push(id);
+                push(count);
+            }
+        }
+
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, OracleError> {
+        let invalid = |msg: &str| markov_error(format!("Invalid serialized model: {}", msg));
+
+        let body = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| invalid("missing header"))?;
+        if body.len() % 4 != 0 {
+            return Err(invalid("truncated"));
+        }
+        let mut words = body
+            .chunks(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()));
+        let mut next = || words.next().ok_or_else(|| invalid("truncated"));
+
+        let version = next()?;
+        if version != FORMAT_VERSION {
+            return Err(invalid(&format!("unsupported format version {}", version)));
+        }
+
+        let level = match next()? {
+            0 => Level::Char,
+            1 => Level::Word,
+            _ => return Err(invalid("bad level")),
+        };
+        let order = next()? as usize;
+        if order == 0 || order > MAX_ORDER {
+            return Err(invalid("bad order"));
+        }
+
+        let mut tokens = vec![String::new()];
+        for _ in 0..next()? {
+            let token = (0..next()?)
+                .map(|_| char::from_u32(next()?).ok_or_else(|| invalid("bad char")))
+                .collect::<Result<String, OracleError>>()?;
+            if token.is_empty() {
+                return Err(invalid("empty token"));
+            }
+            tokens.push(token);
+        }
+
+        let mut transitions = HashMap::new();
+        for _ in 0..next()? {
+            let context = (0..order)
+                .map(|_| next())
+                .collect::<Result<Vec<u32>, OracleError>>()?;
+
+            let mut following = Vec::new();
+            for _ in 0..next()? {
+                following.push((next()?, next()?));
+            }
+            // Sorted ids and rising counts, what sentence() relies on
+            let mut previous = (None, 0);
+            for &(id, count) in &following {
+                if Some(id) <= previous.0 || count <= previous.1 {
+                    return Err(invalid("transitions aren't canonical"));
+                }
+                previous = (Some(id), count);
+            }
+
+            let known = |id: &u32| (*id as usize) < tokens.len();
+            if following.is_empty()
+                || !context.iter().all(known)
+                || !following.iter().all(|(id, _)| known(id))
+            {
+                return Err(invalid("transition to a missing token"));
+            }
+            if transitions.insert(context, following).is_some() {
+                return Err(invalid("repeated context"));
+            }
+        }
+
+        if next().is_ok() {
+            return Err(invalid("trailing bytes"));
+        }
+        if !transitions.contains_key(&vec![BOUNDARY; order]) {
+            return Err(invalid("no sentence start"));
+        }
+
+        Ok(Self {
+            level,
+            order,
+            tokens,
+            transitions,
+        })
+    }
+}
+
+impl Text {
+    /// Tokens per sentence, words, or chars for a char model.
+    pub fn length(mut self, min: usize, max: usize) -> Result<Self, OracleError> {
+        self.length = bounds("length", min, max)?;
+        Ok(self)
+    }
+
+    /// Sentences per value, joined by spaces.
+    pub fn sentences(mut self, min: usize, max: usize) -> Result<Self, OracleError> {
+        self.sentences = bounds("sentence count", min, max)?;
+        Ok(self)
+    }
+}
+
+impl Generator for Text {
+    /// False if no sentence reached the minimum length in MAX_REDRAWS draws.
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        let start = out.len();
+        let count = rng.random_range(self.sentences.0..=self.sentences.1);
+
+        for i in 0..count {
+            if i > 0 {
+                out.push(' ');
+            }
+            let mark = out.len();
+            let mut drawn = false;
+            for _ in 0..MAX_REDRAWS {
+                if self
+                    .model
+                    .sentence(rng, self.length, &mut self.context, out)
+                {
+                    drawn = true;
+                    break;
+                }
+                out.truncate(mark);
+            }
+            if !drawn {
+                out.truncate(start);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Finite for any corpus, but too many to count and more than a uniqueness check needs
+    fn cardinality(&self) -> Cardinality {
+        Cardinality::Infinite
+    }
+}
+
+fn split(level: Level, text: &str) -> Vec<Vec<&str>> {
+    match level {
+        Level::Char => text
+            .split_whitespace()
+            .map(|word| {
+                word.char_indices()
+                    .map(|(i, c)| &word[i..i + c.len_utf8()])
+                    .collect()
+            })
+            .collect(),
+        Level::Word => {
+            let mut sentences = vec![Vec::new()];
+            for word in text.split_whitespace() {
+                let last = sentences.len() - 1;
+                sentences[last].push(word);
+                if word.ends_with(['.', '!', '?']) {
+                    sentences.push(Vec::new());
+                }
+            }
+            sentences.retain(|sentence| !sentence.is_empty());
+            sentences
+        }
+    }
+}
+
+fn shift(context: &mut [u32], id: u32) {
+    context.copy_within(1.., 0);
+    context[context.len() - 1] = id;
+}
+
+fn bounds(what: &str, min: usize, max: usize) -> Result<(usize, usize), OracleError> {
+    if min == 0 || min > max {
+        return Err(markov_error(format!(
+            "The {} needs 1 <= min <= max, got {}..={}",
+            what, min, max
+        )));
+    }
+    Ok((min, max))
+}
+
+fn markov_error(message: String) -> OracleError {
+    OracleError::MarkovError(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::io::Write;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    const WORDS: [&str; 12] = [
+        "Apfel", "Abend", "Bahnhof", "Brot", "Fenster", "Garten", "Haus", "Kirche", "Lampe",
+        "Sonne", "Straße", "Wasser",
+    ];
+
+    const REVIEWS: &str = "Das Produkt ist sehr gut. Die Lieferung war schnell und \
+        das Produkt ist gut verpackt. Ich bin sehr zufrieden! Die Qualität ist gut \
+        und der Preis ist fair. Würde ich wieder kaufen? Ja, sehr gerne.";
+
+    #[test]
+    fn test_char_model_makes_up_words_from_seen_ngrams() {
+        let mut text = Markov::train(Level::Char, 2, WORDS)
+            .unwrap()
+            .text()
+            .length(3, 9)
+            .unwrap();
+
+        let seen: HashSet<(char, char)> = WORDS
+            .iter()
+            .flat_map(|w| w.chars().zip(w.chars().skip(1)))
+            .collect();
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let mut new_words = 0;
+        for _ in 0..200 {
+            let word = text.next_string(&mut rng).unwrap();
+            let chars: Vec<char> = word.chars().collect();
+            assert!((3..=9).contains(&chars.len()), "{}", word);
+            assert!(
+                chars.windows(2).all(|p| seen.contains(&(p[0], p[1]))),
+                "{}",
+                word
+            );
+            new_words += usize::from(!WORDS.contains(&word.as_str()));
+        }
+        assert!(new_words > 20, "{}", new_words);
+    }
+
+    #[test]
+    fn test_word_model_writes_paragraphs() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(REVIEWS.as_bytes()).unwrap();
+        let model = Markov::train_file(Level::Word, 1, file.path()).unwrap();
+        let vocabulary: HashSet<&str> = REVIEWS.split_whitespace().collect();
+
+        let mut text = model.text().length(2, 12).unwrap().sentences(2, 3).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(8);
+        let mut out = String::new();
+        for _ in 0..100 {
+            out.clear();
+            assert!(text.next_into(&mut rng, &mut out));
+            let words: Vec<&str> = out.split(' ').collect();
+            assert!((4..=36).contains(&words.len()), "{}", out);
+            assert!(words.iter().all(|w| vocabulary.contains(w)), "{}", out);
+        }
+
+        // No sentence of this corpus is longer than two words
+        let words_only = Markov::train(Level::Word, 1, ["Gut. Sehr gut."]).unwrap();
+        let mut short = words_only.text().length(3, 5).unwrap();
+        assert!(short.next_string(&mut rng).is_none());
+    }
+
+    #[test]
+    fn test_models_round_trip_through_bytes() {
+        let model = Markov::train(Level::Word, 2, [REVIEWS]).unwrap();
+        let bytes = model.to_bytes();
+        let loaded = Markov::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded, model);
+        assert_eq!(loaded.to_bytes(), bytes);
+
+        let paragraph = |model: Markov| {
+            let mut text = model.text().sentences(1, 4).unwrap();
+            let mut rng = ChaCha8Rng::seed_from_u64(5);
+            (0..20)
+                .map(|_| text.next_string(&mut rng).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paragraph(loaded), paragraph(model));
+
+        let mut corrupt = bytes.clone();
+        corrupt.truncate(bytes.len() - 4);
+        assert!(Markov::from_bytes(&corrupt).is_err());
+        corrupt = bytes.clone();
+        corrupt[MAGIC.len() + 8] = 0;
+        assert!(Markov::from_bytes(&corrupt).is_err());
+        assert!(Markov::from_bytes(b"ORMKV").is_err());
+
+        assert!(Markov::train(Level::Word, 0, [REVIEWS]).is_err());
+        assert!(Markov::train(Level::Char, 2, ["  "]).is_err());
+        assert!(
+            Markov::train(Level::Char, 2, WORDS)
+                .unwrap()
+                .text()
+                .length(4, 2)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,989 @@
+use std::fmt;
+
+use rand::RngCore;
+use rand::distr::Uniform;
+use rand::distr::weighted::WeightedIndex;
+use rand_distr::Distribution as _;
+use rand_distr::{Exp, LogNormal, Normal, Pareto, Poisson, Zipf};
+use tseger::generator::{Cardinality, Generator};
+
+use crate::common::OracleError;
+
+/*
+* Typed numbers for quantities, prices and ages:
+*
+* - every distribution is sampled as f64, ints are rounded to the nearest one
+*   and floats to `precision` decimal places, bounds apply to the rounded value
+* - uniform ints are the exception, rounding would give both ends half the
+*   weight of the values between them, they are drawn as ints directly
+* - range() truncates the distribution, values outside it are drawn again, a
+*   range the distribution (almost) never reaches fails after MAX_REDRAWS
+* - clamp() moves values outside the bounds onto them instead, which piles up
+*   probability at the edges but never draws again
+* - parameters are checked when the generator is built, not when it runs
+* - at_quantile() turns a uniform number into a value of the distribution,
+*   which is how copulas correlate numeric fields; range() still truncates
+*   there, u is spread over [F(min), F(max)] before the quantile is taken
+* - Zipf's cdf and quantile read a table of cumulative weights for the first
+*   ZIPF_TABLE ranks and integrate 1 / k^exponent past them, a Numeric keeps
+*   its table so per row lookups don't depend on n
+*/
+
+const MAX_REDRAWS: usize = 1000;
+// Ranks with exact cumulative weights, the midpoint integral is within ~1e-8 past them
+const ZIPF_TABLE: u64 = 4096;
+
+#[derive(Clone, Debug)]
+pub enum Distribution {
+    /// Both ends included.
+    Uniform {
+        low: f64,
+        high: f64,
+    },
+    Normal {
+        mean: f64,
+        std_dev: f64,
+    },
+    /// Parameters of the underlying normal distribution.
+    LogNormal {
+        mu: f64,
+        sigma: f64,
+    },
+    Exponential {
+        lambda: f64,
+    },
+    Poisson {
+        lambda: f64,
+    },
+    /// Ranks 1..=n, rank k weighted 1 / k^exponent.
+    Zipf {
+        n: u64,
+        exponent: f64,
+    },
+    Pareto {
+        scale: f64,
+        shape: f64,
+    },
+    Categorical {
+        values: Vec<f64>,
+        weights: Vec<f64>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NumberKind {
+    Int,
+    Float,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+pub struct Numeric {
+    kind: NumberKind,
+    distribution: Distribution,
+    sampler: Sampler,
+    precision: Option<u32>,
+    bounds: Option<Bounds>,
+}
+
+struct Bounds {
+    min: f64,
+    max: f64,
+    clamp: bool,
+}
+
+// Validated rand_distr instances, one per Distribution
+enum Sampler {
+    Uniform(Uniform<f64>),
+    UniformInt(Uniform<i64>),
+    Normal(Normal<f64>),
+    LogNormal(LogNormal<f64>),
+    Exponential(Exp<f64>),
+    Poisson(Poisson<f64>),
+    Zipf(Zipf<f64>, ZipfTable),
+    Pareto(Pareto<f64>),
+    Categorical {
+        values: Vec<f64>,
+        index: WeightedIndex<f64>,
+    },
+}
+
+// Weights of ranks 1..=k summed, the generalized harmonic number H(k, exponent)
+struct ZipfTable {
+    n: u64,
+    exponent: f64,
+    cumulative: Vec<f64>,
+    total: f64,
+}
+
+impl Numeric {
+    pub fn new(kind: NumberKind, distribution: Distribution) -> Result<Self, OracleError> {
+        Ok(Self {
+            kind,
+            sampler: Sampler::new(kind, distribution.clone())?,
+            distribution,
+            precision: None,
+            bounds: None,
+        })
+    }
+
+    pub fn int(distribution: Distribution) -> Result<Self, OracleError> {
+        Self::new(NumberKind::Int, distribution)
+    }
+
+    pub fn float(distribution: Distribution) -> Result<Self, OracleError> {
+        Self::new(NumberKind::Float, distribution)
+    }
+
+    /// Decimal places floats are rounded to, ints ignore it.
+    pub fn precision(mut self, digits: u32) -> Self {
+        self.precision = Some(digits);
+        self
+    }
+
+    /// Values outside `min..=max` are drawn again.
+    pub fn range(self, min: f64, max: f64) -> Result<Self, OracleError> {
+        self.bounded(min, max, false)
+    }
+
+    /// Values outside `min..=max` are moved onto the nearest bound.
+    pub fn clamp(self, min: f64, max: f64) -> Result<Self, OracleError> {
+        self.bounded(min, max, true)
+    }
+
+    fn bounded(mut self, min: f64, max: f64, clamp: bool) -> Result<Self, OracleError> {
+        if min.is_nan() || max.is_nan() || min > max {
+            return Err(OracleError::DistributionError(format!(
+                "Empty bounds {}..={}",
+                min, max
+            )));
+        }
+        if self.kind == NumberKind::Int && min.ceil() > max.floor() {
+            return Err(OracleError::DistributionError(format!(
+                "No int between {} and {}",
+                min, max
+            )));
+        }
+
+        self.bounds = Some(Bounds { min, max, clamp });
+        Ok(self)
+    }
+
+    pub fn sample(&self, rng: &mut dyn RngCore) -> Result<Number, OracleError> {
+        for _ in 0..MAX_REDRAWS {
+            let mut value = self.round(self.sampler.sample(rng));
+
+            if let Some(Bounds { min, max, clamp }) = self.bounds {
+                if clamp {
+                    // Ints are clamped onto the ints inside the bounds
+                    value = match self.kind {
+                        NumberKind::Int => value.clamp(min.ceil(), max.floor()),
+                        NumberKind::Float => value.clamp(min, max),
+                    };
+                } else if !(min..=max).contains(&value) {
+                    continue;
+                }
+            }
+
+            return Ok(match self.kind {
+                NumberKind::Int => Number::Int(value as i64),
+                NumberKind::Float => Number::Float(value),
+            });
+        }
+
+        let bounds = self.bounds.as_ref().expect("only bounds redraw values");
+        Err(OracleError::RejectionError(format!(
+            "No value fell within {}..={} in {} draws",
+            bounds.min, bounds.max, MAX_REDRAWS
+        )))
+    }
+
+    /// The value below which a fraction `u` of the distribution lies, rounded.
+    /// range() bounds truncate the distribution here too, clamp() ones clamp.
+    pub fn at_quantile(&self, u: f64) -> Number {
+        // Spread u over the part of the distribution inside the bounds
+        let u = match self.bounds {
+            Some(Bounds {
+                min,
+                max,
+                clamp: false,
+            }) => {
+                // Ints round to the bounds from half a step outside them
+                let (below, upto) = match self.kind {
+                    NumberKind::Int => (min.ceil() - 0.5, max.floor() + 0.5),
+                    NumberKind::Float => (min.next_down(), max),
+                };
+                let (low, high) = (self.cdf(below), self.cdf(upto));
+                low + u * (high - low)
+            }
+            _ => u,
+        };
+
+        let mut value = match (&self.sampler, &self.distribution) {
+            // Each int gets an equal slice of 0..1, as in sample()
+            (Sampler::UniformInt(_), Distribution::Uniform { low, high }) => {
+                let (low, high) = (low.ceil(), high.floor());
+                (low + (u * (high - low + 1.0)).floor()).min(high)
+            }
+            (Sampler::Zipf(_, table), _) => table.quantile(u),
+            _ => self.round(self.distribution.quantile(u)),
+        };
+        // Rounding can still step over a bound
+        if let Some(Bounds { min, max, .. }) = self.bounds {
+            value = match self.kind {
+                NumberKind::Int => value.clamp(min.ceil(), max.floor()),
+                NumberKind::Float => value.clamp(min, max),
+            };
+        }
+
+        match self.kind {
+            NumberKind::Int => Number::Int(value as i64),
+            NumberKind::Float => Number::Float(value),
+        }
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        match (&self.sampler, &self.distribution) {
+            (Sampler::UniformInt(_), Distribution::Uniform { low, high }) => {
+                let (low, high) = (low.ceil(), high.floor());
+                ((x.floor() - low + 1.0) / (high - low + 1.0)).clamp(0.0, 1.0)
+            }
+            (Sampler::Zipf(_, table), _) => table.cdf(x),
+            (_, distribution) => distribution.cdf(x),
+        }
+    }
+
+    fn round(&self, value: f64) -> f64 {
+        match (self.kind, self.precision) {
+            (NumberKind::Int, _) => value.round(),
+            (NumberKind::Float, Some(digits)) => {
+                let scale = 10f64.powi(digits as i32);
+                (value * scale).round() / scale
+            }
+            (NumberKind::Float, None) => value,
+        }
+    }
+}
+
+impl Sampler {
+    fn new(kind: NumberKind, distribution: Distribution) -> Result<Self, OracleError> {
+        let invalid = |name: &str, e: &dyn fmt::Display| {
+            OracleError::DistributionError(format!("Invalid {} distribution: {}", name, e))
+        };
+
+        // rand_distr mirrors a negative standard deviation instead of refusing it
+        match distribution {
+            Distribution::Normal { std_dev: sd, .. }
+            | Distribution::LogNormal { sigma: sd, .. }
+                if sd < 0.0 =>
+            {
+                return Err(invalid("normal", &"negative standard deviation"));
+            }
+            _ => {}
+        }
+
+        Ok(match distribution {
+            Distribution::Uniform { low, high } if kind == NumberKind::Int => {
+                if low.is_nan() || high.is_nan() || low.ceil() > high.floor() {
+                    return Err(invalid(
+                        "uniform",
+                        &format!("no int between {} and {}", low, high),
+                    ));
+                }
+                Self::UniformInt(
+                    Uniform::new_inclusive(low.ceil() as i64, high.floor() as i64)
+                        .map_err(|e| invalid("uniform", &e))?,
+                )
+            }
+            Distribution::Uniform { low, high } => Self::Uniform(
+                Uniform::new_inclusive(low, high).map_err(|e| invalid("uniform", &e))?,
+            ),
+            Distribution::Normal { mean, std_dev } => {
+                Self::Normal(Normal::new(mean, std_dev).map_err(|e| invalid("normal", &e))?)
+            }
+            Distribution::LogNormal { mu, sigma } => {
+                Self::LogNormal(LogNormal::new(mu, sigma).map_err(|e| invalid("log-normal", &e))?)
+            }
+            Distribution::Exponential { lambda } => {
+                Self::Exponential(Exp::new(lambda).map_err(|e| invalid("exponential", &e))?)
+            }
+            Distribution::Poisson { lambda } => {
+                Self::Poisson(Poisson::new(lambda).map_err(|e| invalid("Poisson", &e))?)
+            }
+            Distribution::Zipf { n, exponent } => Self::Zipf(
+                Zipf::new(n as f64, exponent).map_err(|e| invalid("Zipf", &e))?,
+                ZipfTable::new(n, exponent),
+            ),
+            Distribution::Pareto { scale, shape } => {
+                Self::Pareto(Pareto::new(scale, shape).map_err(|e| invalid("Pareto", &e))?)
+            }
+            Distribution::Categorical { values, weights } => {
+                if values.len() != weights.len() {
+                    return Err(invalid(
+                        "categorical",
+                        &format!("{} values but {} weights", values.len(), weights.len()),
+                    ));
+                }
+                Self::Categorical {
+                    index: WeightedIndex::new(weights).map_err(|e| invalid("categorical", &e))?,
+                    values,
+                }
+            }
+        })
+    }
+
+    fn sample(&self, rng: &mut dyn RngCore) -> f64 {
+        match self {
+            Self::Uniform(d) => d.sample(rng),
+            Self::UniformInt(d) => d.sample(rng) as f64,
+            Self::Normal(d) => d.sample(rng),
+            Self::LogNormal(d) => d.sample(rng),
+            Self::Exponential(d) => d.sample(rng),
+            Self::Poisson(d) => d.sample(rng),
+            Self::Zipf(d, _) => d.sample(rng),
+            Self::Pareto(d) => d.sample(rng),
+            Self::Categorical { values, index } => values[index.sample(rng)],
+        }
+    }
+}
+
+impl Distribution {
+    /// Inverse of the cumulative distribution function, `u` in 0..1. Poisson
+    /// walks its values up from the smallest one.
+    pub fn quantile(&self, u: f64) -> f64 {
+        let u = u.clamp(f64::EPSILON, 1.0 - f64::EPSILON);
+
+        match self {
+            Self::Uniform { low, high } => low + u * (high - low),
+            Self::Normal { mean, std_dev } => mean + std_dev * normal_quantile(u),
+            Self::LogNormal { mu, sigma } => (mu + sigma * normal_quantile(u)).exp(),
+            Self::Exponential { lambda } => -(1.0 - u).ln() / lambda,
+            Self::Pareto { scale, shape } => scale / (1.0 - u).powf(1.0 / shape),
+            // exp(-lambda) underflows past ~700, the normal approximation is close there
+            Self::Poisson { lambda } if *lambda > 500.0 => (lambda
+                + lambda.sqrt() * normal_quantile(u))
+            .round()
+            .max(0.0),
+            Self::Poisson { lambda } => {
+                let (mut k, mut p) = (0.0, (-lambda).exp());
+                let mut cumulative = p;
+                while cumulative < u && p > 0.0 {
+                    k += 1.0;
+                    p *= lambda / k;
+                    cumulative += p;
+                }
+                k
+            }
+            Self::Zipf { n, exponent } => ZipfTable::new(*n, *exponent).quantile(u),
+            // Walked in value order, so larger u's give larger values
+            Self::Categorical { values, weights } => {
+                let mut target = u * weights.iter().sum::<f64>();
+                for (value, weight) in sorted(values, weights) {
+                    target -= weight;
+                    if target <= 0.0 {
+                        return value;
+                    }
+                }
+                values.iter().copied().fold(f64::MIN, f64::max)
+            }
+        }
+    }
+
+    /// Cumulative distribution function, the fraction of values <= x.
+    pub fn cdf(&self, x: f64) -> f64 {
+        let p = match self {
+            Self::Uniform { low, high } if high > low => (x - low) / (high - low),
+            Self::Uniform { low, .. } => f64::from(x >= *low),
+            Self::Normal { mean, std_dev } => normal_cdf((x - mean) / std_dev),
+            Self::LogNormal { .. } | Self::Exponential { .. } | Self::Poisson { .. } if x < 0.0 => {
+                0.0
+            }
+            Self::LogNormal { mu, sigma } => normal_cdf((x.ln() - mu) / sigma),
+            Self::Exponential { lambda } => 1.0 - (-lambda * x).exp(),
+            Self::Pareto { scale, .. } if x < *scale => 0.0,
+            Self::Pareto { scale, shape } => 1.0 - (scale / x).powf(*shape),
+            // Same approximation as quantile(), with a continuity correction
+            Self::Poisson { lambda } if *lambda > 500.0 => {
+                normal_cdf((x.floor() + 0.5 - lambda) / lambda.sqrt())
+            }
+            Self::Poisson { lambda } => {
+                let (mut p, mut cumulative) = ((-lambda).exp(), 0.0);
+                for k in 0..=(x.floor().min(u32::MAX as f64) as u32) {
+                    if k > 0 {
+                        p *= lambda / k as f64;
+                    }
+                    cumulative += p;
+                    if p == 0.0 && k as f64 > *lambda {
+                        break;
+                    }
+                }
+                cumulative
+            }
+            Self::Zipf { n, exponent } => ZipfTable::new(*n, *exponent).cdf(x),
+            Self::Categorical { values, weights } => {
+                let below: f64 = sorted(values, weights)
+                    .take_while(|(value, _)| *value <= x)
+                    .map(|(_, weight)| weight)
+                    .sum();
+                below / weights.iter().sum::<f64>()
+            }
+        };
+        p.clamp(0.0, 1.0)
+    }
+}
+
+impl ZipfTable {
+    fn new(n: u64, exponent: f64) -> Self {
+        let cumulative: Vec<f64> = (1..=n.min(ZIPF_TABLE))
+            .scan(0.0, |sum, k| {
+                *sum += (k as f64).powf(-exponent);
+                Some(*sum)
+            })
+            .collect();
+        let mut table = Self {
+            n,
+            exponent,
+            cumulative,
+            total: 0.0,
+        };
+        table.total = table.harmonic(n);
+        table
+    }
+
+    fn head(&self) -> (u64, f64) {
+        (
+            self.cumulative.len() as u64,
+            self.cumulative.last().copied().unwrap_or(0.0),
+        )
+    }
+
+    fn harmonic(&self, k: u64) -> f64 {
+        let (head, sum) = self.head();
+        match k {
+            0 => 0.0,
+            k if k <= head => self.cumulative[k as usize - 1],
+            k => sum + self.integral(head as f64 + 0.5, k as f64 + 0.5),
+        }
+    }
+
+    // Of x^-exponent from a to b
+    fn integral(&self, a: f64, b: f64) -> f64 {
+        let rise = 1.0 - self.exponent;
+        match rise.abs() < 1e-12 {
+            true => (b / a).ln(),
+            false => (b.powf(rise) - a.powf(rise)) / rise,
+        }
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        self.harmonic(x.floor().clamp(0.0, self.n as f64) as u64) / self.total
+    }
+
+    // Smallest rank whose cumulative weight reaches u of the total
+    fn quantile(&self, u: f64) -> f64 {
+        let target = u.clamp(0.0, 1.0) * self.total;
+        let (head, sum) = self.head();
+        if target <= sum {
+            let k = self.cumulative.partition_point(|&c| c < target) as u64 + 1;
+            return k.min(self.n) as f64;
+        }
+
+        // Inverts the integral past the table
+        let (a, rest, rise) = (head as f64 + 0.5, target - sum, 1.0 - self.exponent);
+        let b = match rise.abs() < 1e-12 {
+            true => a * rest.exp(),
+            false => (a.powf(rise) + rest * rise).powf(1.0 / rise),
+        };
+        match b.is_finite() {
+            true => (b - 0.5).ceil().clamp(head as f64 + 1.0, self.n as f64),
+            false => self.n as f64,
+        }
+    }
+}
+
+fn sorted(values: &[f64], weights: &[f64]) -> impl Iterator<Item = (f64, f64)> {
+    let mut pairs: Vec<(f64, f64)> = values
+        .iter()
+        .copied()
+        .zip(weights.iter().copied())
+        .collect();
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+    pairs.into_iter()
+}
+
+/// Standard normal cumulative distribution function.
+pub fn normal_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / std::f64::consts::SQRT_2)
+}
+
+// Complementary error function, Chebyshev fit from Numerical Recipes, error below 1.2e-7
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = [
+        -1.26551223,
+        1.00002368,
+        0.37409196,
+        0.09678418,
+        -0.18628806,
+        0.27886807,
+        -1.13520398,
+        1.48851587,
+        -0.82215223,
+        0.17087277,
+    ]
+    .iter()
+    .rev()
+    .fold(0.0, |acc, c| acc * t + c);
+    let r = t * (-z * z + poly).exp();
+
+    if x >= 0.0 { r } else { 2.0 - r }
+}
+
+/// Standard normal quantile, Acklam's rational approximation, relative error
+/// below 1.2e-9.
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e+01,
+        2.209460984245205e+02,
+        -2.759285104469687e+02,
+        1.38357751867269e+02,
+        -3.066479806614716e+01,
+        2.506628277459239e+00,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e+01,
+        1.615858368580409e+02,
+        -1.556989798598866e+02,
+        6.680131188771972e+01,
+        -1.328068155288572e+01,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-03,
+        -3.223964580411365e-01,
+        -2.400758277161838e+00,
+        -2.549732539343734e+00,
+        4.374664141464968e+00,
+        2.938163982698783e+00,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-03,
+        3.224671290700398e-01,
+        2.445134137142996e+00,
+        3.754408661907416e+00,
+    ];
+    const LOW: f64 = 0.02425;
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+
+    if p < LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - LOW {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(value) => write!(f, "{}", value),
+            Self::Float(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Numbers as text, so the Oracle and the string adapters take them too.
+impl Generator for Numeric {
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        match self.sample(rng) {
+            Ok(number) => {
+                out.push_str(&number.to_string());
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn cardinality(&self) -> Cardinality {
+        match (&self.sampler, self.kind, &self.bounds) {
+            (Sampler::Categorical { values, .. }, _, _) => {
+                Cardinality::Finite(values.len() as u128)
+            }
+            (_, NumberKind::Int, Some(Bounds { min, max, .. })) => {
+                // Spans past u128 saturate the cast, infinite bounds aren't finite at all
+                let span = max.floor() - min.ceil();
+                match span.is_finite() && span < u128::MAX as f64 {
+                    true => (span as u128)
+                        .checked_add(1)
+                        .map_or(Cardinality::Infinite, Cardinality::Finite),
+                    false => Cardinality::Infinite,
+                }
+            }
+            _ => Cardinality::Infinite,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    fn draw(numeric: &Numeric, n: usize) -> Vec<f64> {
+        let mut rng = ChaCha8Rng::seed_from_u64(17);
+        (0..n)
+            .map(|_| match numeric.sample(&mut rng).unwrap() {
+                Number::Int(value) => value as f64,
+                Number::Float(value) => value,
+            })
+            .collect()
+    }
+
+    fn mean(values: &[f64]) -> f64 {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+
+    #[test]
+    fn test_distributions_have_their_means() {
+        let cases = [
+            (
+                Distribution::Normal {
+                    mean: 40.0,
+                    std_dev: 12.0,
+                },
+                40.0,
+            ),
+            (
+                Distribution::LogNormal {
+                    mu: 0.0,
+                    sigma: 0.5,
+                },
+                (0.125f64).exp(),
+            ),
+            (Distribution::Exponential { lambda: 0.5 }, 2.0),
+            (Distribution::Poisson { lambda: 3.0 }, 3.0),
+            (
+                Distribution::Pareto {
+                    scale: 1.0,
+                    shape: 3.0,
+                },
+                1.5,
+            ),
+            (
+                Distribution::Uniform {
+                    low: 10.0,
+                    high: 20.0,
+                },
+                15.0,
+            ),
+        ];
+
+        for (distribution, expected) in cases {
+            let values = draw(&Numeric::float(distribution).unwrap(), 20_000);
+            let mean = mean(&values);
+            assert!(
+                (mean - expected).abs() < expected * 0.05,
+                "{} vs {}",
+                mean,
+                expected
+            );
+        }
+
+        let ranks = draw(
+            &Numeric::int(Distribution::Zipf {
+                n: 10,
+                exponent: 1.5,
+            })
+            .unwrap(),
+            5000,
+        );
+        let ones = ranks.iter().filter(|&&r| r == 1.0).count();
+        assert!(ranks.iter().all(|r| (1.0..=10.0).contains(r)));
+        assert!(ones > 2500, "{}", ones);
+    }
+
+    #[test]
+    fn test_rounding_range_and_clamp() {
+        let ages = Numeric::int(Distribution::Normal {
+            mean: 35.0,
+            std_dev: 20.0,
+        })
+        .unwrap()
+        .range(18.0, 99.0)
+        .unwrap();
+        let values = draw(&ages, 2000);
+        assert!(
+            values
+                .iter()
+                .all(|v| v.fract() == 0.0 && (18.0..=99.0).contains(v))
+        );
+        assert_eq!(ages.cardinality(), Cardinality::Finite(82));
+
+        let prices = Numeric::float(Distribution::LogNormal {
+            mu: 3.0,
+            sigma: 1.0,
+        })
+        .unwrap()
+        .precision(2)
+        .clamp(0.99, 100.0)
+        .unwrap();
+        let values = draw(&prices, 2000);
+        assert!(values.iter().all(|v| (0.99..=100.0).contains(v)));
+        assert!(
+            values
+                .iter()
+                .all(|v| ((v * 100.0).round() - v * 100.0).abs() < 1e-6)
+        );
+        assert!(values.contains(&100.0));
+
+        let mut unreachable = Numeric::int(Distribution::Uniform {
+            low: 0.0,
+            high: 1.0,
+        })
+        .unwrap()
+        .range(5.0, 6.0)
+        .unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        assert!(unreachable.sample(&mut rng).is_err());
+        assert!(unreachable.next_string(&mut rng).is_none());
+    }
+
+    #[test]
+    fn test_uniform_ints_are_evenly_spread() {
+        let dice = Numeric::int(Distribution::Uniform {
+            low: 1.0,
+            high: 6.0,
+        })
+        .unwrap();
+
+        let mut faces = [0; 6];
+        for value in draw(&dice, 60_000) {
+            faces[value as usize - 1] += 1;
+        }
+        assert!(
+            faces.iter().all(|n| (9_500..10_500).contains(n)),
+            "{:?}",
+            faces
+        );
+
+        let mut faces = [0; 6];
+        for i in 0..6000 {
+            match dice.at_quantile((i as f64 + 0.5) / 6000.0) {
+                Number::Int(face) => faces[face as usize - 1] += 1,
+                number => panic!("{}", number),
+            }
+        }
+        assert_eq!(faces, [1000; 6]);
+
+        // Wider than any count, but no overflow
+        let any = Numeric::int(Distribution::Normal {
+            mean: 0.0,
+            std_dev: 1.0,
+        })
+        .unwrap()
+        .range(f64::NEG_INFINITY, f64::INFINITY)
+        .unwrap();
+        assert_eq!(any.cardinality(), Cardinality::Infinite);
+        assert!(
+            Numeric::int(Distribution::Uniform {
+                low: 1.2,
+                high: 1.8
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_quantiles_invert_the_distributions() {
+        for x in [-3.0, -1.0, 0.0, 0.5, 2.5] {
+            assert!((normal_quantile(normal_cdf(x)) - x).abs() < 1e-5, "{}", x);
+        }
+        assert!((normal_cdf(1.959964) - 0.975).abs() < 1e-6);
+
+        // Quantiles of evenly spread u's have the distribution's mean
+        let cases = [
+            (
+                Distribution::Normal {
+                    mean: 5.0,
+                    std_dev: 2.0,
+                },
+                5.0,
+            ),
+            (Distribution::Exponential { lambda: 0.25 }, 4.0),
+            (Distribution::Poisson { lambda: 6.0 }, 6.0),
+            (
+                Distribution::Pareto {
+                    scale: 2.0,
+                    shape: 4.0,
+                },
+                8.0 / 3.0,
+            ),
+            (
+                Distribution::Categorical {
+                    values: vec![1.0, 10.0],
+                    weights: vec![3.0, 1.0],
+                },
+                3.25,
+            ),
+        ];
+        for (distribution, expected) in cases {
+            let n = 20_000;
+            let mean = (0..n)
+                .map(|i| distribution.quantile((i as f64 + 0.5) / n as f64))
+                .sum::<f64>()
+                / n as f64;
+            assert!(
+                (mean - expected).abs() < expected * 0.02,
+                "{:?}: {}",
+                distribution,
+                mean
+            );
+        }
+
+        let ages = Numeric::int(Distribution::Normal {
+            mean: 40.0,
+            std_dev: 15.0,
+        })
+        .unwrap()
+        .range(18.0, 90.0)
+        .unwrap();
+        // Truncating below 18 moves the median up a year
+        assert_eq!(ages.at_quantile(0.5), Number::Int(41));
+        assert_eq!(ages.at_quantile(0.0001), Number::Int(18));
+
+        for (distribution, x) in [
+            (
+                Distribution::LogNormal {
+                    mu: 1.0,
+                    sigma: 0.5,
+                },
+                3.0,
+            ),
+            (Distribution::Exponential { lambda: 2.0 }, 0.4),
+            (
+                Distribution::Pareto {
+                    scale: 1.0,
+                    shape: 2.0,
+                },
+                2.5,
+            ),
+        ] {
+            let u = distribution.cdf(x);
+            assert!(
+                (distribution.quantile(u) - x).abs() < 1e-6,
+                "{:?}",
+                distribution
+            );
+        }
+        assert!(
+            (Distribution::Poisson { lambda: 2.0 }.cdf(1.0) - 3.0 * (-2f64).exp()).abs() < 1e-12
+        );
+
+        // range() truncates instead of piling values onto the bounds
+        let truncated = Numeric::float(Distribution::Normal {
+            mean: 0.0,
+            std_dev: 1.0,
+        })
+        .unwrap()
+        .range(-1.0, 1.0)
+        .unwrap();
+        let values: Vec<f64> = (0..10_000)
+            .map(
+                |i| match truncated.at_quantile((i as f64 + 0.5) / 10_000.0) {
+                    Number::Float(value) => value,
+                    number => panic!("{}", number),
+                },
+            )
+            .collect();
+        let on_bounds = values.iter().filter(|v| v.abs() > 0.999).count();
+        assert!(on_bounds < 20, "{}", on_bounds);
+        assert!(mean(&values).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_zipf_tables_match_the_sums() {
+        for (n, exponent) in [(10, 1.5), (100_000, 1.0), (100_000, 0.7), (100_000, 2.0)] {
+            let table = ZipfTable::new(n, exponent);
+            let weights: Vec<f64> = (1..=n).map(|k| (k as f64).powf(-exponent)).collect();
+            let total: f64 = weights.iter().sum();
+
+            let mut below = 0.0;
+            for k in 1..=n {
+                let upto = below + weights[k as usize - 1];
+                if k % 997 == 1 || k == n {
+                    assert!((table.cdf(k as f64) - upto / total).abs() < 1e-8, "{}", k);
+                    let u = (below + upto) / 2.0 / total;
+                    assert_eq!(table.quantile(u), k as f64, "{} {}", n, exponent);
+                }
+                below = upto;
+            }
+        }
+
+        // Huge n costs the same as small ones
+        let ranks = Numeric::int(Distribution::Zipf {
+            n: 1_000_000_000_000,
+            exponent: 1.1,
+        })
+        .unwrap()
+        .range(1.0, 1e9)
+        .unwrap();
+        let values: Vec<i64> = (0..10_000)
+            .map(|i| match ranks.at_quantile((i as f64 + 0.5) / 10_000.0) {
+                Number::Int(rank) => rank,
+                number => panic!("{}", number),
+            })
+            .collect();
+        assert!(values.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(values[0], 1);
+        assert!(values[9_999] > 1000 && values[9_999] <= 1_000_000_000);
+    }
+
+    #[test]
+    fn test_invalid_parameters_fail_early() {
+        let errors = [
+            Numeric::float(Distribution::Normal {
+                mean: 0.0,
+                std_dev: -1.0,
+            })
+            .err(),
+            Numeric::float(Distribution::Uniform {
+                low: 2.0,
+                high: 1.0,
+            })
+            .err(),
+            Numeric::float(Distribution::Categorical {
+                values: vec![1.0, 2.0],
+                weights: vec![1.0],
+            })
+            .err(),
+            Numeric::int(Distribution::Poisson { lambda: 1.0 })
+                .unwrap()
+                .range(0.2, 0.8)
+                .err(),
+        ];
+        assert!(errors.iter().all(|e| e.is_some()));
+
+        let dice = Numeric::int(Distribution::Categorical {
+            values: vec![1.0, 6.0],
+            weights: vec![1.0, 3.0],
+        })
+        .unwrap();
+        let sixes = draw(&dice, 4000).iter().filter(|&&v| v == 6.0).count();
+        assert!((2800..3200).contains(&sixes), "{}", sixes);
+        assert_eq!(dice.cardinality(), Cardinality::Finite(2));
+    }
+}
@@ -0,0 +1,150 @@
+use std::ops::Range;
+
+use rayon::prelude::*;
+use tseger::random;
+
+use crate::common::OracleError;
+use crate::engine::Oracle;
+
+/*
+* Rows generated on every core, in the same order and with the same values as
+* a single-threaded run:
+*
+* - row i of generator `name` draws from random::stream(seed, name, i) and
+*   nothing else, so a row doesn't depend on which thread made it or on the
+*   rows before it
+* - rows are cut into shards of `shard_size`, each rayon job builds its own
+*   Oracle with the caller's `build` (Oracles hold predicates and generators
+*   that aren't Send) and fills whole shards, collect() puts them back in order
+* - uniqueness remembers earlier rows, which breaks the first point, so
+*   Oracles with it are refused; generator bases should be stateless for the
+*   same reason, a stateful one only ever sees its own shard
+*/
+
+const DEFAULT_SHARD_SIZE: usize = 4096;
+
+pub struct Executor {
+    seed: u64,
+    name: String,
+    shard_size: usize,
+}
+
+impl Executor {
+    pub fn new(seed: u64, name: &str) -> Self {
+        Self {
+            seed,
+            name: name.to_string(),
+            shard_size: DEFAULT_SHARD_SIZE,
+        }
+    }
+
+    /// Rows per parallel job, doesn't change the output.
+    pub fn shard_size(mut self, rows: usize) -> Self {
+        self.shard_size = rows.max(1);
+        self
+    }
+
+    /// Appends row `index` to `out`.
+    pub fn row(
+        &self,
+        oracle: &mut Oracle,
+        index: u64,
+        out: &mut String,
+    ) -> Result<(), OracleError> {
+        oracle.exec_into(&mut random::stream(self.seed, &self.name, index), out)
+    }
+
+    /// The rows in `rows`, one after the other on this thread.
+    pub fn run(&self, oracle: &mut Oracle, rows: Range<u64>) -> Result<Vec<String>, OracleError> {
+        check(oracle)?;
+
+        rows.map(|index| {
+            let mut value = String::new();
+            self.row(oracle, index, &mut value)?;
+            Ok(value)
+        })
+        .collect()
+    }
+
+    /// Same rows as run() on rayon's thread pool, `build` makes an Oracle per job.
+    pub fn run_parallel<F>(&self, build: F, rows: Range<u64>) -> Result<Vec<String>, OracleError>
+    where
+        F: Fn() -> Result<Oracle, OracleError> + Sync + Send,
+    {
+        // Surfaces build errors and refused Oracles before any thread starts
+        check(&build()?)?;
+
+        let starts: Vec<u64> = rows.clone().step_by(self.shard_size).collect();
+        let shards = starts
+            .par_iter()
+            .map_init(&build, |oracle, &start| {
+                let oracle = oracle.as_mut().map_err(|e| {
+                    OracleError::ConstraintError(format!(
+                        "Building a worker's Oracle failed: {}",
+                        e
+                    ))
+                })?;
+                let end = rows.end.min(start + self.shard_size as u64);
+                self.run(oracle, start..end)
+            })
+            .collect::<Result<Vec<Vec<String>>, OracleError>>()?;
+
+        Ok(shards.concat())
+    }
+}
+
+fn check(oracle: &Oracle) -> Result<(), OracleError> {
+    if oracle.is_unique() {
+        return Err(OracleError::ConstraintError(
+            "Unique values depend on every row before them, they can't be split into streams"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constraint::Constraint;
+    use crate::engine::Token;
+    use crate::unique::Uniqueness;
+    use tseger::parser::Parser;
+
+    fn build() -> Result<Oracle, OracleError> {
+        Oracle::new(&[
+            Token::Regex(Parser::new().parse("[a-z]{3,8}")?),
+            Token::SingleChar('-'),
+            Token::Range { from: '0', to: ':' },
+        ])?
+        .constrain(Constraint::predicate("no q", |v| !v.contains('q')))
+    }
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let executor = Executor::new(42, "usernames").shard_size(7);
+        let sequential = executor.run(&mut build().unwrap(), 0..500).unwrap();
+        let parallel = executor.run_parallel(build, 0..500).unwrap();
+        assert_eq!(parallel, sequential);
+
+        // Any slice of rows is the same slice of the whole run
+        let middle = executor.run_parallel(build, 123..321).unwrap();
+        assert_eq!(middle, sequential[123..321]);
+
+        // Shard size only changes how the work is split
+        let coarse = Executor::new(42, "usernames").shard_size(1000);
+        assert_eq!(coarse.run_parallel(build, 0..500).unwrap(), sequential);
+
+        let renamed = Executor::new(42, "nicknames");
+        assert_ne!(renamed.run_parallel(build, 0..500).unwrap(), sequential);
+    }
+
+    #[test]
+    fn test_uniqueness_is_refused() {
+        let unique = || build()?.unique(Uniqueness::Exact, 100);
+        let err = Executor::new(1, "ids")
+            .run_parallel(unique, 0..100)
+            .unwrap_err();
+        assert!(err.to_string().contains("can't be split"), "{}", err);
+    }
+}
@@ -0,0 +1,579 @@
+use std::collections::HashMap;
+
+use rand::RngCore;
+use rand::distr::Distribution as _;
+use rand::distr::weighted::WeightedIndex;
+use rand_distr::StandardNormal;
+use tseger::generator::Generator;
+
+use crate::common::OracleError;
+use crate::numeric::{Number, Numeric, normal_cdf};
+use crate::solver::Solver;
+
+/*
+* Records are rows of named fields that may depend on each other:
+*
+* - fields are filled in the order they were added, a field can only depend
+*   on fields before it
+* - a joint table fills several fields from one of its rows, so city, zip code
+*   and country always come from the same row
+* - a conditional field picks its generator by the value of an earlier field
+* - a copula draws correlated standard normals (Cholesky factor of the
+*   correlation matrix), turns them into uniforms with the normal CDF and then
+*   into values with each field's own quantile function, so every field keeps
+*   its distribution and the fields move together
+* - a solver fills its variables at once, satisfying the rules between them
+*/
+
+pub struct Record {
+    columns: Vec<String>,
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Field {
+        column: usize,
+        generator: Box<dyn Generator>,
+    },
+    Joint {
+        columns: Vec<usize>,
+        table: JointTable,
+    },
+    Conditional {
+        column: usize,
+        on: usize,
+        conditional: Conditional,
+    },
+    Copula {
+        columns: Vec<usize>,
+        copula: Copula,
+    },
+    // The solver's columns are added together, they start at `first`
+    Solved {
+        first: usize,
+        solver: Solver,
+    },
+}
+
+pub struct JointTable {
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+    index: Option<WeightedIndex<f64>>,
+}
+
+pub struct Conditional {
+    on: String,
+    cases: HashMap<String, Box<dyn Generator>>,
+    otherwise: Option<Box<dyn Generator>>,
+}
+
+pub struct Copula {
+    columns: Vec<String>,
+    marginals: Vec<Numeric>,
+    // Lower triangular, cholesky * cholesky^T is the correlation matrix
+    cholesky: Vec<Vec<f64>>,
+}
+
+impl Default for Record {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Record {
+    pub fn new() -> Self {
+        Self {
+            columns: Vec::new(),
+            parts: Vec::new(),
+        }
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn field(
+        mut self,
+        name: &str,
+        generator: impl Generator + 'static,
+    ) -> Result<Self, OracleError> {
+        let column = self.add_column(name)?;
+        self.parts.push(Part::Field {
+            column,
+            generator: Box::new(generator),
+        });
+        Ok(self)
+    }
+
+    pub fn joint(mut self, table: JointTable) -> Result<Self, OracleError> {
+        let columns = table
+            .columns
+            .iter()
+            .map(|name| self.add_column(name))
+            .collect::<Result<Vec<usize>, OracleError>>()?;
+        self.parts.push(Part::Joint { columns, table });
+        Ok(self)
+    }
+
+    pub fn conditional(
+        mut self,
+        name: &str,
+        conditional: Conditional,
+    ) -> Result<Self, OracleError> {
+        let on = self.find_column(&conditional.on, name)?;
+        let column = self.add_column(name)?;
+        self.parts.push(Part::Conditional {
+            column,
+            on,
+            conditional,
+        });
+        Ok(self)
+    }
+
+    pub fn copula(mut self, copula: Copula) -> Result<Self, OracleError> {
+        let columns = copula
+            .columns
+            .iter()
+            .map(|name| self.add_column(name))
+            .collect::<Result<Vec<usize>, OracleError>>()?;
+        self.parts.push(Part::Copula { columns, copula });
+        Ok(self)
+    }
+
+    pub fn solved(mut self, solver: Solver) -> Result<Self, OracleError> {
+        let first = self.columns.len();
+        for name in solver.columns() {
+            self.add_column(name)?;
+        }
+        self.parts.push(Part::Solved { first, solver });
+        Ok(self)
+    }
+
+    fn add_column(&mut self, name: &str) -> Result<usize, OracleError> {
+        if self.columns.iter().any(|column| column == name) {
+            return Err(OracleError::RecordError(format!(
+                "Field {} is defined twice",
+                name
+            )));
+        }
+        self.columns.push(name.to_string());
+        Ok(self.columns.len() - 1)
+    }
+
+    fn find_column(&self, name: &str, dependent: &str) -> Result<usize, OracleError> {
+        self.columns
+            .iter()
+            .position(|column| column == name)
+            .ok_or_else(|| {
+                OracleError::RecordError(format!(
+                    "{} depends on {}, which has to be defined before it",
+                    dependent, name
+                ))
+            })
+    }
+
+    pub fn sample(&mut self, rng: &mut dyn RngCore) -> Result<Vec<String>, OracleError> {
+        let mut row = Vec::new();
+        self.sample_into(rng, &mut row)?;
+        Ok(row)
+    }
+
+    /// One value per column in `row`, in the order of columns(), reusing its strings.
+    pub fn sample_into(
+        &mut self,
+        rng: &mut dyn RngCore,
+        row: &mut Vec<String>,
+    ) -> Result<(), OracleError> {
+        row.resize_with(self.columns.len(), String::new);
+        row.iter_mut().for_each(String::clear);
+
+        for part in self.parts.iter_mut() {
+            match part {
+                Part::Field { column, generator } => {
+                    if !generator.next_into(rng, &mut row[*column]) {
+                        return Err(exhausted(&self.columns[*column]));
+                    }
+                }
+                Part::Joint { columns, table } => {
+                    for (&column, value) in columns.iter().zip(table.sample(rng)) {
+                        row[column].push_str(value);
+                    }
+                }
+                Part::Conditional {
+                    column,
+                    on,
+                    conditional,
+                } => {
+                    let (head, tail) = row.split_at_mut(*column);
+                    let Some(generator) = conditional.generator(&head[*on]) else {
+                        return Err(OracleError::RecordError(format!(
+                            "{} has no case for {} = {:?}",
+                            self.columns[*column], conditional.on, head[*on]
+                        )));
+                    };
+                    if !generator.next_into(rng, &mut tail[0]) {
+                        return Err(exhausted(&self.columns[*column]));
+                    }
+                }
+                Part::Copula { columns, copula } => {
+                    for (&column, number) in columns.iter().zip(copula.sample(rng)) {
+                        row[column].push_str(&number.to_string());
+                    }
+                }
+                Part::Solved { first, solver } => {
+                    let end = *first + solver.columns().len();
+                    solver.sample_into(rng, &mut row[*first..end])?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl JointTable {
+    /// Rows are drawn uniformly unless weights() says otherwise.
+    pub fn new(columns: &[&str], rows: Vec<Vec<String>>) -> Result<Self, OracleError> {
+        if rows.is_empty() {
+            return Err(OracleError::RecordError(format!(
+                "Joint table of {} has no rows",
+                columns.join(", ")
+            )));
+        }
+        if let Some(row) = rows.iter().find(|row| row.len() != columns.len()) {
+            return Err(OracleError::RecordError(format!(
+                "Joint table row {:?} doesn't have {} values",
+                row,
+                columns.len()
+            )));
+        }
+
+        Ok(Self {
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            rows,
+            index: None,
+        })
+    }
+
+    pub fn weights(mut self, weights: Vec<f64>) -> Result<Self, OracleError> {
+        if weights.len() != self.rows.len() {
+            return Err(OracleError::RecordError(format!(
+                "{} weights for {} joint table rows",
+                weights.len(),
+                self.rows.len()
+            )));
+        }
+        self.index = Some(WeightedIndex::new(weights).map_err(|e| {
+            OracleError::RecordError(format!("Invalid joint table weights: {}", e))
+        })?);
+        Ok(self)
+    }
+
+    fn sample(&self, rng: &mut dyn RngCore) -> &[String] {
+        let row = match &self.index {
+            Some(index) => index.sample(rng),
+            None => rand::Rng::random_range(rng, 0..self.rows.len()),
+        };
+        &self.rows[row]
+    }
+}
+
+impl Conditional {
+    /// Depends on the earlier field `on`.
+    pub fn on(field: &str) -> Self {
+        Self {
+            on: field.to_string(),
+            cases: HashMap::new(),
+            otherwise: None,
+        }
+    }
+
+    pub fn case(mut self, value: &str, generator: impl Generator + 'static) -> Self {
+        self.cases.insert(value.to_string(), Box::new(generator));
+        self
+    }
+
+    /// For values without a case of their own, they are an error otherwise.
+    pub fn otherwise(mut self, generator: impl Generator + 'static) -> Self {
+        self.otherwise = Some(Box::new(generator));
+        self
+    }
+
+    fn generator(&mut self, value: &str) -> Option<&mut Box<dyn Generator>> {
+        match self.cases.get_mut(value) {
+            Some(generator) => Some(generator),
+            None => self.otherwise.as_mut(),
+        }
+    }
+}
+
+impl Copula {
+    /// Gaussian copula over `fields`, `correlation` is their correlation matrix.
+    pub fn new(
+        fields: Vec<(&str, Numeric)>,
+        correlation: Vec<Vec<f64>>,
+    ) -> Result<Self, OracleError> {
+        let n = fields.len();
+        let symmetric = correlation.len() == n
+            && correlation.iter().all(|row| row.len() == n)
+            && (0..n).all(|i| {
+                correlation[i][i] == 1.0
+                    && (0..n).all(|j| {
+                        correlation[i][j] == correlation[j][i] && correlation[i][j].abs() <= 1.0
+                    })
+            });
+        if !symmetric {
+            return Err(OracleError::DistributionError(format!(
+                "A correlation matrix of {} fields is {}x{}, symmetric, with ones on the diagonal",
+                n, n, n
+            )));
+        }
+
+        let cholesky = cholesky(&correlation).ok_or_else(|| {
+            OracleError::DistributionError(
+                "Correlation matrix isn't positive definite, the correlations contradict each other"
+                    .to_string(),
+            )
+        })?;
+        let (columns, marginals) = fields
+            .into_iter()
+            .map(|(name, numeric)| (name.to_string(), numeric))
+            .unzip();
+
+        Ok(Self {
+            columns,
+            marginals,
+            cholesky,
+        })
+    }
+
+    /// Two fields with correlation coefficient `rho`.
+    pub fn pair(
+        first: (&str, Numeric),
+        second: (&str, Numeric),
+        rho: f64,
+    ) -> Result<Self, OracleError> {
+        Self::new(vec![first, second], vec![vec![1.0, rho], vec![rho, 1.0]])
+    }
+
+    fn sample(&self, rng: &mut dyn RngCore) -> impl Iterator<Item = Number> {
+        let z: Vec<f64> = (0..self.marginals.len())
+            .map(|_| StandardNormal.sample(rng))
+            .collect();
+
+        self.cholesky
+            .iter()
+            .zip(&self.marginals)
+            .map(move |(row, marginal)| {
+                let x: f64 = row.iter().zip(&z).map(|(l, z)| l * z).sum();
+                marginal.at_quantile(normal_cdf(x))
+            })
+    }
+}
+
+// None if the matrix isn't positive definite
+fn cholesky(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut l = vec![vec![0.0; n]; n];
+
+    for i in 0..n {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+            if i == j {
+                let diagonal = matrix[i][i] - sum;
+                if diagonal <= 1e-12 {
+                    return None;
+                }
+                l[i][j] = diagonal.sqrt();
+            } else {
+                l[i][j] = (matrix[i][j] - sum) / l[j][j];
+            }
+        }
+    }
+
+    Some(l)
+}
+
+fn exhausted(column: &str) -> OracleError {
+    OracleError::RecordError(format!("Field {} ran out of values", column))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use tseger::generator::Choice;
+
+    use super::*;
+    use crate::numeric::Distribution;
+
+    fn cities() -> JointTable {
+        let rows = [
+            ["Munich", "80331", "Germany"],
+            ["Berlin", "10115", "Germany"],
+            ["Lyon", "69001", "France"],
+        ];
+        JointTable::new(
+            &["city", "zip", "country"],
+            rows.iter()
+                .map(|row| row.iter().map(|v| v.to_string()).collect())
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_joint_and_conditional_fields_stay_consistent() {
+        let mut record = Record::new()
+            .joint(cities().weights(vec![1.0, 1.0, 2.0]).unwrap())
+            .unwrap()
+            .conditional(
+                "phone_prefix",
+                Conditional::on("country")
+                    .case("Germany", Choice::new(["+49"]))
+                    .case("France", Choice::new(["+33"])),
+            )
+            .unwrap()
+            .field("name", Choice::new(["Ana", "Ben"]))
+            .unwrap()
+            .solved(
+                Solver::new()
+                    .int("qty", 1, 5)
+                    .and_then(|s| s.int("stock", 1, 5))
+                    .and_then(|s| s.rule("qty < stock"))
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            record.columns(),
+            [
+                "city",
+                "zip",
+                "country",
+                "phone_prefix",
+                "name",
+                "qty",
+                "stock"
+            ]
+        );
+
+        let mut rng = ChaCha8Rng::seed_from_u64(4);
+        let mut row = Vec::new();
+        let mut lyon = 0;
+        for _ in 0..400 {
+            record.sample_into(&mut rng, &mut row).unwrap();
+            let (zip, prefix) = match row[0].as_str() {
+                "Munich" => ("80331", "+49"),
+                "Berlin" => ("10115", "+49"),
+                _ => ("69001", "+33"),
+            };
+            assert_eq!(
+                (row[1].as_str(), row[3].as_str()),
+                (zip, prefix),
+                "{:?}",
+                row
+            );
+            let (qty, stock): (u8, u8) = (row[5].parse().unwrap(), row[6].parse().unwrap());
+            assert!(qty < stock, "{:?}", row);
+            lyon += usize::from(row[0] == "Lyon");
+        }
+        assert!((160..240).contains(&lyon), "{}", lyon);
+    }
+
+    #[test]
+    fn test_copula_correlates_and_keeps_marginals() {
+        let height = Numeric::float(Distribution::Normal {
+            mean: 170.0,
+            std_dev: 10.0,
+        })
+        .unwrap();
+        let weight = Numeric::float(Distribution::LogNormal {
+            mu: 4.2,
+            sigma: 0.2,
+        })
+        .unwrap();
+        let mut record = Record::new()
+            .copula(Copula::pair(("height", height), ("weight", weight), 0.8).unwrap())
+            .unwrap();
+
+        let mut rng = ChaCha8Rng::seed_from_u64(8);
+        let (heights, weights): (Vec<f64>, Vec<f64>) = (0..5000)
+            .map(|_| {
+                let row = record.sample(&mut rng).unwrap();
+                (
+                    row[0].parse::<f64>().unwrap(),
+                    row[1].parse::<f64>().unwrap().ln(),
+                )
+            })
+            .unzip();
+
+        let mean = |v: &[f64]| v.iter().sum::<f64>() / v.len() as f64;
+        let (mh, mw) = (mean(&heights), mean(&weights));
+        let covariance = heights
+            .iter()
+            .zip(&weights)
+            .map(|(h, w)| (h - mh) * (w - mw))
+            .sum::<f64>()
+            / heights.len() as f64;
+        let deviation = |v: &[f64], m: f64| {
+            (v.iter().map(|x| (x - m).powi(2)).sum::<f64>() / v.len() as f64).sqrt()
+        };
+        let correlation = covariance / (deviation(&heights, mh) * deviation(&weights, mw));
+
+        assert!((mh - 170.0).abs() < 0.5, "{}", mh);
+        assert!((mw - 4.2).abs() < 0.01, "{}", mw);
+        assert!((correlation - 0.8).abs() < 0.03, "{}", correlation);
+    }
+
+    #[test]
+    fn test_invalid_records_are_refused() {
+        let uniform = || {
+            Numeric::float(Distribution::Uniform {
+                low: 0.0,
+                high: 1.0,
+            })
+            .unwrap()
+        };
+        let contradicting = vec![
+            vec![1.0, 0.9, -0.9],
+            vec![0.9, 1.0, 0.9],
+            vec![-0.9, 0.9, 1.0],
+        ];
+        let errors = [
+            Copula::new(
+                vec![("a", uniform()), ("b", uniform()), ("c", uniform())],
+                contradicting,
+            )
+            .err(),
+            Copula::pair(("a", uniform()), ("b", uniform()), 1.5).err(),
+            JointTable::new(&["a", "b"], vec![vec!["x".to_string()]]).err(),
+            Record::new()
+                .conditional("zip", Conditional::on("city"))
+                .err(),
+            Record::new()
+                .joint(cities())
+                .and_then(|r| r.field("zip", Choice::new(["1"])))
+                .err(),
+        ];
+        for error in &errors {
+            assert!(error.is_some());
+        }
+
+        let mut record = Record::new()
+            .joint(cities())
+            .unwrap()
+            .conditional(
+                "prefix",
+                Conditional::on("country").case("Germany", Choice::new(["+49"])),
+            )
+            .unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let err = (0..50).find_map(|_| record.sample(&mut rng).err()).unwrap();
+        assert!(
+            err.to_string()
+                .contains("prefix has no case for country = \"France\""),
+            "{}",
+            err
+        );
+    }
+}
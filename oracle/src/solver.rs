@@ -0,0 +1,641 @@
+use std::fmt;
+
+use rand::RngCore;
+use rand::prelude::*;
+
+use crate::common::OracleError;
+
+/*
+* Finite-domain solver for linear rules between the fields of one record,
+* `end_date >= start_date + 7`, `discount <= price`, `sum(qty) == total`:
+*
+* - every variable is an integer inside: ints as they are, floats in steps of
+*   their precision (cents for 2) and dates as days since 1970-01-01, so rules
+*   are integer linear and `<` is simply `<= - 1` step
+* - bounds propagation shrinks each domain to what the rules still allow, it
+*   runs when a rule is added, so contradicting rules fail right there; rules
+*   that keep shrinking the domains a step at a time (x < y and y < x over
+*   wide domains) are refused there too once MAX_PASSES runs out
+* - a record is sampled by fixing the variables one by one, in random order,
+*   to a random value of their propagated domain and propagating again; tight
+*   rules only narrow the domains, nothing is rejected, only an equality with
+*   gaps (2 * x == y with y odd) can dead-end, and the record then starts over
+* - values are spread over all solutions but not uniformly, the first variable
+*   fixed is uniform over its feasible range and the rest follow it
+*/
+
+const MAX_RESTARTS: usize = 100;
+// Propagation passes per call, rules like x < y, y < x over huge domains only
+// shrink by one step per pass and are cut off here
+const MAX_PASSES: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VarKind {
+    Int,
+    Float { precision: u32 },
+    Date,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Relation {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+pub struct Solver {
+    names: Vec<String>,
+    kinds: Vec<VarKind>,
+    // Domains after propagating every rule, what sampling starts from
+    domains: Vec<(i64, i64)>,
+    rules: Vec<Linear>,
+}
+
+// sum(coefficient * var) <= constant, in internal units
+struct Linear {
+    terms: Vec<(i128, usize)>,
+    constant: i128,
+    // The rule as written, for errors
+    text: String,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        Self {
+            names: Vec::new(),
+            kinds: Vec::new(),
+            domains: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn int(self, name: &str, min: i64, max: i64) -> Result<Self, OracleError> {
+        self.variable(name, VarKind::Int, (min, max))
+    }
+
+    /// Values are multiples of 10^-precision.
+    pub fn float(
+        self,
+        name: &str,
+        min: f64,
+        max: f64,
+        precision: u32,
+    ) -> Result<Self, OracleError> {
+        let scale = 10f64.powi(precision as i32);
+        let domain = (
+            snap(min * scale).ceil() as i64,
+            snap(max * scale).floor() as i64,
+        );
+        self.variable(name, VarKind::Float { precision }, domain)
+    }
+
+    /// Bounds are `YYYY-MM-DD`, both included.
+    pub fn date(self, name: &str, min: &str, max: &str) -> Result<Self, OracleError> {
+        let domain = (parse_date(min)?, parse_date(max)?);
+        self.variable(name, VarKind::Date, domain)
+    }
+
+    fn variable(
+        mut self,
+        name: &str,
+        kind: VarKind,
+        (min, max): (i64, i64),
+    ) -> Result<Self, OracleError> {
+        if self.names.iter().any(|n| n == name) {
+            return Err(solver_error(format!("Variable {} is defined twice", name)));
+        }
+        if min > max {
+            return Err(solver_error(format!(
+                "Variable {} has an empty domain",
+                name
+            )));
+        }
+
+        self.names.push(name.to_string());
+        self.kinds.push(kind);
+        self.domains.push((min, max));
+        Ok(self)
+    }
+
+    /// Rule over variables already defined, like `total == a + b - 2 * c + 10`.
+    /// Constants are in the units of the variables: days for dates.
+    /// `sum(qty)` stands for every variable named `qty.<something>`.
+    pub fn rule(self, text: &str) -> Result<Self, OracleError> {
+        let (left, relation, right) = parse_rule(text)?;
+        let mut written = left.terms;
+        written.extend(right.terms.into_iter().map(|(c, name)| (-c, name)));
+
+        let mut terms = Vec::new();
+        for (c, name) in written {
+            let Some(prefix) = name.strip_prefix("sum(").and_then(|n| n.strip_suffix(')')) else {
+                terms.push((c, name));
+                continue;
+            };
+            let prefix = format!("{}.", prefix);
+            let before = terms.len();
+            terms.extend(
+                self.names
+                    .iter()
+                    .filter(|n| n.starts_with(&prefix))
+                    .map(|n| (c, n.clone())),
+            );
+            if terms.len() == before {
+                return Err(solver_error(format!(
+                    "{}: no variables named {}*",
+                    text, prefix
+                )));
+            }
+        }
+        self.constrain(&terms, relation, right.constant - left.constant, text)
+    }
+
+    /// sum(coefficient * variable) relation constant
+    pub fn constrain(
+        mut self,
+        terms: &[(f64, String)],
+        relation: Relation,
+        constant: f64,
+        text: &str,
+    ) -> Result<Self, OracleError> {
+        // Everything is scaled up to the finest precision among the variables
+        let index = |name: &String| {
+            self.names
+                .iter()
+                .position(|n| n == name)
+                .ok_or_else(|| solver_error(format!("{}: unknown variable {}", text, name)))
+        };
+        let resolved = terms
+            .iter()
+            .map(|(c, name)| Ok((*c, index(name)?)))
+            .collect::<Result<Vec<(f64, usize)>, OracleError>>()?;
+        let finest = resolved
+            .iter()
+            .map(|&(_, var)| self.precision(var))
+            .max()
+            .unwrap_or(0);
+
+        let mut scaled = Vec::new();
+        for (coefficient, var) in resolved {
+            let c = snap(coefficient * 10f64.powi((finest - self.precision(var)) as i32));
+            if c.fract() != 0.0 {
+                return Err(solver_error(format!(
+                    "{}: coefficients have to be whole numbers, {} isn't",
+                    text, coefficient
+                )));
+            }
+            scaled.push((c as i128, var));
+        }
+        let constant = snap(constant * 10f64.powi(finest as i32));
+
+        // Everything ends up as sum <= constant
+        let negated = |terms: &[(i128, usize)]| terms.iter().map(|&(c, v)| (-c, v)).collect();
+        let mut added = Vec::new();
+        let mut add = |terms: Vec<(i128, usize)>, constant: i128| {
+            added.push(Linear {
+                terms,
+                constant,
+                text: text.to_string(),
+            })
+        };
+        match relation {
+            Relation::LessOrEqual => add(scaled, constant.floor() as i128),
+            Relation::Less => add(scaled, constant.ceil() as i128 - 1),
+            Relation::GreaterOrEqual => add(negated(&scaled), -(constant.ceil() as i128)),
+            Relation::Greater => add(negated(&scaled), -(constant.floor() as i128) - 1),
+            Relation::Equal => {
+                if constant.fract() != 0.0 {
+                    return Err(unsatisfiable(text));
+                }
+                add(negated(&scaled), -(constant as i128));
+                add(scaled, constant as i128);
+            }
+        }
+        self.rules.extend(added);
+
+        let mut domains = self.domains.clone();
+        match propagate(&self.rules, &mut domains) {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(solver_error(format!(
+                    "{} keeps narrowing the domains without settling in {} passes, \
+                     it likely contradicts the rules before it",
+                    text, MAX_PASSES
+                )));
+            }
+            Err(_) => return Err(unsatisfiable(text)),
+        }
+        self.domains = domains;
+        Ok(self)
+    }
+
+    fn precision(&self, var: usize) -> u32 {
+        match self.kinds[var] {
+            VarKind::Float { precision } => precision,
+            VarKind::Int | VarKind::Date => 0,
+        }
+    }
+
+    /// One value per variable, in the order of columns().
+    pub fn sample(&self, rng: &mut dyn RngCore) -> Result<Vec<String>, OracleError> {
+        let mut values = vec![String::new(); self.names.len()];
+        self.sample_into(rng, &mut values)?;
+        Ok(values)
+    }
+
+    /// Appends each variable's value to its string in `values`.
+    pub fn sample_into(
+        &self,
+        rng: &mut dyn RngCore,
+        values: &mut [String],
+    ) -> Result<(), OracleError> {
+        let mut order: Vec<usize> = (0..self.names.len()).collect();
+        let mut dead_end = "";
+
+        'restart: for _ in 0..MAX_RESTARTS {
+            let mut domains = self.domains.clone();
+            order.shuffle(rng);
+
+            for &var in &order {
+                let (min, max) = domains[var];
+                if min < max {
+                    let value = rng.random_range(min..=max);
+                    domains[var] = (value, value);
+                    if let Err(rule) = propagate(&self.rules, &mut domains) {
+                        dead_end = rule;
+                        continue 'restart;
+                    }
+                }
+            }
+
+            for ((value, kind), &(fixed, _)) in values.iter_mut().zip(&self.kinds).zip(&domains) {
+                render(*kind, fixed, value);
+            }
+            return Ok(());
+        }
+
+        Err(OracleError::RejectionError(format!(
+            "No assignment satisfied the rules in {} attempts, the last one stuck on {}",
+            MAX_RESTARTS, dead_end
+        )))
+    }
+}
+
+// Bounds consistency, Err with the rule that emptied a domain, Ok(false) if
+// the domains were still shrinking after MAX_PASSES, they are sound either way
+fn propagate<'a>(rules: &'a [Linear], domains: &mut [(i64, i64)]) -> Result<bool, &'a str> {
+    for _ in 0..MAX_PASSES {
+        let mut changed = false;
+
+        for rule in rules {
+            let total: i128 = rule.terms.iter().map(|t| least(t, domains)).sum();
+            if total > rule.constant {
+                return Err(&rule.text);
+            }
+
+            for term in &rule.terms {
+                let (c, var) = *term;
+                // c * x <= constant - (everything else at its smallest)
+                let slack = rule.constant - (total - least(term, domains));
+                let (min, max) = domains[var];
+                let bound = if c > 0 {
+                    (min, max.min(clamp_i64(slack.div_euclid(c))))
+                } else {
+                    (min.max(clamp_i64(-(slack.div_euclid(-c)))), max)
+                };
+                if bound != (min, max) {
+                    if bound.0 > bound.1 {
+                        return Err(&rule.text);
+                    }
+                    domains[var] = bound;
+                    changed = true;
+                }
+            }
+        }
+
+        if !changed {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Smallest value a term can take
+fn least(&(c, var): &(i128, usize), domains: &[(i64, i64)]) -> i128 {
+    let (min, max) = domains[var];
+    if c > 0 {
+        c * min as i128
+    } else {
+        c * max as i128
+    }
+}
+
+// 1.1 * 100 is 110.00000000000001, whole numbers are meant
+fn snap(value: f64) -> f64 {
+    match (value - value.round()).abs() < 1e-9 {
+        true => value.round(),
+        false => value,
+    }
+}
+
+fn clamp_i64(value: i128) -> i64 {
+    value.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+fn render(kind: VarKind, value: i64, out: &mut String) {
+    use std::fmt::Write;
+
+    let _ = match kind {
+        VarKind::Int => write!(out, "{}", value),
+        VarKind::Float { precision } => write!(
+            out,
+            "{:.*}",
+            precision as usize,
+            value as f64 / 10f64.powi(precision as i32)
+        ),
+        VarKind::Date => {
+            let (y, m, d) = civil_from_days(value);
+            write!(out, "{:04}-{:02}-{:02}", y, m, d)
+        }
+    };
+}
+
+// Howard Hinnant's days_from_civil and civil_from_days, proleptic Gregorian
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(m <= 2), m, d)
+}
+
+fn parse_date(text: &str) -> Result<i64, OracleError> {
+    let invalid = || solver_error(format!("Invalid date {:?}, expected YYYY-MM-DD", text));
+    let parts: Vec<i64> = text
+        .split('-')
+        .map(|part| part.parse::<i64>().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let [y, m, d] = parts[..] else {
+        return Err(invalid());
+    };
+
+    let days = days_from_civil(y, m, d);
+    // Out of range months and days roll over, a round trip catches them
+    match civil_from_days(days) == (y, m, d) {
+        true => Ok(days),
+        false => Err(invalid()),
+    }
+}
+
+// One side of a rule, sum(coefficient * name) + constant
+struct Side {
+    terms: Vec<(f64, String)>,
+    constant: f64,
+}
+
+fn parse_rule(text: &str) -> Result<(Side, Relation, Side), OracleError> {
+    let relations = [
+        ("<=", Relation::LessOrEqual),
+        (">=", Relation::GreaterOrEqual),
+        ("==", Relation::Equal),
+        ("<", Relation::Less),
+        (">", Relation::Greater),
+    ];
+    let (at, op, relation) = relations
+        .iter()
+        .filter_map(|&(op, relation)| text.find(op).map(|at| (at, op, relation)))
+        .min_by_key(|&(at, op, _)| (at, usize::MAX - op.len()))
+        .ok_or_else(|| solver_error(format!("{}: no relation, one of <, <=, ==, >=, >", text)))?;
+
+    let side = |part: &str| parse_side(part).map_err(|e| solver_error(format!("{}: {}", text, e)));
+    Ok((side(&text[..at])?, relation, side(&text[at + op.len()..])?))
+}
+
+// Terms like `2 * x`, `x`, `3` joined by + and -
+fn parse_side(text: &str) -> Result<Side, String> {
+    let mut side = Side {
+        terms: Vec::new(),
+        constant: 0.0,
+    };
+
+    let spaced = text
+        .replace('+', " + ")
+        .replace('-', " - ")
+        .replace('*', " * ");
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+    let mut i = 0;
+    let mut sign = 1.0;
+
+    while i < tokens.len() {
+        match tokens[i] {
+            "+" => {}
+            "-" => sign = -sign,
+            token => {
+                match token.parse::<f64>() {
+                    Ok(number) if tokens.get(i + 1) == Some(&"*") => {
+                        let name = tokens
+                            .get(i + 2)
+                            .filter(|t| is_name(t))
+                            .ok_or_else(|| format!("expected a variable after {} *", token))?;
+                        side.terms.push((sign * number, name.to_string()));
+                        i += 2;
+                    }
+                    Ok(number) => side.constant += sign * number,
+                    Err(_) if is_name(token) => side.terms.push((sign, token.to_string())),
+                    Err(_) => return Err(format!("unexpected {:?}", token)),
+                }
+                sign = 1.0;
+            }
+        }
+        i += 1;
+    }
+
+    match side.terms.is_empty() && tokens.is_empty() {
+        true => Err("empty side".to_string()),
+        false => Ok(side),
+    }
+}
+
+fn is_name(token: &str) -> bool {
+    let token = match token.strip_prefix("sum(") {
+        Some(inner) => inner.strip_suffix(')').unwrap_or("("),
+        None => token,
+    };
+    token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn solver_error(message: String) -> OracleError {
+    OracleError::SolverError(message)
+}
+
+fn unsatisfiable(rule: &str) -> OracleError {
+    solver_error(format!(
+        "{} can't be satisfied together with the rules and domains before it",
+        rule
+    ))
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Self::Less => "<",
+            Self::LessOrEqual => "<=",
+            Self::Equal => "==",
+            Self::GreaterOrEqual => ">=",
+            Self::Greater => ">",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_dates_and_floats() {
+        let solver = Solver::new()
+            .date("start_date", "2024-01-01", "2024-12-31")
+            .unwrap()
+            .date("end_date", "2024-01-01", "2024-12-31")
+            .unwrap()
+            .rule("end_date >= start_date + 7")
+            .unwrap()
+            .float("price", 1.1, 100.0, 2)
+            .unwrap()
+            .float("discount", 0.0, 50.0, 2)
+            .unwrap()
+            .rule("discount <= price - 0.99")
+            .unwrap();
+
+        let mut rng = ChaCha8Rng::seed_from_u64(6);
+        for _ in 0..500 {
+            let row = solver.sample(&mut rng).unwrap();
+            let (start, end) = (parse_date(&row[0]).unwrap(), parse_date(&row[1]).unwrap());
+            let (price, discount): (f64, f64) = (row[2].parse().unwrap(), row[3].parse().unwrap());
+
+            assert!(end >= start + 7, "{:?}", row);
+            assert!(row[1].as_str() <= "2024-12-31", "{:?}", row);
+            assert!(
+                discount <= price - 0.99 + 1e-9 && row[2].split('.').nth(1).unwrap().len() == 2
+            );
+        }
+
+        // 1.1 * 100 isn't exactly 110
+        let single = Solver::new().float("p", 1.1, 1.1, 2).unwrap();
+        assert_eq!(single.sample(&mut rng).unwrap(), ["1.10"]);
+
+        assert_eq!(
+            civil_from_days(parse_date("2000-02-29").unwrap()),
+            (2000, 2, 29)
+        );
+        assert!(parse_date("2023-02-29").is_err());
+    }
+
+    #[test]
+    fn test_tight_sums_are_sampled_not_rejected() {
+        let solver = Solver::new()
+            .int("items.qty.0", 1, 10)
+            .unwrap()
+            .int("items.qty.1", 1, 10)
+            .unwrap()
+            .int("items.qty.2", 1, 10)
+            .unwrap()
+            .int("total_qty", 29, 29)
+            .unwrap()
+            .rule("sum(items.qty) == total_qty")
+            .unwrap()
+            .int("even", 0, 100)
+            .unwrap()
+            .int("half", 0, 100)
+            .unwrap()
+            .rule("2 * half == even")
+            .unwrap();
+
+        // 29 leaves only permutations of 10, 10, 9
+        let mut rng = ChaCha8Rng::seed_from_u64(10);
+        let mut nines = [0; 3];
+        for _ in 0..300 {
+            let row: Vec<i64> = solver
+                .sample(&mut rng)
+                .unwrap()
+                .iter()
+                .map(|v| v.parse().unwrap())
+                .collect();
+            assert_eq!(row[0] + row[1] + row[2], 29);
+            assert_eq!(row[4], 2 * row[5]);
+            (0..3).for_each(|i| nines[i] += usize::from(row[i] == 9));
+        }
+        assert!(nines.iter().all(|&n| n > 50), "{:?}", nines);
+    }
+
+    #[test]
+    fn test_contradictions_fail_when_added() {
+        let base = || {
+            Solver::new()
+                .int("x", 0, 10)
+                .unwrap()
+                .int("y", 0, 10)
+                .unwrap()
+        };
+
+        let err = base().rule("x < y").unwrap().rule("y < x").err().unwrap();
+        assert!(
+            err.to_string().contains("y < x can't be satisfied"),
+            "{}",
+            err
+        );
+        // Over wide domains the same rules only shrink a step per pass
+        let wide = Solver::new()
+            .int("x", 0, 1_000_000_000)
+            .and_then(|s| s.int("y", 0, 1_000_000_000))
+            .and_then(|s| s.rule("x < y"))
+            .unwrap();
+        let err = wide.rule("y < x").err().unwrap();
+        assert!(err.to_string().contains("y < x keeps narrowing"), "{}", err);
+        assert!(base().rule("x + y > 20").is_err());
+        assert!(base().rule("x + y == 20").is_ok());
+
+        for bad in [
+            "x y",
+            "x <= z",
+            "x * y < 3",
+            "0.5 * x < y",
+            "sum(x) < y",
+            "",
+        ] {
+            assert!(base().rule(bad).is_err(), "{}", bad);
+        }
+        assert!(Solver::new().date("d", "2024-13-01", "2025-01-01").is_err());
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::LN_2;
+use std::fs::{File, OpenOptions};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+use crate::common::OracleError;
+
+/*
+* Sets of the values an Oracle already handed out, for unique columns:
+*
+* - Exact keeps every value in a HashSet, right for anything that fits in memory
+* - Spilled keeps at most `memory_limit` values in memory, spread over buckets
+*   by hash, and appends the largest bucket to its file on disk when full; a
+*   Bloom filter over the spilled values means a bucket file is only read when
+*   the value was probably seen, and then an index from value hashes to file
+*   offsets means only the records with the same hash are read back
+* - Approximate is a Bloom filter alone, a handful of bits per value; now and
+*   then a new value is mistaken for a duplicate and dropped, a duplicate never
+*   gets through
+*/
+
+// Buckets of the spilled set, each one is a file once it spills
+const BUCKETS: usize = 64;
+// Spilled values are checked against a Bloom filter before any file is read
+const SPILL_FALSE_POSITIVE_RATE: f64 = 0.01;
+// 1 GiB, Bloom filters are allocated up front and sized by the requested count
+const MAX_BLOOM_BITS: f64 = (1u64 << 33) as f64;
+// Offset of the previous record with the same hash, for the first one
+const NO_RECORD: u64 = u64::MAX;
+
+pub enum Uniqueness {
+    Exact,
+    Spilled {
+        memory_limit: usize,
+        // System temp directory when None
+        dir: Option<PathBuf>,
+    },
+    Approximate {
+        false_positive_rate: f64,
+    },
+}
+
+pub(crate) enum SeenSet {
+    Exact(HashSet<String>),
+    Spilled(Box<SpilledSet>),
+    Approximate(BloomFilter),
+}
+
+impl SeenSet {
+    /// `expected` values are going to be inserted, it sizes the Bloom filters.
+    pub(crate) fn new(backing: &Uniqueness, expected: usize) -> Result<Self, OracleError> {
+        Ok(match backing {
+            Uniqueness::Exact => Self::Exact(HashSet::new()),
+            Uniqueness::Spilled { memory_limit, dir } => Self::Spilled(Box::new(SpilledSet::new(
+                *memory_limit,
+                dir.as_deref(),
+                expected,
+            )?)),
+            Uniqueness::Approximate {
+                false_positive_rate,
+            } => Self::Approximate(BloomFilter::new(expected, *false_positive_rate)?),
+        })
+    }
+
+    /// True if the value wasn't seen before, from now on it is.
+    pub(crate) fn insert(&mut self, value: &str) -> Result<bool, OracleError> {
+        match self {
+            Self::Exact(set) => Ok(set.insert(value.to_string())),
+            Self::Spilled(set) => set.insert(value),
+            Self::Approximate(filter) => Ok(filter.insert(value)),
+        }
+    }
+}
+
+pub(crate) struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u64,
+}
+
+impl BloomFilter {
+    pub(crate) fn new(expected: usize, false_positive_rate: f64) -> Result<Self, OracleError> {
+        let n = expected.max(1) as f64;
+        let p = false_positive_rate.clamp(1e-12, 0.5);
+        let bits = (-n * p.ln() / (LN_2 * LN_2)).ceil().max(64.0);
+        if bits > MAX_BLOOM_BITS {
+            return Err(OracleError::UniquenessError(format!(
+                "{} values at a false positive rate of {} need a {} MiB Bloom filter, at most {} MiB are allowed",
+                expected,
+                p,
+                (bits / 8.0 / 1048576.0).ceil(),
+                MAX_BLOOM_BITS / 8.0 / 1048576.0
+            )));
+        }
+
+        Ok(Self {
+            bits: vec![0; (bits as usize).div_ceil(64)],
+            hashes: (bits / n * LN_2).round().clamp(1.0, 32.0) as u64,
+        })
+    }
+
+    // Kirsch-Mitzenmacher, two hashes stand in for all of them
+    fn positions(&self, value: &str) -> impl Iterator<Item = usize> + use<> {
+        let (h1, h2) = (hash(0, value), hash(1, value) | 1);
+        let len = self.bits.len() as u64 * 64;
+
+        (0..self.hashes).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    pub(crate) fn contains(&self, value: &str) -> bool {
+        self.positions(value)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// True if the value wasn't (probably) in the filter before.
+    pub(crate) fn insert(&mut self, value: &str) -> bool {
+        let mut new = false;
+        for bit in self.positions(value) {
+            let word = &mut self.bits[bit / 64];
+            new |= *word & (1 << (bit % 64)) == 0;
+            *word |= 1 << (bit % 64);
+        }
+        new
+    }
+}
+
+pub(crate) struct SpilledSet {
+    dir: TempDir,
+    memory_limit: usize,
+    in_memory: Vec<HashSet<String>>,
+    held: usize,
+    // Per bucket, hash of a spilled value to the offset of the last record with that hash
+    index: Vec<HashMap<u64, u64>>,
+    written: Vec<u64>,
+    on_disk: BloomFilter,
+}
+
+impl SpilledSet {
+    fn new(memory_limit: usize, dir: Option<&Path>, expected: usize) -> Result<Self, OracleError> {
+        let dir = match dir {
+            Some(dir) => tempfile::tempdir_in(dir).map_err(|e| spill_error(dir, e))?,
+            None => tempfile::tempdir().map_err(|e| spill_error(&std::env::temp_dir(), e))?,
+        };
+
+        Ok(Self {
+            dir,
+            memory_limit: memory_limit.max(1),
+            in_memory: vec![HashSet::new(); BUCKETS],
+            held: 0,
+            index: vec![HashMap::new(); BUCKETS],
+            written: vec![0; BUCKETS],
+            on_disk: BloomFilter::new(expected, SPILL_FALSE_POSITIVE_RATE)?,
+        })
+    }
+
+    fn insert(&mut self, value: &str) -> Result<bool, OracleError> {
+        let bucket = (hash(2, value) % BUCKETS as u64) as usize;
+
+        if self.in_memory[bucket].contains(value)
+            || (self.on_disk.contains(value) && self.scan(bucket, value)?)
+        {
+            return Ok(false);
+        }
+
+        self.in_memory[bucket].insert(value.to_string());
+        self.held += 1;
+        if self.held > self.memory_limit {
+            self.spill()?;
+        }
+        Ok(true)
+    }
+
+    fn path(&self, bucket: usize) -> PathBuf {
+        self.dir.path().join(format!("{}.bucket", bucket))
+    }
+
+    // Appends the largest bucket to its file, each record is the offset of the
+    // previous one with the same hash, the value's length and the value
+    fn spill(&mut self) -> Result<(), OracleError> {
+        let bucket = (0..BUCKETS)
+            .max_by_key(|&b| self.in_memory[b].len())
+            .expect("there is at least one bucket");
+        let path = self.path(bucket);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| spill_error(&path, e))?;
+        let mut writer = BufWriter::new(file);
+        for value in self.in_memory[bucket].drain() {
+            let key = hash(3, &value);
+            let previous = self.index[bucket].insert(key, self.written[bucket]);
+            writer
+                .write_all(&previous.unwrap_or(NO_RECORD).to_le_bytes())
+                .and_then(|_| writer.write_all(&(value.len() as u32).to_le_bytes()))
+                .and_then(|_| writer.write_all(value.as_bytes()))
+                .map_err(|e| spill_error(&path, e))?;
+            self.written[bucket] += 12 + value.len() as u64;
+            self.on_disk.insert(&value);
+            self.held -= 1;
+        }
+        writer.flush().map_err(|e| spill_error(&path, e))?;
+
+        Ok(())
+    }
+
+    // Follows the records with the value's hash, newest first
+    fn scan(&self, bucket: usize, value: &str) -> Result<bool, OracleError> {
+        let Some(&last) = self.index[bucket].get(&hash(3, value)) else {
+            return Ok(false);
+        };
+        let path = self.path(bucket);
+        let mut reader = BufReader::new(File::open(&path).map_err(|e| spill_error(&path, e))?);
+        let mut header = [0u8; 12];
+        let mut stored = Vec::new();
+
+        let mut offset = last;
+        while offset != NO_RECORD {
+            reader
+                .seek(SeekFrom::Start(offset))
+                .and_then(|_| reader.read_exact(&mut header))
+                .map_err(|e| spill_error(&path, e))?;
+            let (previous, len) = header.split_at(8);
+            stored.resize(u32::from_le_bytes(len.try_into().unwrap()) as usize, 0);
+            reader
+                .read_exact(&mut stored)
+                .map_err(|e| spill_error(&path, e))?;
+            if stored == value.as_bytes() {
+                return Ok(true);
+            }
+            offset = u64::from_le_bytes(previous.try_into().unwrap());
+        }
+
+        Ok(false)
+    }
+}
+
+fn hash(seed: u8, value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn spill_error(path: &Path, e: std::io::Error) -> OracleError {
+    OracleError::UniquenessError(format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter_has_no_false_negatives() {
+        let mut filter = BloomFilter::new(1000, 0.01).unwrap();
+        let values: Vec<String> = (0..1000).map(|i| format!("user{}", i)).collect();
+
+        let new = values.iter().filter(|v| filter.insert(v)).count();
+        assert!(new > 980, "{}", new);
+        assert!(
+            values
+                .iter()
+                .all(|v| filter.contains(v) && !filter.insert(v))
+        );
+
+        let false_positives = (1000..11000)
+            .filter(|i| filter.contains(&format!("user{}", i)))
+            .count();
+        assert!(false_positives < 300, "{}", false_positives);
+
+        // Huge counts are refused before anything is allocated
+        let approximate = Uniqueness::Approximate {
+            false_positive_rate: 0.01,
+        };
+        let err = SeenSet::new(&approximate, usize::MAX).err().unwrap();
+        assert!(err.to_string().contains("at most 1024 MiB"), "{}", err);
+        let spilled = Uniqueness::Spilled {
+            memory_limit: 10,
+            dir: None,
+        };
+        assert!(SeenSet::new(&spilled, 1 << 40).is_err());
+    }
+
+    #[test]
+    fn test_spilled_set_agrees_with_exact() {
+        let mut exact = SeenSet::new(&Uniqueness::Exact, 500).unwrap();
+        let mut spilled = SeenSet::new(
+            &Uniqueness::Spilled {
+                memory_limit: 50,
+                dir: None,
+            },
+            500,
+        )
+        .unwrap();
+
+        // Every value shows up twice, the second time well after it spilled
+        for i in (0..500).chain(0..500).map(|i| (i * 7919) % 500) {
+            let value = format!("row\n{}", i);
+            assert_eq!(
+                exact.insert(&value).unwrap(),
+                spilled.insert(&value).unwrap(),
+                "{:?}",
+                value
+            );
+        }
+
+        let SeenSet::Spilled(set) = &spilled else {
+            unreachable!()
+        };
+        assert!(set.held <= 50);
+        assert!(set.index.iter().any(|index| !index.is_empty()));
+        assert_eq!(
+            set.index.iter().map(HashMap::len).sum::<usize>() + set.held,
+            500
+        );
+    }
+}
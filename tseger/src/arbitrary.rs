@@ -0,0 +1,124 @@
+use quickcheck::Arbitrary;
+use quickcheck::Gen;
+
+use crate::common::TsegerError;
+use crate::dfa::Dfa;
+use crate::generate;
+use crate::generate::Sampler;
+use crate::random;
+
+pub use quickcheck;
+
+/*
+* quickcheck support, behind the `quickcheck` feature. Arbitrary is implemented
+* per type, so patterns get a newtype from regex_arbitrary!:
+*
+*   tseger::regex_arbitrary!(pub struct Zip = "[0-9]{5}");
+*
+*   #[quickcheck]
+*   fn parses(zip: Zip) -> bool { parse_zip(&zip.0).is_ok() }
+*
+* Shrinking goes through generate::shrink_candidates, like the proptest strategy.
+*/
+
+const DEFAULT_MAX_LEN: usize = 32;
+
+pub struct RegexArbitrary {
+    dfa: Dfa,
+    sampler: Sampler,
+}
+
+impl RegexArbitrary {
+    pub fn new(pattern: &str) -> Result<Self, TsegerError> {
+        Self::from_dfa(Dfa::compile(pattern)?, DEFAULT_MAX_LEN)
+    }
+
+    pub fn from_dfa(dfa: Dfa, max_len: usize) -> Result<Self, TsegerError> {
+        Ok(Self {
+            sampler: Sampler::new(&dfa, max_len)?,
+            dfa,
+        })
+    }
+
+    pub fn arbitrary(&self, g: &mut Gen) -> String {
+        // Gen doesn't expose its RNG, a seed drawn from it keeps runs reproducible
+        let seed = u64::arbitrary(g);
+        self.sampler.sample(&mut random::seeded(seed))
+    }
+
+    pub fn shrink(&self, value: &str) -> Box<dyn Iterator<Item = String>> {
+        Box::new(generate::shrink_candidates(&self.dfa, value).into_iter())
+    }
+}
+
+/// Declares a `String` newtype whose `Arbitrary` values match the pattern.
+#[macro_export]
+macro_rules! regex_arbitrary {
+    ($vis:vis struct $name:ident = $pattern:expr) => {
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        $vis struct $name(pub String);
+
+        impl $name {
+            fn generator() -> &'static $crate::arbitrary::RegexArbitrary {
+                static GENERATOR: ::std::sync::OnceLock<$crate::arbitrary::RegexArbitrary> =
+                    ::std::sync::OnceLock::new();
+                GENERATOR.get_or_init(|| {
+                    $crate::arbitrary::RegexArbitrary::new($pattern)
+                        .expect(concat!("invalid pattern for ", stringify!($name)))
+                })
+            }
+        }
+
+        impl $crate::arbitrary::quickcheck::Arbitrary for $name {
+            fn arbitrary(g: &mut $crate::arbitrary::quickcheck::Gen) -> Self {
+                Self(Self::generator().arbitrary(g))
+            }
+
+            fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+                Box::new(Self::generator().shrink(&self.0).map(Self))
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use quickcheck::Arbitrary;
+    use quickcheck::QuickCheck;
+    use quickcheck::TestResult;
+
+    crate::regex_arbitrary!(struct Plate = "[A-Z]{2}[0-9]{3,4}");
+
+    #[test]
+    fn test_values_match() {
+        fn property(plate: Plate) -> bool {
+            crate::dfa::Dfa::compile("[A-Z]{2}[0-9]{3,4}")
+                .unwrap()
+                .is_match(&plate.0)
+        }
+
+        QuickCheck::new().quickcheck(property as fn(Plate) -> bool);
+    }
+
+    #[test]
+    fn test_shrinking_keeps_matching() {
+        let shrunk: Vec<Plate> = Plate("ZK9481".to_string()).shrink().collect();
+
+        assert_eq!(shrunk[0], Plate("AA000".to_string()));
+        assert!(
+            shrunk
+                .iter()
+                .all(|plate| plate.0.as_str() < "ZK9481" || plate.0.len() < 6)
+        );
+
+        fn fails_on_nines(plate: Plate) -> TestResult {
+            TestResult::from_bool(!plate.0.contains('9'))
+        }
+        let result = std::panic::catch_unwind(|| {
+            QuickCheck::new()
+                .tests(500)
+                .quickcheck(fails_on_nines as fn(Plate) -> TestResult)
+        });
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,374 @@
+use rand::Rng;
+
+use crate::charset::CharSet;
+use crate::common::TsegerError;
+use crate::dfa::Dfa;
+use crate::dialect::{self, ClassAtom, Scanner};
+use crate::generate::Enumerator;
+use crate::generate::Sampler;
+use crate::parser::RegexAST;
+
+/*
+* Byte-level patterns, the alphabet is 0x00-0xFF instead of Unicode scalars:
+*
+* - \x7F \x00       exactly two hex digits, one byte
+* - [\x00-\x1F]     byte classes, [^...] negates within 0x00-0xFF
+* - .               any byte, including \n
+* - \n \r \t \0     control bytes, \d \w \s keep their ASCII meaning
+* - é               non-ASCII literals stand for their UTF-8 bytes
+*
+* Bytes are carried through the regular machinery as scalars U+0000-U+00FF,
+* so the automata, samplers and enumerators are the same as for text.
+*/
+
+type BytesResult<T> = Result<T, TsegerError>;
+
+/// Compiled byte pattern, e.g. `\x89PNG\r\n\x1A\n.{4}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteRegex {
+    dfa: Dfa,
+}
+
+impl ByteRegex {
+    pub fn compile(pattern: &str) -> BytesResult<Self> {
+        Ok(Self {
+            dfa: Dfa::from_ast(&parse(pattern)?)?,
+        })
+    }
+
+    pub fn dfa(&self) -> &Dfa {
+        &self.dfa
+    }
+
+    pub fn is_match(&self, input: &[u8]) -> bool {
+        self.dfa.is_match(&to_scalars(input))
+    }
+
+    pub fn sampler(&self, max_len: usize) -> BytesResult<ByteSampler> {
+        Ok(ByteSampler {
+            sampler: Sampler::new(&self.dfa, max_len)?,
+        })
+    }
+
+    /// Every match up to `max_len` bytes, shortest first, then by byte value.
+    pub fn enumerate(&self, max_len: usize) -> impl Iterator<Item = Vec<u8>> + use<> {
+        Enumerator::new(&self.dfa, max_len).map(|s| to_bytes(&s))
+    }
+}
+
+pub struct ByteSampler {
+    sampler: Sampler,
+}
+
+impl ByteSampler {
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<u8> {
+        to_bytes(&self.sampler.sample(rng))
+    }
+
+    pub fn sample_len<R: Rng + ?Sized>(&self, len: usize, rng: &mut R) -> Option<Vec<u8>> {
+        self.sampler.sample_len(len, rng).map(|s| to_bytes(&s))
+    }
+}
+
+/// Bytes of a string over U+0000-U+00FF, e.g. a match of a byte pattern.
+pub fn to_bytes(scalars: &str) -> Vec<u8> {
+    scalars
+        .chars()
+        .map(|c| u8::try_from(c).expect("byte patterns only produce U+0000-U+00FF"))
+        .collect()
+}
+
+pub fn to_scalars(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+pub fn parse(pattern: &str) -> BytesResult<RegexAST> {
+    let mut parser = ByteParser {
+        chars: pattern.chars().collect(),
+        pos: 0,
+    };
+
+    let ast = parser.parse_alteration()?;
+    if let Some(c) = parser.peek() {
+        return Err(parser.error(format!("Unbalanced '{}'", c), parser.pos));
+    }
+
+    Ok(ast)
+}
+
+/// Bytes as scalars, U+0000-U+00FF.
+pub fn alphabet() -> CharSet {
+    CharSet::range('\0', '\u{FF}')
+}
+
+fn set_node(set: &CharSet) -> RegexAST {
+    RegexAST::BracketExpression {
+        negated: false,
+        items: dialect::bracket_items(set),
+    }
+}
+
+struct ByteParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl ByteParser {
+    fn expect(&mut self, expected: char) -> BytesResult<()> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(format!("Expected '{}'", expected), self.pos)),
+        }
+    }
+
+    fn parse_alteration(&mut self) -> BytesResult<RegexAST> {
+        let mut branches = vec![self.parse_concatenation()?];
+
+        while self.peek() == Some('|') {
+            self.next();
+            branches.push(self.parse_concatenation()?);
+        }
+
+        if branches.len() == 1 {
+            Ok(branches.remove(0))
+        } else {
+            Ok(RegexAST::Alteration(branches))
+        }
+    }
+
+    fn parse_concatenation(&mut self) -> BytesResult<RegexAST> {
+        let mut nodes = Vec::new();
+
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifier(atom)?);
+        }
+
+        match nodes.len() {
+            1 => Ok(nodes.remove(0)),
+            _ => Ok(RegexAST::Concat(nodes)),
+        }
+    }
+
+    fn parse_quantifier(&mut self, atom: RegexAST) -> BytesResult<RegexAST> {
+        let node = match self.peek() {
+            Some('*') => RegexAST::Star(Box::new(atom)),
+            Some('+') => RegexAST::Plus(Box::new(atom)),
+            Some('?') => RegexAST::Question(Box::new(atom)),
+            Some('{') => {
+                let (min, max) = self.parse_repetition_bounds()?;
+                return self.check_single_quantifier(RegexAST::Repetition {
+                    node: Box::new(atom),
+                    min,
+                    max,
+                });
+            }
+            _ => return Ok(atom),
+        };
+
+        self.next();
+        self.check_single_quantifier(node)
+    }
+
+    fn check_single_quantifier(&self, node: RegexAST) -> BytesResult<RegexAST> {
+        match self.peek() {
+            Some('*') | Some('+') | Some('?') | Some('{') => {
+                Err(self.error("Nested quantifier without a group".to_string(), self.pos))
+            }
+            _ => Ok(node),
+        }
+    }
+
+    fn parse_atom(&mut self) -> BytesResult<RegexAST> {
+        let start = self.pos;
+
+        match self.next() {
+            None => Err(self.error("Unexpected end of pattern".to_string(), start)),
+            Some('(') => {
+                let node = self.parse_alteration()?;
+                self.expect(')')?;
+                Ok(RegexAST::Group(Box::new(node)))
+            }
+            Some('[') => Ok(set_node(&self.parse_class()?)),
+            Some('.') => Ok(set_node(&alphabet())),
+            Some('\\') => Ok(set_node(&self.parse_escape()?)),
+            Some('*') | Some('+') | Some('?') | Some('{') => {
+                Err(self.error("Quantifier without a target".to_string(), start))
+            }
+            Some(c) if c.is_ascii() => Ok(RegexAST::Literal(c)),
+            Some(c) => {
+                let mut utf8 = [0u8; 4];
+                Ok(RegexAST::Group(Box::new(RegexAST::Concat(
+                    c.encode_utf8(&mut utf8)
+                        .bytes()
+                        .map(|b| RegexAST::Literal(char::from(b)))
+                        .collect(),
+                ))))
+            }
+        }
+    }
+
+    // Escape after the backslash, as a set of bytes
+    fn parse_escape(&mut self) -> BytesResult<CharSet> {
+        let start = self.pos - 1;
+
+        let byte = match self.next() {
+            None => return Err(self.error("Unescaped slash at the end".to_string(), start)),
+            Some('x') => {
+                let digits: String = (0..2).filter_map(|_| self.next()).collect();
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 => byte,
+                    _ => {
+                        return Err(
+                            self.error("Expected two hex digits after \\x".to_string(), start)
+                        );
+                    }
+                }
+            }
+            Some('n') => b'\n',
+            Some('r') => b'\r',
+            Some('t') => b'\t',
+            Some('0') => 0,
+            Some('d') => return Ok(CharSet::named("digits").unwrap()),
+            Some('w') => {
+                return Ok(CharSet::named("alphanumeric_ascii")
+                    .unwrap()
+                    .union(&CharSet::single('_')));
+            }
+            Some('s') => return Ok(CharSet::from_ranges([(' ', ' '), ('\t', '\r')])),
+            Some(c) if c.is_ascii_punctuation() => c as u8,
+            Some(c) => {
+                return Err(self.error(format!("Unknown escape sequence '\\{}'", c), start));
+            }
+        };
+
+        Ok(CharSet::single(char::from(byte)))
+    }
+
+    fn parse_class(&mut self) -> BytesResult<CharSet> {
+        let start = self.pos - 1;
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.next();
+        }
+
+        let mut set = CharSet::empty();
+        let mut first = true;
+
+        loop {
+            let item_start = self.pos;
+
+            match self.peek() {
+                None => return Err(self.error("Missing ']'".to_string(), start)),
+                Some(']') if !first => {
+                    self.next();
+                    break;
+                }
+                _ => (),
+            }
+            first = false;
+
+            set = set.union(&self.parse_class_item(item_start)?);
+        }
+
+        Ok(if negated {
+            alphabet().difference(&set)
+        } else {
+            set
+        })
+    }
+}
+
+impl Scanner for ByteParser {
+    fn chars(&self) -> &[char] {
+        &self.chars
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn seek(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    fn error(&self, msg: String, at: usize) -> TsegerError {
+        TsegerError::ParserError(format!("Byte pattern: {} at {}", msg, at))
+    }
+
+    fn parse_class_atom(&mut self) -> BytesResult<ClassAtom> {
+        let at = self.pos;
+
+        match self.next() {
+            None => Err(self.error("Missing ']'".to_string(), at)),
+            Some('\\') => {
+                let set = self.parse_escape()?;
+                Ok(single(&set).map_or(ClassAtom::Set(set), ClassAtom::Char))
+            }
+            Some(c) if c.is_ascii() => Ok(ClassAtom::Char(c)),
+            Some(c) => Err(self.error(format!("Non-ASCII '{}' in a byte class, use \\xNN", c), at)),
+        }
+    }
+}
+
+fn single(set: &CharSet) -> Option<char> {
+    match set.ranges() {
+        [(from, to)] if from == to => Some(*from),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+
+    #[test]
+    fn test_binary_payloads() {
+        let regex = ByteRegex::compile(r"\x89PNG\r\n\x1A\n[\x00-\x0F].{0,8}").unwrap();
+        let sampler = regex.sampler(32).unwrap();
+        let mut rng = StdRng::seed_from_u64(5);
+
+        for _ in 0..100 {
+            let payload = sampler.sample(&mut rng);
+            assert!(payload.starts_with(b"\x89PNG\r\n\x1a\n"));
+            assert!(payload[8] < 0x10);
+            assert!(payload.len() <= 17);
+            assert!(regex.is_match(&payload));
+        }
+    }
+
+    #[test]
+    fn test_byte_classes() {
+        let regex = ByteRegex::compile(r"[^\x00-\xFE]\d|\xC3\xA9|é-").unwrap();
+
+        assert!(regex.is_match(b"\xFF7"));
+        assert!(regex.is_match("é".as_bytes()));
+        assert!(regex.is_match("é-".as_bytes()));
+        assert!(!regex.is_match(b"\xFE7"));
+
+        let all: Vec<Vec<u8>> = ByteRegex::compile("[\\x00-\\x02]")
+            .unwrap()
+            .enumerate(4)
+            .collect();
+        assert_eq!(all, vec![vec![0], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn test_byte_syntax_errors() {
+        assert!(ByteRegex::compile(r"\x1").is_err());
+        assert!(ByteRegex::compile(r"\xZZ").is_err());
+        assert!(ByteRegex::compile("[é]").is_err());
+        assert!(ByteRegex::compile(r"[\d-z]").is_err());
+        assert!(ByteRegex::compile("a{3,1}").is_err());
+        assert!(ByteRegex::compile("a{}").is_err());
+        assert!(ByteRegex::compile("a{,}").is_err());
+        assert!(ByteRegex::compile("a{2,x}").is_err());
+        assert!(ByteRegex::compile("(ab").is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::common::TsegerError;
+use crate::dfa;
+use crate::dfa::Dfa;
+
+/*
+* Content-addressed cache of compiled automata:
+*
+* - the key is SHA-256 over the tseger version, the serialization format
+*   version, the pattern syntax and the pattern text, so upgrades never read
+*   stale entries
+* - entries are Dfa::to_bytes() files named <key>.dfa, written to a temporary
+*   file first and renamed, so concurrent runs never see half-written entries
+* - unreadable or corrupt entries count as misses and get rewritten
+*
+* Caching is best-effort: compile() only fails if compiling fails, storing an
+* entry can't break a run, e.g. in CI with a read-only cache directory.
+*/
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone)]
+pub struct DfaCache {
+    dir: PathBuf,
+}
+
+impl DfaCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// $TSEGER_CACHE_DIR, $XDG_CACHE_HOME/tseger or ~/.cache/tseger.
+    pub fn default_dir() -> Option<PathBuf> {
+        if let Some(dir) = env::var_os("TSEGER_CACHE_DIR") {
+            return Some(PathBuf::from(dir));
+        }
+        if let Some(dir) = env::var_os("XDG_CACHE_HOME") {
+            return Some(Path::new(&dir).join("tseger"));
+        }
+        env::var_os("HOME").map(|home| Path::new(&home).join(".cache").join("tseger"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Key of a pattern, `syntax` tells apart equal texts read differently (e.g. "bytes").
+    pub fn key(syntax: &str, pattern: &str) -> String {
+        let mut hasher = Sha256::new();
+        for part in [
+            VERSION.as_bytes(),
+            &dfa::FORMAT_VERSION.to_le_bytes(),
+            syntax.as_bytes(),
+            pattern.as_bytes(),
+        ] {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Compiles a tseger pattern, or loads it if it was compiled before.
+    pub fn compile(&self, pattern: &str) -> Result<Dfa, TsegerError> {
+        self.get_or_compile("tseger", pattern, || Dfa::compile(pattern))
+    }
+
+    pub fn get_or_compile(
+        &self,
+        syntax: &str,
+        pattern: &str,
+        compile: impl FnOnce() -> Result<Dfa, TsegerError>,
+    ) -> Result<Dfa, TsegerError> {
+        let key = Self::key(syntax, pattern);
+
+        if let Ok(Some(dfa)) = self.load(&key) {
+            return Ok(dfa);
+        }
+
+        let dfa = compile()?;
+        let _ = self.store(&key, &dfa);
+        Ok(dfa)
+    }
+
+    /// Entry for a key, None on a miss.
+    pub fn load(&self, key: &str) -> Result<Option<Dfa>, TsegerError> {
+        match fs::read(self.path(key)) {
+            Ok(bytes) => Dfa::from_bytes(&bytes).map(Some),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(cache_error(&self.path(key), e)),
+        }
+    }
+
+    pub fn store(&self, key: &str, dfa: &Dfa) -> Result<(), TsegerError> {
+        fs::create_dir_all(&self.dir).map_err(|e| cache_error(&self.dir, e))?;
+
+        let path = self.path(key);
+        let temporary = self.dir.join(format!("{}.{}.tmp", key, process::id()));
+
+        fs::write(&temporary, dfa.to_bytes()).map_err(|e| cache_error(&temporary, e))?;
+        fs::rename(&temporary, &path).map_err(|e| {
+            let _ = fs::remove_file(&temporary);
+            cache_error(&path, e)
+        })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.dfa", key))
+    }
+}
+
+fn cache_error(path: &Path, e: std::io::Error) -> TsegerError {
+    TsegerError::CacheError(format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("tseger-cache-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_entries_are_reused() {
+        let dir = temporary_dir("reuse");
+        let cache = DfaCache::new(&dir);
+
+        let first = cache.compile("[a-z]{3}-\\d+").unwrap();
+        let key = DfaCache::key("tseger", "[a-z]{3}-\\d+");
+        assert!(dir.join(format!("{}.dfa", key)).exists());
+
+        // A hit never calls the compiler
+        let cached = cache
+            .get_or_compile("tseger", "[a-z]{3}-\\d+", || panic!("compiled twice"))
+            .unwrap();
+        assert_eq!(cached, first);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_keys_and_corrupt_entries() {
+        assert_ne!(DfaCache::key("tseger", "a"), DfaCache::key("bytes", "a"));
+        assert_ne!(DfaCache::key("tseger", "ab"), DfaCache::key("tseger", "a"));
+        assert_eq!(DfaCache::key("tseger", "a").len(), 64);
+
+        let dir = temporary_dir("corrupt");
+        let cache = DfaCache::new(&dir);
+        let key = DfaCache::key("tseger", "x+");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("{}.dfa", key)), b"garbage").unwrap();
+
+        assert!(cache.load(&key).is_err());
+        assert!(cache.compile("x+").unwrap().is_match("xxx"));
+        assert!(cache.load(&key).unwrap().is_some());
+        assert!(cache.compile("(").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,281 @@
+/*
+* Character sets used on automaton transitions:
+*
+* - sets are sorted, disjoint inclusive ranges of Unicode scalar values
+* - surrogates (0xD800..=0xDFFF) are never members, ranges are split around them
+* - \p{name} classes resolve through CharSet::named
+*/
+
+const SURROGATE_START: u32 = 0xD800;
+const SURROGATE_END: u32 = 0xDFFF;
+const MAX_SCALAR: u32 = 0x10FFFF;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, PartialOrd, Ord)]
+pub struct CharSet {
+    ranges: Vec<(char, char)>,
+}
+
+impl CharSet {
+    pub fn empty() -> Self {
+        Self { ranges: Vec::new() }
+    }
+
+    pub fn any() -> Self {
+        Self::from_ranges([('\0', char::MAX)])
+    }
+
+    pub fn single(c: char) -> Self {
+        Self {
+            ranges: vec![(c, c)],
+        }
+    }
+
+    pub fn range(from: char, to: char) -> Self {
+        Self::from_ranges([(from, to)])
+    }
+
+    pub fn from_ranges(ranges: impl IntoIterator<Item = (char, char)>) -> Self {
+        let mut scalars: Vec<(u32, u32)> = Vec::new();
+
+        for (from, to) in ranges {
+            let (from, to) = (from as u32, to as u32);
+            if from > to {
+                continue;
+            }
+            // Split around the surrogate block
+            if from < SURROGATE_START {
+                scalars.push((from, to.min(SURROGATE_START - 1)));
+            }
+            if to > SURROGATE_END {
+                scalars.push((from.max(SURROGATE_END + 1), to));
+            }
+        }
+
+        Self::from_scalars(scalars)
+    }
+
+    // Expects ranges that contain no surrogates
+    fn from_scalars(mut scalars: Vec<(u32, u32)>) -> Self {
+        scalars.sort_unstable();
+
+        let mut merged: Vec<(u32, u32)> = Vec::with_capacity(scalars.len());
+        for (from, to) in scalars {
+            match merged.last_mut() {
+                Some(last) if from <= last.1.saturating_add(1) => last.1 = last.1.max(to),
+                _ => merged.push((from, to)),
+            }
+        }
+
+        Self {
+            ranges: merged
+                .into_iter()
+                .map(|(from, to)| (to_char(from), to_char(to)))
+                .collect(),
+        }
+    }
+
+    /// Built-in classes available as \p{name}.
+    pub fn named(name: &str) -> Option<Self> {
+        let ranges: &[(char, char)] = match name {
+            "digits" => &[('0', '9')],
+            "hex_digits" => &[('0', '9'), ('A', 'F'), ('a', 'f')],
+            "lowercase_ascii" => &[('a', 'z')],
+            "uppercase_ascii" => &[('A', 'Z')],
+            "letters_ascii" => &[('A', 'Z'), ('a', 'z')],
+            "alphanumeric_ascii" => &[('0', '9'), ('A', 'Z'), ('a', 'z')],
+            "punctuation_ascii" => &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+            "printable_ascii" => &[(' ', '~')],
+            "whitespace" => &[('\t', '\r'), (' ', ' ')],
+            "cyrillic" => &[('\u{400}', '\u{4FF}')],
+            "greek" => &[('\u{370}', '\u{3FF}')],
+            "emojis" => &[
+                ('\u{1F300}', '\u{1F5FF}'),
+                ('\u{1F600}', '\u{1F64F}'),
+                ('\u{1F680}', '\u{1F6FF}'),
+                ('\u{1F900}', '\u{1F9FF}'),
+            ],
+            _ => return None,
+        };
+
+        Some(Self::from_ranges(ranges.iter().copied()))
+    }
+
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn len(&self) -> u32 {
+        self.ranges
+            .iter()
+            .map(|&(from, to)| to as u32 - from as u32 + 1)
+            .sum()
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges
+            .binary_search_by(|&(from, to)| {
+                if to < c {
+                    std::cmp::Ordering::Less
+                } else if from > c {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    pub fn first(&self) -> Option<char> {
+        self.ranges.first().map(|&(from, _)| from)
+    }
+
+    /// Character at `index` in ascending order.
+    pub fn nth(&self, mut index: u32) -> Option<char> {
+        for &(from, to) in &self.ranges {
+            let size = to as u32 - from as u32 + 1;
+            if index < size {
+                return char::from_u32(from as u32 + index);
+            }
+            index -= size;
+        }
+
+        None
+    }
+
+    /// Number of members smaller than `c`.
+    pub fn count_below(&self, c: char) -> u32 {
+        let c = c as u32;
+
+        self.scalars()
+            .take_while(|&(from, _)| from < c)
+            .map(|(from, to)| to.min(c - 1) - from + 1)
+            .sum()
+    }
+
+    pub fn union(&self, other: &CharSet) -> CharSet {
+        Self::from_scalars(self.scalars().chain(other.scalars()).collect())
+    }
+
+    pub fn complement(&self) -> CharSet {
+        let mut gaps = Vec::new();
+        let mut next = 0u32;
+
+        for (from, to) in self.scalars() {
+            if from > next {
+                gaps.push((next, from - 1));
+            }
+            next = to + 1;
+        }
+        if next <= MAX_SCALAR {
+            gaps.push((next, MAX_SCALAR));
+        }
+
+        Self::from_ranges(gaps.into_iter().map(|(from, to)| {
+            (
+                to_char(skip_surrogates_up(from)),
+                to_char(skip_surrogates_down(to)),
+            )
+        }))
+    }
+
+    pub fn intersection(&self, other: &CharSet) -> CharSet {
+        let mut result = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a_from, a_to) = self.ranges[i];
+            let (b_from, b_to) = other.ranges[j];
+
+            let from = a_from.max(b_from);
+            let to = a_to.min(b_to);
+            if from <= to {
+                result.push((from as u32, to as u32));
+            }
+
+            if a_to < b_to {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        Self::from_scalars(result)
+    }
+
+    pub fn difference(&self, other: &CharSet) -> CharSet {
+        self.intersection(&other.complement())
+    }
+
+    fn scalars(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.ranges
+            .iter()
+            .map(|&(from, to)| (from as u32, to as u32))
+    }
+}
+
+fn skip_surrogates_up(c: u32) -> u32 {
+    if (SURROGATE_START..=SURROGATE_END).contains(&c) {
+        SURROGATE_END + 1
+    } else {
+        c
+    }
+}
+
+fn skip_surrogates_down(c: u32) -> u32 {
+    if (SURROGATE_START..=SURROGATE_END).contains(&c) {
+        SURROGATE_START - 1
+    } else {
+        c
+    }
+}
+
+fn to_char(c: u32) -> char {
+    char::from_u32(c).expect("surrogates are filtered out before conversion")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalization() {
+        let set = CharSet::from_ranges([('d', 'f'), ('a', 'c'), ('x', 'z'), ('y', 'y')]);
+
+        assert_eq!(set.ranges(), &[('a', 'f'), ('x', 'z')]);
+        assert_eq!(set.len(), 9);
+        assert_eq!(set.nth(6), Some('x'));
+        assert_eq!(set.nth(9), None);
+        assert_eq!(set.count_below('y'), 7);
+        assert_eq!(set.count_below('a'), 0);
+        assert!(set.contains('e') && !set.contains('g'));
+    }
+
+    #[test]
+    fn test_surrogates_are_never_members() {
+        let any = CharSet::any();
+
+        assert_eq!(any.ranges(), &[('\0', '\u{D7FF}'), ('\u{E000}', char::MAX)]);
+        assert_eq!(any.len(), 0x110000 - 0x800);
+        assert!(any.complement().is_empty());
+        assert_eq!(CharSet::empty().complement(), any);
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let lower = CharSet::range('a', 'z');
+        let vowels = CharSet::from_ranges("aeiou".chars().map(|c| (c, c)));
+
+        assert_eq!(lower.intersection(&vowels), vowels);
+        assert_eq!(lower.difference(&vowels).len(), 21);
+        assert_eq!(
+            vowels.union(&CharSet::range('b', 'd')).ranges(),
+            &[('a', 'e'), ('i', 'i'), ('o', 'o'), ('u', 'u')]
+        );
+        assert!(!lower.complement().contains('q'));
+        assert!(lower.complement().contains('Q'));
+    }
+}
@@ -8,4 +8,20 @@ pub enum TsegerError {
     ParserError(String),
     #[error("TSEGER | DFA error: {0}")]
     DFAError(String),
+    #[error("TSEGER | Dialect error: {0}")]
+    DialectError(String),
+    #[error("TSEGER | Inference error: {0}")]
+    InferenceError(String),
+    #[error("TSEGER | Rank error: {0}")]
+    RankError(String),
+    #[error("TSEGER | Generator error: {0}")]
+    GeneratorError(String),
+    #[error("TSEGER | Grammar error: {0}")]
+    GrammarError(String),
+    #[error("TSEGER | Definition error: {0}")]
+    DefinitionError(String),
+    #[error("TSEGER | Cache error: {0}")]
+    CacheError(String),
+    #[error("TSEGER | Wordlist error: {0}")]
+    WordlistError(String),
 }
@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use rand::Rng;
+
+use crate::charset::CharSet;
+use crate::common::TsegerError;
+use crate::dfa::Dfa;
+use crate::dfa::DfaState;
+use crate::nfa::StateId;
+use crate::rank::Ranker;
+
+/*
+* Wordlists as minimal acyclic automata (DAWGs):
+*
+* - DawgBuilder takes words in sorted order and keeps the automaton minimal
+*   while it grows (Daciuk et al. incremental construction), so memory stays
+*   proportional to the DAWG, not to the list
+* - the result is an ordinary Dfa, so it intersects with compiled patterns
+* - Wordlist counts, indexes and samples uniformly over the whole list
+*/
+
+// Node of the automaton under construction, edges are sorted by char
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+struct Node {
+    last: bool,
+    edges: Vec<(char, usize)>,
+}
+
+#[derive(Debug)]
+pub struct DawgBuilder {
+    nodes: Vec<Node>,
+    // Minimal nodes by content, nodes on the path of the last word aren't in it yet
+    register: HashMap<Node, usize>,
+    previous: Option<String>,
+}
+
+impl Default for DawgBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DawgBuilder {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![Node::default()],
+            register: HashMap::new(),
+            previous: None,
+        }
+    }
+
+    /// Adds a word, words must come in ascending order, repeats are ignored.
+    pub fn insert(&mut self, word: &str) -> Result<(), TsegerError> {
+        if let Some(previous) = &self.previous {
+            if word == previous {
+                return Ok(());
+            }
+            if word < previous.as_str() {
+                return Err(TsegerError::WordlistError(format!(
+                    "'{}' comes after '{}', words must be sorted",
+                    word, previous
+                )));
+            }
+        }
+
+        let mut node = 0;
+        let mut chars = word.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            match self.nodes[node].edges.last() {
+                Some(&(last, child)) if last == c => {
+                    node = child;
+                    chars.next();
+                }
+                _ => break,
+            }
+        }
+
+        // Everything past the common prefix belongs to finished words now
+        self.register_last_child(node);
+
+        for c in chars {
+            self.nodes.push(Node::default());
+            let child = self.nodes.len() - 1;
+            self.nodes[node].edges.push((c, child));
+            node = child;
+        }
+        self.nodes[node].last = true;
+        self.previous = Some(word.to_string());
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Dfa {
+        self.register_last_child(0);
+
+        // Renumber the live nodes in BFS order, replaced ones are unreachable
+        let mut ids: HashMap<usize, StateId> = HashMap::from([(0, 0)]);
+        let mut order = vec![0];
+        let mut i = 0;
+        while i < order.len() {
+            for &(_, child) in &self.nodes[order[i]].edges {
+                if let Entry::Vacant(entry) = ids.entry(child) {
+                    entry.insert(order.len());
+                    order.push(child);
+                }
+            }
+            i += 1;
+        }
+
+        let states = order
+            .iter()
+            .map(|&node| {
+                let mut by_target: BTreeMap<StateId, Vec<(char, char)>> = BTreeMap::new();
+                for &(c, child) in &self.nodes[node].edges {
+                    by_target.entry(ids[&child]).or_default().push((c, c));
+                }
+
+                let mut transitions: Vec<(CharSet, StateId)> = by_target
+                    .into_iter()
+                    .map(|(target, chars)| (CharSet::from_ranges(chars), target))
+                    .collect();
+                transitions.sort_by_key(|(set, _)| set.first());
+
+                DfaState {
+                    accepting: self.nodes[node].last,
+                    transitions,
+                }
+            })
+            .collect();
+
+        Dfa::from_states(states, 0)
+    }
+
+    fn register_last_child(&mut self, node: usize) {
+        let Some(&(c, child)) = self.nodes[node].edges.last() else {
+            return;
+        };
+
+        self.register_last_child(child);
+
+        match self.register.get(&self.nodes[child]) {
+            Some(&equivalent) => {
+                *self.nodes[node].edges.last_mut().unwrap() = (c, equivalent);
+                // Freshly added nodes sit at the end, reuse their slots
+                if child == self.nodes.len() - 1 {
+                    self.nodes.pop();
+                }
+            }
+            None => {
+                self.register.insert(self.nodes[child].clone(), child);
+            }
+        }
+    }
+}
+
+/// Finite language with counting, indexing and uniform sampling.
+pub struct Wordlist {
+    ranker: Ranker,
+    // (length, words of that length), only lengths with words
+    lengths: Vec<(usize, u128)>,
+    total: u128,
+}
+
+impl Wordlist {
+    /// Words in any order, they are sorted and deduplicated first.
+    pub fn from_words<S: AsRef<str>>(words: &[S]) -> Result<Self, TsegerError> {
+        let mut sorted: Vec<&str> = words.iter().map(AsRef::as_ref).collect();
+        sorted.sort_unstable();
+        Self::from_sorted(sorted)
+    }
+
+    /// Words in ascending order, e.g. a sorted dictionary file streamed line by line.
+    pub fn from_sorted<S: AsRef<str>>(
+        words: impl IntoIterator<Item = S>,
+    ) -> Result<Self, TsegerError> {
+        let mut builder = DawgBuilder::new();
+        for word in words {
+            builder.insert(word.as_ref())?;
+        }
+        Self::from_dfa(builder.finish())
+    }
+
+    pub fn from_dfa(dfa: Dfa) -> Result<Self, TsegerError> {
+        if !dfa.is_finite() {
+            return Err(TsegerError::WordlistError(
+                "Automaton accepts infinitely many words".to_string(),
+            ));
+        }
+
+        let longest = dfa.longest().unwrap_or(0);
+        let ranker = Ranker::new(dfa);
+
+        let mut lengths = Vec::new();
+        let mut total = 0u128;
+        for len in 0..=longest {
+            let count = ranker.count(len)?;
+            if count > 0 {
+                lengths.push((len, count));
+                total = total.checked_add(count).ok_or_else(|| {
+                    TsegerError::WordlistError("More than 2^128 words".to_string())
+                })?;
+            }
+        }
+
+        Ok(Self {
+            ranker,
+            lengths,
+            total,
+        })
+    }
+
+    pub fn dfa(&self) -> &Dfa {
+        self.ranker.dfa()
+    }
+
+    pub fn count(&self) -> u128 {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Length of the longest word, 0 for an empty list.
+    pub fn longest(&self) -> usize {
+        self.lengths.last().map_or(0, |&(len, _)| len)
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.dfa().is_match(word)
+    }
+
+    /// Words that also match the pattern's automaton.
+    pub fn filter(&self, dfa: &Dfa) -> Result<Self, TsegerError> {
+        Self::from_dfa(self.dfa().intersect(dfa)?)
+    }
+
+    /// Word at `index` in shortlex order (by length, then by scalar value).
+    pub fn nth(&self, mut index: u128) -> Option<String> {
+        for &(len, count) in &self.lengths {
+            if index < count {
+                return self.ranker.unrank(len, index).ok();
+            }
+            index -= count;
+        }
+        None
+    }
+
+    /// Every word is equally likely, whatever its length.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        self.nth(rng.random_range(0..self.total))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+
+    const WORDS: [&str; 10] = [
+        "apple", "apply", "ate", "bake", "baked", "bakes", "cake", "caked", "cakes", "tap",
+    ];
+
+    #[test]
+    fn test_dawg_is_minimal() {
+        let wordlist = Wordlist::from_words(&WORDS).unwrap();
+
+        assert_eq!(wordlist.count(), 10);
+        assert!(WORDS.iter().all(|w| wordlist.contains(w)));
+        assert!(!wordlist.contains("app"));
+        assert!(!wordlist.contains("cakess"));
+
+        // Same language through the regex pipeline gives the same minimal automaton
+        let regex = Dfa::compile(&WORDS.join("|")).unwrap();
+        assert_eq!(wordlist.dfa().states().len(), regex.states().len());
+        assert_eq!(wordlist.dfa().intersect(&regex).unwrap(), regex);
+    }
+
+    #[test]
+    fn test_filter_count_and_index() {
+        let wordlist = Wordlist::from_words(&WORDS).unwrap();
+        let five = wordlist.filter(&Dfa::compile("[a-z]{5}").unwrap()).unwrap();
+
+        assert_eq!(five.count(), 6);
+        let all: Vec<String> = (0..6).map(|i| five.nth(i).unwrap()).collect();
+        assert_eq!(all, ["apple", "apply", "baked", "bakes", "caked", "cakes"]);
+        assert_eq!(five.nth(6), None);
+
+        let none = wordlist.filter(&Dfa::compile("z+").unwrap()).unwrap();
+        assert!(none.is_empty());
+        assert_eq!(none.sample(&mut StdRng::seed_from_u64(0)), None);
+    }
+
+    #[test]
+    fn test_sampling_is_uniform_over_words() {
+        let wordlist = Wordlist::from_words(&WORDS).unwrap();
+        let mut rng = StdRng::seed_from_u64(2);
+
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for _ in 0..5000 {
+            *seen.entry(wordlist.sample(&mut rng).unwrap()).or_default() += 1;
+        }
+        assert_eq!(seen.len(), 10);
+        assert!(
+            seen.values().all(|&n| (350..650).contains(&n)),
+            "{:?}",
+            seen
+        );
+    }
+
+    #[test]
+    fn test_builder_needs_sorted_input() {
+        let mut builder = DawgBuilder::new();
+        builder.insert("b").unwrap();
+        builder.insert("b").unwrap();
+        assert!(builder.insert("a").is_err());
+
+        assert!(Wordlist::from_sorted(["", "a", "ab"]).unwrap().contains(""));
+        assert!(Wordlist::from_dfa(Dfa::compile("a+").unwrap()).is_err());
+    }
+}
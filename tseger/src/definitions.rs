@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use crate::common::TsegerError;
+use crate::dfa::Dfa;
+use crate::lexer::Lexer;
+use crate::lexer::Position;
+use crate::lexer::RegexAtom;
+use crate::parser::Parser;
+use crate::parser::RegexAST;
+
+/*
+* Named sub-patterns, expanded at compile time:
+*
+*   define octet = 25[0-5]|2[0-4]\d|1?\d?\d
+*   define ipv4  = #{octet}\.#{octet}\.#{octet}\.#{octet}
+*
+* - a #{name} that refers to a definition is replaced by its AST in a group
+* - other #{name}s are left alone, they are runtime variables
+* - definitions may refer to ones defined later, cycles are rejected as soon
+*   as the definition closing them is added
+*/
+
+type DefinitionResult<T> = Result<T, TsegerError>;
+
+#[derive(Debug, Clone)]
+struct Definition {
+    ast: RegexAST,
+    // Where the definition came from, e.g. `line 3`
+    origin: String,
+    // #{name} references in the pattern, spans include the '#'
+    references: Vec<(String, Position)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Definitions {
+    definitions: HashMap<String, Definition>,
+}
+
+impl Definitions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Definitions from `define name = pattern` lines, blank lines and `//` comments are skipped.
+    pub fn parse(source: &str) -> DefinitionResult<Self> {
+        let mut definitions = Self::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            let Some((name, pattern)) = line
+                .strip_prefix("define ")
+                .and_then(|rest| rest.split_once('='))
+            else {
+                return Err(TsegerError::DefinitionError(format!(
+                    "Expected `define name = pattern` on line {}",
+                    i + 1
+                )));
+            };
+
+            definitions.insert(name.trim(), pattern.trim(), format!("line {}", i + 1))?;
+        }
+
+        Ok(definitions)
+    }
+
+    pub fn define(&mut self, name: &str, pattern: &str) -> DefinitionResult<()> {
+        self.insert(name, pattern, format!("'{}'", name))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.definitions.contains_key(name)
+    }
+
+    /// Parses a pattern and inlines every definition it uses.
+    pub fn parse_pattern(&self, pattern: &str) -> DefinitionResult<RegexAST> {
+        self.expand(&Parser::new().parse(pattern)?)
+    }
+
+    pub fn compile(&self, pattern: &str) -> DefinitionResult<Dfa> {
+        Dfa::from_ast(&self.parse_pattern(pattern)?)
+    }
+
+    pub fn expand(&self, ast: &RegexAST) -> DefinitionResult<RegexAST> {
+        let boxed = |node: &RegexAST| self.expand(node).map(Box::new);
+        let all = |nodes: &[RegexAST]| {
+            nodes
+                .iter()
+                .map(|node| self.expand(node))
+                .collect::<DefinitionResult<Vec<RegexAST>>>()
+        };
+
+        Ok(match ast {
+            RegexAST::Variable(name) => match self.definitions.get(name) {
+                Some(definition) => RegexAST::Group(Box::new(self.expand(&definition.ast)?)),
+                None => ast.clone(),
+            },
+            RegexAST::Group(node) => RegexAST::Group(boxed(node)?),
+            RegexAST::Star(node) => RegexAST::Star(boxed(node)?),
+            RegexAST::Plus(node) => RegexAST::Plus(boxed(node)?),
+            RegexAST::Question(node) => RegexAST::Question(boxed(node)?),
+            RegexAST::Repetition { node, min, max } => RegexAST::Repetition {
+                node: boxed(node)?,
+                min: *min,
+                max: *max,
+            },
+            RegexAST::Concat(nodes) => RegexAST::Concat(all(nodes)?),
+            RegexAST::Alteration(nodes) => RegexAST::Alteration(all(nodes)?),
+            _ => ast.clone(),
+        })
+    }
+
+    fn insert(&mut self, name: &str, pattern: &str, origin: String) -> DefinitionResult<()> {
+        let valid_name = name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+        if name.is_empty() || !valid_name {
+            return Err(TsegerError::DefinitionError(format!(
+                "Invalid definition name '{}' ({})",
+                name, origin
+            )));
+        }
+        if self.definitions.contains_key(name) {
+            return Err(TsegerError::DefinitionError(format!(
+                "'{}' is already defined ({})",
+                name, origin
+            )));
+        }
+
+        let references = Lexer::new(pattern)
+            .lex()?
+            .into_iter()
+            .filter_map(|(atom, position)| match atom {
+                RegexAtom::Variable(name) => Some((
+                    name,
+                    Position {
+                        start: position.start - 1,
+                        end: position.end,
+                    },
+                )),
+                _ => None,
+            })
+            .collect();
+
+        let definition = Definition {
+            ast: Parser::new().parse(pattern)?,
+            origin,
+            references,
+        };
+        self.definitions.insert(name.to_string(), definition);
+
+        if let Some(cycle) = self.cycle_through(name) {
+            self.definitions.remove(name);
+            return Err(TsegerError::DefinitionError(format!(
+                "Recursive definition: {}",
+                cycle
+            )));
+        }
+
+        Ok(())
+    }
+
+    // Every cycle is closed by the last definition added, so only paths back to it matter
+    fn cycle_through(&self, name: &str) -> Option<String> {
+        let mut path: Vec<(&str, &str, Position)> = Vec::new();
+        let mut visited: Vec<&str> = Vec::new();
+
+        self.find_path(name, name, &mut path, &mut visited)
+            .then(|| {
+                path.iter()
+                    .map(|(from, to, span)| {
+                        format!(
+                            "#{{{}}} in '{}' at {}..{} ({})",
+                            to, from, span.start, span.end, self.definitions[*from].origin
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join(" -> ")
+            })
+    }
+
+    fn find_path<'a>(
+        &'a self,
+        from: &'a str,
+        target: &str,
+        path: &mut Vec<(&'a str, &'a str, Position)>,
+        visited: &mut Vec<&'a str>,
+    ) -> bool {
+        if visited.contains(&from) {
+            return false;
+        }
+        visited.push(from);
+
+        let Some(definition) = self.definitions.get(from) else {
+            return false;
+        };
+
+        for (to, span) in &definition.references {
+            path.push((from, to, *span));
+            if to == target || self.find_path(to, target, path, visited) {
+                return true;
+            }
+            path.pop();
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipv4_from_octets() {
+        let mut definitions = Definitions::new();
+        definitions
+            .define("octet", r"25[0-5]|2[0-4]\d|1?\d?\d")
+            .unwrap();
+
+        let dfa = definitions
+            .compile(r"#{octet}\.#{octet}\.#{octet}\.#{octet}")
+            .unwrap();
+
+        assert!(dfa.is_match("192.168.0.1"));
+        assert!(dfa.is_match("255.255.255.255"));
+        assert!(!dfa.is_match("256.1.1.1"));
+        assert!(!dfa.is_match("1.1.1"));
+    }
+
+    #[test]
+    fn test_definition_files_and_forward_references() {
+        let definitions = Definitions::parse(
+            "
+            // dates
+            define date  = #{year}-#{month}
+            define year  = (19|20)\\d\\d
+            define month = 0[1-9]|1[0-2]
+            ",
+        )
+        .unwrap();
+
+        let dfa = definitions.compile("#{date}").unwrap();
+        assert!(dfa.is_match("2024-02"));
+        assert!(!dfa.is_match("2024-13"));
+
+        // Unknown names stay runtime variables
+        assert_eq!(
+            definitions.parse_pattern("#{user}").unwrap(),
+            RegexAST::Variable("user".to_string())
+        );
+        assert!(Definitions::parse("octet = 1").is_err());
+    }
+
+    #[test]
+    fn test_recursion_is_reported_with_spans() {
+        let mut definitions = Definitions::new();
+        definitions.define("a", "x#{b}").unwrap();
+        definitions.define("b", "(#{c})?").unwrap();
+
+        let err = definitions.define("c", "y|#{a}").unwrap_err().to_string();
+        assert!(err.contains("#{a} in 'c' at 2..5"), "{}", err);
+        assert!(err.contains("#{b} in 'a' at 1..4"), "{}", err);
+        assert!(err.contains("#{c} in 'b' at 1..4"), "{}", err);
+
+        // The rejected definition isn't kept
+        assert!(!definitions.contains("c"));
+        assert!(definitions.define("self", "#{self}a").is_err());
+        assert!(definitions.define("a", "z").is_err());
+    }
+}
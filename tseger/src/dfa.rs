@@ -0,0 +1,792 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::collections::hash_map::Entry;
+
+use crate::charset::CharSet;
+use crate::common::TsegerError;
+use crate::nfa::Nfa;
+use crate::nfa::StateId;
+use crate::parser::Parser;
+use crate::parser::RegexAST;
+
+// Subset construction can blow up exponentially, this keeps it from eating all the memory
+const MAX_STATES: usize = 100_000;
+
+/*
+* Binary format, every integer is a little-endian u32:
+*
+*   "TSDFA" format_version start state_count
+*   state:      accepting transition_count transition*
+*   transition: target range_count (from to)*
+*
+* Ranges are written in canonical form (sorted, disjoint, no surrogates) and
+* from_bytes() rejects anything else, so equal automata give equal bytes.
+* The sets of one state's transitions can't overlap, a cache file that says
+* otherwise would load a nondeterministic automaton. The automaton has to be
+* trimmed and minimal already, is_finite() and longest() rely on it.
+*/
+const MAGIC: &[u8] = b"TSDFA";
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Minimal DFA without dead states. Missing transitions lead to rejection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dfa {
+    states: Vec<DfaState>,
+    start: StateId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DfaState {
+    pub accepting: bool,
+    // Disjoint sets, sorted by their first character
+    pub transitions: Vec<(CharSet, StateId)>,
+}
+
+impl Dfa {
+    // For builders that produce trimmed, minimal automata on their own
+    pub(crate) fn from_states(states: Vec<DfaState>, start: StateId) -> Self {
+        Self { states, start }
+    }
+
+    pub fn compile(pattern: &str) -> Result<Self, TsegerError> {
+        Self::from_ast(&Parser::new().parse(pattern)?)
+    }
+
+    pub fn from_ast(ast: &RegexAST) -> Result<Self, TsegerError> {
+        Self::from_nfa(&Nfa::from_ast(ast)?)
+    }
+
+    pub fn from_nfa(nfa: &Nfa) -> Result<Self, TsegerError> {
+        let start = nfa.epsilon_closure([nfa.start()]);
+
+        let mut states: Vec<DfaState> = Vec::new();
+        let mut subsets: Vec<BTreeSet<StateId>> = vec![start.clone()];
+        let mut index: HashMap<BTreeSet<StateId>, StateId> = HashMap::from([(start, 0)]);
+        let mut queue = VecDeque::from([0]);
+
+        while let Some(id) = queue.pop_front() {
+            let subset = subsets[id].clone();
+            let moves: Vec<&(CharSet, StateId)> = subset
+                .iter()
+                .flat_map(|&s| nfa.states()[s].transitions.iter())
+                .collect();
+
+            let mut state = DfaState {
+                accepting: subset.contains(&nfa.accept()),
+                transitions: Vec::new(),
+            };
+
+            for (set, target) in split_moves(&moves) {
+                let closure = nfa.epsilon_closure(target);
+                let next = match index.get(&closure) {
+                    Some(&next) => next,
+                    None => {
+                        if subsets.len() >= MAX_STATES {
+                            return Err(TsegerError::DFAError(format!(
+                                "Pattern needs more than {} DFA states",
+                                MAX_STATES
+                            )));
+                        }
+                        subsets.push(closure.clone());
+                        index.insert(closure, subsets.len() - 1);
+                        queue.push_back(subsets.len() - 1);
+                        subsets.len() - 1
+                    }
+                };
+                state.transitions.push((set, next));
+            }
+
+            states.push(state);
+        }
+
+        // States are pushed in discovery order, which matches their ids
+        Ok(Self { states, start: 0 }.trim().minimize())
+    }
+
+    /// Accepts a match of every part, one after the other.
+    pub fn concat(parts: &[Dfa]) -> Result<Self, TsegerError> {
+        Self::from_nfa(&Nfa::from_dfas(parts)?)
+    }
+
+    /// Accepts any string whose length in characters lies in `min..=max`.
+    pub fn length_range(min: usize, max: Option<usize>) -> Result<Self, TsegerError> {
+        let last = max.unwrap_or(min);
+        if last < min {
+            return Err(TsegerError::DFAError(format!(
+                "Invalid length range {}..={}",
+                min, last
+            )));
+        }
+        if last >= MAX_STATES {
+            return Err(TsegerError::DFAError(format!(
+                "Length bound {} needs more than {} DFA states",
+                last, MAX_STATES
+            )));
+        }
+
+        let mut states: Vec<DfaState> = (0..=last)
+            .map(|len| DfaState {
+                accepting: len >= min,
+                transitions: if len < last {
+                    vec![(CharSet::any(), len + 1)]
+                } else {
+                    Vec::new()
+                },
+            })
+            .collect();
+        if max.is_none() {
+            states[last].transitions.push((CharSet::any(), last));
+        }
+
+        Ok(Self { states, start: 0 }.trim().minimize())
+    }
+
+    /// Product automaton, accepts what both automata accept.
+    pub fn intersect(&self, other: &Dfa) -> Result<Self, TsegerError> {
+        let mut states: Vec<DfaState> = Vec::new();
+        let mut pairs = vec![(self.start, other.start)];
+        let mut index: HashMap<(StateId, StateId), StateId> =
+            HashMap::from([((self.start, other.start), 0)]);
+
+        while states.len() < pairs.len() {
+            let (left, right) = pairs[states.len()];
+            let mut state = DfaState {
+                accepting: self.states[left].accepting && other.states[right].accepting,
+                transitions: Vec::new(),
+            };
+
+            for (left_set, left_target) in &self.states[left].transitions {
+                for (right_set, right_target) in &other.states[right].transitions {
+                    let set = left_set.intersection(right_set);
+                    if set.is_empty() {
+                        continue;
+                    }
+
+                    let pair = (*left_target, *right_target);
+                    let next = match index.entry(pair) {
+                        Entry::Occupied(entry) => *entry.get(),
+                        Entry::Vacant(entry) => {
+                            if pairs.len() >= MAX_STATES {
+                                return Err(TsegerError::DFAError(format!(
+                                    "Intersection needs more than {} DFA states",
+                                    MAX_STATES
+                                )));
+                            }
+                            pairs.push(pair);
+                            *entry.insert(pairs.len() - 1)
+                        }
+                    };
+                    state.transitions.push((set, next));
+                }
+            }
+
+            state.transitions.sort_by_key(|(set, _)| set.first());
+            states.push(state);
+        }
+
+        Ok(Self { states, start: 0 }.trim().minimize())
+    }
+
+    /// Accepts exactly the strings this automaton rejects.
+    pub fn complement(&self) -> Self {
+        let sink = self.states.len();
+        let mut states: Vec<DfaState> = self
+            .states
+            .iter()
+            .map(|state| {
+                let covered = state
+                    .transitions
+                    .iter()
+                    .fold(CharSet::empty(), |covered, (set, _)| covered.union(set));
+                let mut transitions = state.transitions.clone();
+                let rest = covered.complement();
+                if !rest.is_empty() {
+                    transitions.push((rest, sink));
+                    transitions.sort_by_key(|(set, _)| set.first());
+                }
+
+                DfaState {
+                    accepting: !state.accepting,
+                    transitions,
+                }
+            })
+            .collect();
+        states.push(DfaState {
+            accepting: true,
+            transitions: vec![(CharSet::any(), sink)],
+        });
+
+        Self {
+            states,
+            start: self.start,
+        }
+        .trim()
+        .minimize()
+    }
+
+    /// Keeps the matches with `min..=max` characters, fails if there are none.
+    pub fn restrict_length(&self, min: usize, max: Option<usize>) -> Result<Self, TsegerError> {
+        let restricted = self.intersect(&Self::length_range(min, max)?)?;
+
+        if restricted.is_empty() {
+            let range = match max {
+                Some(max) if max == min => format!("exactly {}", min),
+                Some(max) => format!("between {} and {}", min, max),
+                None => format!("at least {}", min),
+            };
+            return Err(TsegerError::DFAError(format!(
+                "Pattern has no matches of {} characters",
+                range
+            )));
+        }
+
+        Ok(restricted)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        let mut push = |n: usize| out.extend_from_slice(&(n as u32).to_le_bytes());
+
+        push(FORMAT_VERSION as usize);
+        push(self.start);
+        push(self.states.len());
+        for state in &self.states {
+            push(usize::from(state.accepting));
+            push(state.transitions.len());
+            for (set, target) in &state.transitions {
+                push(*target);
+                push(set.ranges().len());
+                for &(from, to) in set.ranges() {
+                    push(from as usize);
+                    push(to as usize);
+                }
+            }
+        }
+
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TsegerError> {
+        let invalid = |msg: &str| TsegerError::DFAError(format!("Invalid serialized DFA: {}", msg));
+
+        let body = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| invalid("missing header"))?;
+        let mut words = body.chunks(4).map(|chunk| {
+            chunk
+                .try_into()
+                .map(u32::from_le_bytes)
+                .map_err(|_| invalid("truncated"))
+        });
+        let mut next = || words.next().unwrap_or_else(|| Err(invalid("truncated")));
+
+        let version = next()?;
+        if version != FORMAT_VERSION {
+            return Err(invalid(&format!("unsupported format version {}", version)));
+        }
+
+        let start = next()? as usize;
+        let count = next()? as usize;
+        if count == 0 || count > MAX_STATES || start >= count {
+            return Err(invalid("bad state count"));
+        }
+
+        let mut states = Vec::with_capacity(count);
+        for _ in 0..count {
+            let accepting = match next()? {
+                0 => false,
+                1 => true,
+                _ => return Err(invalid("bad accepting flag")),
+            };
+
+            let transition_count = next()?;
+            let mut transitions = Vec::new();
+            for _ in 0..transition_count {
+                let target = next()? as usize;
+                if target >= count {
+                    return Err(invalid("transition to a missing state"));
+                }
+
+                let range_count = next()?;
+                let mut ranges = Vec::new();
+                for _ in 0..range_count {
+                    let from = char::from_u32(next()?).ok_or_else(|| invalid("bad char"))?;
+                    let to = char::from_u32(next()?).ok_or_else(|| invalid("bad char"))?;
+                    ranges.push((from, to));
+                }
+
+                let set = CharSet::from_ranges(ranges.iter().copied());
+                if set.ranges() != ranges || set.is_empty() {
+                    return Err(invalid("ranges aren't canonical"));
+                }
+                transitions.push((set, target));
+            }
+
+            // Overlapping sets would make the automaton nondeterministic
+            let mut ranges: Vec<(char, char)> = transitions
+                .iter()
+                .flat_map(|(set, _)| set.ranges().iter().copied())
+                .collect();
+            ranges.sort_unstable();
+            if ranges.windows(2).any(|pair| pair[1].0 <= pair[0].1) {
+                return Err(invalid("overlapping transitions"));
+            }
+
+            states.push(DfaState {
+                accepting,
+                transitions,
+            });
+        }
+
+        if next().is_ok() {
+            return Err(invalid("trailing bytes"));
+        }
+
+        // Dead, unreachable or equivalent states all disappear here
+        let dfa = Self { states, start };
+        if dfa.clone().trim().minimize().states.len() != dfa.states.len() {
+            return Err(invalid("automaton isn't trimmed and minimal"));
+        }
+
+        Ok(dfa)
+    }
+
+    pub fn start(&self) -> StateId {
+        self.start
+    }
+
+    pub fn states(&self) -> &[DfaState] {
+        &self.states
+    }
+
+    pub fn state(&self, id: StateId) -> &DfaState {
+        &self.states[id]
+    }
+
+    pub fn next(&self, state: StateId, c: char) -> Option<StateId> {
+        self.states[state]
+            .transitions
+            .iter()
+            .find(|(set, _)| set.contains(c))
+            .map(|&(_, target)| target)
+    }
+
+    pub fn is_match(&self, input: &str) -> bool {
+        let mut state = self.start;
+
+        for c in input.chars() {
+            match self.next(state, c) {
+                Some(next) => state = next,
+                None => return false,
+            }
+        }
+
+        self.states[state].accepting
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.states[self.start].accepting && self.states[self.start].transitions.is_empty()
+    }
+
+    /// Trimmed automaton is finite exactly when it has no cycles.
+    pub fn is_finite(&self) -> bool {
+        // 0 - unvisited, 1 - on stack, 2 - done
+        let mut marks = vec![0u8; self.states.len()];
+        let mut stack = vec![(self.start, 0usize)];
+        marks[self.start] = 1;
+
+        while let Some((state, edge)) = stack.pop() {
+            match self.states[state].transitions.get(edge) {
+                None => marks[state] = 2,
+                Some(&(_, target)) => {
+                    stack.push((state, edge + 1));
+                    match marks[target] {
+                        1 => return false,
+                        0 => {
+                            marks[target] = 1;
+                            stack.push((target, 0));
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Length of the longest match, None if there are arbitrarily long ones.
+    pub fn longest(&self) -> Option<usize> {
+        if !self.is_finite() {
+            return None;
+        }
+
+        // Post-order over the acyclic graph, every state is finished after its targets
+        let mut longest: Vec<Option<usize>> = vec![None; self.states.len()];
+        let mut stack = vec![(self.start, false)];
+
+        while let Some((state, expanded)) = stack.pop() {
+            if longest[state].is_some() {
+                continue;
+            }
+            let transitions = &self.states[state].transitions;
+            if expanded {
+                longest[state] = Some(
+                    transitions
+                        .iter()
+                        .map(|&(_, target)| longest[target].unwrap() + 1)
+                        .max()
+                        .unwrap_or(0),
+                );
+            } else {
+                stack.push((state, true));
+                stack.extend(
+                    transitions
+                        .iter()
+                        .filter(|&&(_, target)| longest[target].is_none())
+                        .map(|&(_, target)| (target, false)),
+                );
+            }
+        }
+
+        longest[self.start]
+    }
+
+    // Drops states that can't reach an accepting state
+    fn trim(self) -> Self {
+        let mut reverse: Vec<Vec<StateId>> = vec![Vec::new(); self.states.len()];
+        for (from, state) in self.states.iter().enumerate() {
+            for &(_, to) in &state.transitions {
+                reverse[to].push(from);
+            }
+        }
+
+        let mut live = vec![false; self.states.len()];
+        let mut stack: Vec<StateId> = (0..self.states.len())
+            .filter(|&s| self.states[s].accepting)
+            .collect();
+        while let Some(state) = stack.pop() {
+            if !live[state] {
+                live[state] = true;
+                stack.extend(reverse[state].iter().copied());
+            }
+        }
+
+        if !live[self.start] {
+            return Self {
+                states: vec![DfaState::default()],
+                start: 0,
+            };
+        }
+
+        let states = self
+            .states
+            .into_iter()
+            .map(|state| DfaState {
+                accepting: state.accepting,
+                transitions: state
+                    .transitions
+                    .into_iter()
+                    .filter(|&(_, to)| live[to])
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            states,
+            start: self.start,
+        }
+    }
+
+    // Moore's partition refinement, states are renumbered in BFS order from the start
+    fn minimize(self) -> Self {
+        let mut blocks: Vec<usize> = self
+            .states
+            .iter()
+            .map(|s| usize::from(s.accepting))
+            .collect();
+        let mut block_count = 0;
+
+        loop {
+            let mut signatures: HashMap<(usize, Vec<(CharSet, usize)>), usize> = HashMap::new();
+            let refined: Vec<usize> = (0..self.states.len())
+                .map(|s| {
+                    let signature = (blocks[s], self.grouped_transitions(s, &blocks));
+                    let next_id = signatures.len();
+                    *signatures.entry(signature).or_insert(next_id)
+                })
+                .collect();
+
+            let count = signatures.len();
+            blocks = refined;
+            if count == block_count {
+                break;
+            }
+            block_count = count;
+        }
+
+        // Renumber blocks in BFS order so equal languages give equal automata
+        let mut members: HashMap<usize, StateId> = HashMap::new();
+        for (state, block) in blocks.iter().enumerate() {
+            members.entry(*block).or_insert(state);
+        }
+
+        let mut order: HashMap<usize, StateId> = HashMap::from([(blocks[self.start], 0)]);
+        let mut representatives = vec![self.start];
+        let mut queue = VecDeque::from([self.start]);
+
+        while let Some(state) = queue.pop_front() {
+            for (_, target_block) in self.grouped_transitions(state, &blocks) {
+                if let Entry::Vacant(entry) = order.entry(target_block) {
+                    entry.insert(representatives.len());
+                    representatives.push(members[&target_block]);
+                    queue.push_back(members[&target_block]);
+                }
+            }
+        }
+
+        let states = representatives
+            .iter()
+            .map(|&s| {
+                let mut transitions: Vec<(CharSet, StateId)> = self
+                    .grouped_transitions(s, &blocks)
+                    .into_iter()
+                    .map(|(set, block)| (set, order[&block]))
+                    .collect();
+                transitions.sort_by_key(|(set, _)| set.first());
+
+                DfaState {
+                    accepting: self.states[s].accepting,
+                    transitions,
+                }
+            })
+            .collect();
+
+        Self { states, start: 0 }
+    }
+
+    // Transitions of a state merged by target block
+    fn grouped_transitions(&self, state: StateId, blocks: &[usize]) -> Vec<(CharSet, usize)> {
+        let mut grouped: BTreeMap<usize, CharSet> = BTreeMap::new();
+
+        for (set, target) in &self.states[state].transitions {
+            let entry = grouped.entry(blocks[*target]).or_default();
+            *entry = entry.union(set);
+        }
+
+        let mut transitions: Vec<(CharSet, usize)> = grouped
+            .into_iter()
+            .map(|(block, set)| (set, block))
+            .collect();
+        transitions.sort();
+
+        transitions
+    }
+}
+
+// Splits overlapping NFA moves into disjoint sets, each with all the states it leads to
+pub(crate) fn split_moves(moves: &[&(CharSet, StateId)]) -> Vec<(CharSet, BTreeSet<StateId>)> {
+    let mut points: Vec<u32> = moves
+        .iter()
+        .flat_map(|(set, _)| set.ranges().iter())
+        .flat_map(|&(from, to)| [from as u32, to as u32 + 1])
+        .collect();
+    points.sort_unstable();
+    points.dedup();
+
+    let mut by_targets: BTreeMap<BTreeSet<StateId>, Vec<(char, char)>> = BTreeMap::new();
+
+    for window in points.windows(2) {
+        // Only gaps like the surrogate block fail to convert, no set contains them
+        let (Some(from), Some(to)) = (char::from_u32(window[0]), char::from_u32(window[1] - 1))
+        else {
+            continue;
+        };
+
+        let targets: BTreeSet<StateId> = moves
+            .iter()
+            .filter(|(set, _)| set.contains(from))
+            .map(|&&(_, target)| target)
+            .collect();
+
+        if !targets.is_empty() {
+            by_targets.entry(targets).or_default().push((from, to));
+        }
+    }
+
+    by_targets
+        .into_iter()
+        .map(|(targets, ranges)| (CharSet::from_ranges(ranges), targets))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::BracketExpressionItem;
+
+    #[test]
+    fn test_matching() {
+        let dfa = Dfa::compile("[A-Z]{2}-[0-9]{4}|x+").unwrap();
+
+        assert!(dfa.is_match("AB-1234"));
+        assert!(dfa.is_match("xxx"));
+        assert!(!dfa.is_match("AB-123"));
+        assert!(!dfa.is_match("ab-1234"));
+        assert!(!dfa.is_match(""));
+    }
+
+    #[test]
+    fn test_minimization() {
+        // Textbook example, the minimal DFA has 4 states
+        let dfa = Dfa::compile("(a|b)*abb").unwrap();
+        assert_eq!(dfa.states().len(), 4);
+
+        // Equal languages compile to equal automata
+        assert_eq!(
+            Dfa::compile("(a|b)*").unwrap(),
+            Dfa::compile("[ab]*").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_overlapping_classes() {
+        let dfa = Dfa::compile("[a-m]x|[h-z]y|.z").unwrap();
+
+        assert!(dfa.is_match("ax"));
+        assert!(dfa.is_match("hx"));
+        assert!(dfa.is_match("hy"));
+        assert!(dfa.is_match("\u{1F600}z"));
+        assert!(!dfa.is_match("ay"));
+        assert!(!dfa.is_match("\nz"));
+    }
+
+    #[test]
+    fn test_empty_and_finite_languages() {
+        let empty = Dfa::from_ast(&RegexAST::Concat(vec![
+            RegexAST::Literal('a'),
+            RegexAST::BracketExpression {
+                negated: true,
+                items: vec![BracketExpressionItem::Range('\0', char::MAX)],
+            },
+        ]))
+        .unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.states().len(), 1);
+
+        assert!(Dfa::compile("(ab|c){1,3}").unwrap().is_finite());
+        assert!(!Dfa::compile("ab*c").unwrap().is_finite());
+        assert!(!Dfa::compile("a").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_serialization_round_trip() {
+        let dfa = Dfa::compile(r"[A-Z]{2}-\d{4}|\p{emojis}+").unwrap();
+        let bytes = dfa.to_bytes();
+
+        assert_eq!(Dfa::from_bytes(&bytes).unwrap(), dfa);
+        assert_eq!(
+            Dfa::compile("(a|b)*").unwrap().to_bytes(),
+            Dfa::compile("[ab]*").unwrap().to_bytes()
+        );
+
+        assert!(Dfa::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Dfa::from_bytes(&[bytes.as_slice(), &[0, 0, 0, 0]].concat()).is_err());
+        assert!(Dfa::from_bytes(b"TSDFB").is_err());
+
+        let mut wrong_target = Dfa::compile("a").unwrap().to_bytes();
+        // header, version, start, count, accepting, transitions, then the target
+        wrong_target[25] = 9;
+        assert!(Dfa::from_bytes(&wrong_target).is_err());
+
+        // Two states, the first going to the second on `a` and on `second`
+        let crafted = |second: (char, char)| {
+            let words = [1, 0, 2, 0, 2, 1, 1, 'a' as u32, 'a' as u32, 1, 1];
+            let mut bytes = MAGIC.to_vec();
+            words
+                .iter()
+                .chain(&[second.0 as u32, second.1 as u32, 1, 0])
+                .for_each(|w| bytes.extend_from_slice(&w.to_le_bytes()));
+            Dfa::from_bytes(&bytes)
+        };
+        assert!(crafted(('c', 'd')).is_ok());
+        let err = crafted(('a', 'b')).unwrap_err();
+        assert!(err.to_string().contains("overlapping"), "{}", err);
+
+        // Accepts `a`, then `b` loops in a state that never accepts
+        let words = [1, 0, 2, 1, 1, 1, 1, 'a' as u32, 'a' as u32];
+        let dead_loop = [0, 1, 1, 1, 'b' as u32, 'b' as u32];
+        let mut bytes = MAGIC.to_vec();
+        words
+            .iter()
+            .chain(&dead_loop)
+            .for_each(|w| bytes.extend_from_slice(&w.to_le_bytes()));
+        let err = Dfa::from_bytes(&bytes).unwrap_err();
+        assert!(err.to_string().contains("minimal"), "{}", err);
+    }
+
+    #[test]
+    fn test_length_restriction() {
+        let dfa = Dfa::compile("[a-z]+(-[0-9]+)?").unwrap();
+
+        let exact = dfa.restrict_length(4, Some(4)).unwrap();
+        assert!(exact.is_match("abcd"));
+        assert!(exact.is_match("ab-1"));
+        assert!(!exact.is_match("abc"));
+        assert!(!exact.is_match("abcde"));
+
+        let open = dfa.restrict_length(3, None).unwrap();
+        assert!(open.is_match("abcdefghijklmnop-123"));
+        assert!(!open.is_match("ab"));
+
+        assert_eq!(
+            Dfa::compile("[ab]{2}")
+                .unwrap()
+                .restrict_length(2, Some(2))
+                .unwrap(),
+            Dfa::compile("[ab][ab]").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_concat_and_longest() {
+        let parts = [
+            Dfa::compile("a|bc").unwrap(),
+            Dfa::compile("-?").unwrap(),
+            Dfa::compile("[0-9]{2}").unwrap(),
+        ];
+        let dfa = Dfa::concat(&parts).unwrap();
+
+        assert_eq!(dfa, Dfa::compile("(a|bc)-?[0-9]{2}").unwrap());
+        assert_eq!(dfa.longest(), Some(5));
+        assert_eq!(Dfa::length_range(0, Some(0)).unwrap().longest(), Some(0));
+        assert_eq!(Dfa::compile("a+").unwrap().longest(), None);
+    }
+
+    #[test]
+    fn test_complement() {
+        let dfa = Dfa::compile("ab|c+").unwrap();
+        let complement = dfa.complement();
+
+        for input in ["", "a", "abc", "ba", "é"] {
+            assert!(complement.is_match(input), "{}", input);
+        }
+        for input in ["ab", "c", "ccc"] {
+            assert!(!complement.is_match(input), "{}", input);
+        }
+        assert_eq!(complement.complement(), dfa);
+        assert!(dfa.intersect(&complement).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_impossible_length_is_reported() {
+        let dfa = Dfa::compile("[0-9]{3}|[0-9]{5}").unwrap();
+
+        assert!(dfa.restrict_length(4, Some(4)).is_err());
+        assert!(dfa.restrict_length(6, None).is_err());
+        assert!(dfa.restrict_length(4, Some(5)).is_ok());
+        assert!(Dfa::length_range(5, Some(4)).is_err());
+    }
+}
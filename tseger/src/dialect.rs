@@ -0,0 +1,918 @@
+use crate::charset::CharSet;
+use crate::common::TsegerError;
+use crate::lexer::Position;
+use crate::parser::BracketExpressionItem;
+use crate::parser::RegexAST;
+
+/*
+* Foreign regex dialects translated into tseger's AST:
+*
+* - (?:...) (?P<n>...)  non-capturing and named groups become plain groups
+* - \d \w \s            Perl classes, translated to their ASCII meaning
+* - [[:alpha:]]         POSIX classes inside bracket expressions
+* - \xFF \x{FFFF}       hex escapes (plus \u / \U in Rust, \o{} and \Q...\E in PCRE)
+* - *? +? ?? {n,m}?     lazy quantifiers, laziness is dropped and reported
+* - ^ $ \A \z \b \B     anchors and boundaries, dropped and reported
+*
+* Everything that changes the matched language in a way tseger can't express
+* (backreferences, lookarounds, inline flags, possessive quantifiers, Unicode
+* properties, class set operations) is rejected with a DialectError.
+*/
+
+type DialectResult<T> = Result<T, TsegerError>;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Dialect {
+    Pcre,
+    Rust,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DroppedKind {
+    Laziness,
+    Anchor,
+}
+
+/// Construct that was accepted but has no meaning for generation.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DroppedConstruct {
+    pub kind: DroppedKind,
+    pub text: String,
+    pub position: Position,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Translation {
+    pub ast: RegexAST,
+    pub dropped: Vec<DroppedConstruct>,
+}
+
+pub fn translate(pattern: &str, dialect: Dialect) -> DialectResult<Translation> {
+    let mut translator = Translator {
+        chars: pattern.chars().collect(),
+        pos: 0,
+        dialect,
+        dropped: Vec::new(),
+    };
+
+    let ast = translator.parse_alteration()?;
+
+    if let Some(c) = translator.peek() {
+        return Err(translator.error(format!("Unbalanced '{}'", c), translator.pos));
+    }
+
+    Ok(Translation {
+        ast,
+        dropped: translator.dropped,
+    })
+}
+
+struct Translator {
+    chars: Vec<char>,
+    pos: usize,
+    dialect: Dialect,
+    dropped: Vec<DroppedConstruct>,
+}
+
+impl Translator {
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.peek_at(i) == Some(c))
+    }
+
+    fn expect(&mut self, expected: char) -> DialectResult<()> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(
+                format!("Expected '{}', found '{}'", expected, c),
+                self.pos - 1,
+            )),
+            None => Err(self.error(
+                format!("Expected '{}', found end of pattern", expected),
+                self.pos,
+            )),
+        }
+    }
+
+    fn text(&self, start: usize) -> String {
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn unsupported(&self, construct: &str, start: usize) -> TsegerError {
+        TsegerError::DialectError(format!(
+            "Unsupported {} '{}' at {}",
+            construct,
+            self.text(start),
+            start
+        ))
+    }
+
+    fn drop_construct(&mut self, kind: DroppedKind, start: usize) {
+        let text = self.text(start);
+        self.dropped.push(DroppedConstruct {
+            kind,
+            text,
+            position: Position {
+                start,
+                end: self.pos - 1,
+            },
+        });
+    }
+
+    fn parse_alteration(&mut self) -> DialectResult<RegexAST> {
+        let mut branches = vec![self.parse_concatenation()?];
+
+        while self.peek() == Some('|') {
+            self.next();
+            branches.push(self.parse_concatenation()?);
+        }
+
+        if branches.len() == 1 {
+            Ok(branches.remove(0))
+        } else {
+            Ok(RegexAST::Alteration(branches))
+        }
+    }
+
+    fn parse_concatenation(&mut self) -> DialectResult<RegexAST> {
+        let mut nodes = Vec::new();
+
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+
+            if let Some(atom) = self.parse_atom()? {
+                nodes.push(self.parse_quantifiers(atom)?);
+            }
+        }
+
+        // An empty concatenation stands for the empty string, e.g. in `(a|)`
+        match nodes.len() {
+            1 => Ok(nodes.remove(0)),
+            _ => Ok(RegexAST::Concat(nodes)),
+        }
+    }
+
+    fn parse_quantifiers(&mut self, atom: RegexAST) -> DialectResult<RegexAST> {
+        let start = self.pos;
+
+        let node = match self.peek() {
+            Some('*') => {
+                self.next();
+                RegexAST::Star(Box::new(atom))
+            }
+            Some('+') => {
+                self.next();
+                RegexAST::Plus(Box::new(atom))
+            }
+            Some('?') => {
+                self.next();
+                RegexAST::Question(Box::new(atom))
+            }
+            Some('{') => match self.parse_counted_repetition()? {
+                Some((min, max)) => RegexAST::Repetition {
+                    node: Box::new(atom),
+                    min,
+                    max,
+                },
+                None => return Ok(atom),
+            },
+            _ => return Ok(atom),
+        };
+
+        match self.peek() {
+            Some('?') => {
+                self.next();
+                self.drop_construct(DroppedKind::Laziness, start);
+            }
+            Some('+') => {
+                self.next();
+                return Err(self.unsupported("possessive quantifier", start));
+            }
+            _ => (),
+        }
+
+        match self.peek() {
+            Some('*') | Some('+') | Some('?') => {
+                Err(self.error("Nested quantifier without a group".to_string(), self.pos))
+            }
+            Some('{') if self.counted_repetition_at(self.pos) => {
+                Err(self.error("Nested quantifier without a group".to_string(), self.pos))
+            }
+            _ => Ok(node),
+        }
+    }
+
+    // PCRE treats a '{' that doesn't start a valid quantifier as a literal, Rust rejects it
+    fn parse_counted_repetition(&mut self) -> DialectResult<Option<(u32, Option<u32>)>> {
+        if self.dialect == Dialect::Pcre && !self.counted_repetition_at(self.pos) {
+            return Ok(None);
+        }
+
+        self.parse_repetition_bounds().map(Some)
+    }
+
+    // Returns None for constructs that match no characters (dropped anchors, comments)
+    fn parse_atom(&mut self) -> DialectResult<Option<RegexAST>> {
+        let start = self.pos;
+
+        match self.next() {
+            None => Err(self.error("Unexpected end of pattern".to_string(), start)),
+            Some('(') => self.parse_group(start),
+            Some('[') => Ok(Some(self.parse_bracket_expression(start)?)),
+            Some('.') => Ok(Some(RegexAST::Dot)),
+            Some('^') | Some('$') => {
+                self.drop_construct(DroppedKind::Anchor, start);
+                Ok(None)
+            }
+            Some('\\') => self.parse_escape(start),
+            Some('*') | Some('+') | Some('?') => {
+                Err(self.error("Quantifier without a target".to_string(), start))
+            }
+            Some('{') if self.dialect == Dialect::Rust || self.counted_repetition_at(start) => {
+                Err(self.error("Quantifier without a target".to_string(), start))
+            }
+            Some(c) => Ok(Some(RegexAST::Literal(c))),
+        }
+    }
+
+    fn parse_group(&mut self, start: usize) -> DialectResult<Option<RegexAST>> {
+        if self.peek() == Some('?') {
+            self.next();
+
+            if self.starts_with("#") && self.dialect == Dialect::Pcre {
+                while let Some(c) = self.next() {
+                    if c == ')' {
+                        return Ok(None);
+                    }
+                }
+                return Err(self.error("Unterminated comment".to_string(), start));
+            }
+
+            if self.starts_with(":") {
+                self.next();
+            } else if self.starts_with("=")
+                || self.starts_with("!")
+                || self.starts_with("<=")
+                || self.starts_with("<!")
+            {
+                self.next();
+                return Err(self.unsupported("lookaround", start));
+            } else if self.starts_with(">") {
+                self.next();
+                return Err(self.unsupported("atomic group", start));
+            } else if self.starts_with("P<") || self.starts_with("<") {
+                self.skip_group_name('>')?;
+            } else if self.starts_with("'") && self.dialect == Dialect::Pcre {
+                self.skip_group_name('\'')?;
+            } else {
+                while let Some(c) = self.peek() {
+                    if c == ')' || c == ':' {
+                        self.next();
+                        break;
+                    }
+                    self.next();
+                }
+                return Err(self.unsupported("inline flags or group modifier", start));
+            }
+        }
+
+        let inner = self.parse_alteration()?;
+        self.expect(')')?;
+
+        Ok(Some(RegexAST::Group(Box::new(inner))))
+    }
+
+    fn skip_group_name(&mut self, terminator: char) -> DialectResult<()> {
+        let start = self.pos;
+
+        if self.peek() == Some('P') {
+            self.next();
+        }
+        self.next();
+
+        while let Some(c) = self.next() {
+            if c == terminator {
+                return Ok(());
+            }
+            if !(c.is_alphanumeric() || c == '_') {
+                return Err(self.error(format!("Invalid group name character '{}'", c), start));
+            }
+        }
+
+        Err(self.error("Unterminated group name".to_string(), start))
+    }
+
+    fn parse_escape(&mut self, start: usize) -> DialectResult<Option<RegexAST>> {
+        let c = match self.next() {
+            None => return Err(self.error("Trailing backslash".to_string(), start)),
+            Some(c) => c,
+        };
+
+        if let Some(class) = self.perl_class(c) {
+            return Ok(Some(RegexAST::BracketExpression {
+                negated: false,
+                items: bracket_items(&class),
+            }));
+        }
+
+        match c {
+            'b' | 'B' | 'A' | 'z' => {
+                self.drop_construct(DroppedKind::Anchor, start);
+                Ok(None)
+            }
+            'Z' | 'G' if self.dialect == Dialect::Pcre => {
+                self.drop_construct(DroppedKind::Anchor, start);
+                Ok(None)
+            }
+            'Q' if self.dialect == Dialect::Pcre => Ok(Some(self.parse_quoted())),
+            '1'..='9' | 'k' | 'g' => Err(self.unsupported("backreference", start)),
+            'p' | 'P' => {
+                self.skip_property_name();
+                Err(self.unsupported("Unicode property class", start))
+            }
+            _ => Ok(Some(RegexAST::Literal(self.escaped_char(c, start)?))),
+        }
+    }
+
+    fn parse_quoted(&mut self) -> RegexAST {
+        let mut nodes = Vec::new();
+
+        while self.peek().is_some() {
+            if self.starts_with("\\E") {
+                self.pos += 2;
+                break;
+            }
+            nodes.push(RegexAST::Literal(self.next().unwrap()));
+        }
+
+        match nodes.len() {
+            1 => nodes.remove(0),
+            _ => RegexAST::Group(Box::new(RegexAST::Concat(nodes))),
+        }
+    }
+
+    fn skip_property_name(&mut self) {
+        if self.peek() == Some('{') {
+            while let Some(c) = self.next() {
+                if c == '}' {
+                    break;
+                }
+            }
+        } else {
+            self.next();
+        }
+    }
+
+    // Translates a single escaped character, valid both inside and outside bracket expressions
+    fn escaped_char(&mut self, c: char, start: usize) -> DialectResult<char> {
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            'f' => Ok('\x0C'),
+            'a' => Ok('\x07'),
+            'v' if self.dialect == Dialect::Rust => Ok('\x0B'),
+            'e' if self.dialect == Dialect::Pcre => Ok('\x1B'),
+            '0' if self.dialect == Dialect::Pcre => self.parse_digits(8, 2, false, start),
+            'o' if self.dialect == Dialect::Pcre => {
+                self.expect('{')?;
+                self.parse_digits(8, 11, true, start)
+            }
+            'x' => self.parse_hex_escape(2, start),
+            'u' if self.dialect == Dialect::Rust => self.parse_hex_escape(4, start),
+            'U' if self.dialect == Dialect::Rust => self.parse_hex_escape(8, start),
+            c if !c.is_alphanumeric() => Ok(c),
+            _ => Err(self.unsupported("escape sequence", start)),
+        }
+    }
+
+    fn parse_hex_escape(&mut self, width: usize, start: usize) -> DialectResult<char> {
+        if self.peek() == Some('{') {
+            self.next();
+            self.parse_digits(16, 8, true, start)
+        } else {
+            let value = self.parse_digits(16, width, false, start)?;
+            let consumed = self.text(start).chars().count() - 2;
+            if consumed != width {
+                return Err(self.error(format!("Expected {} hex digits in escape", width), start));
+            }
+            Ok(value)
+        }
+    }
+
+    fn parse_digits(
+        &mut self,
+        radix: u32,
+        max_digits: usize,
+        braced: bool,
+        start: usize,
+    ) -> DialectResult<char> {
+        let mut digits = String::new();
+
+        while digits.len() < max_digits {
+            match self.peek() {
+                Some(c) if c.is_digit(radix) => {
+                    digits.push(c);
+                    self.next();
+                }
+                _ => break,
+            }
+        }
+
+        if braced {
+            self.expect('}')?;
+        }
+
+        if digits.is_empty() {
+            return if braced {
+                Err(self.error("Empty character code".to_string(), start))
+            } else {
+                Ok('\0')
+            };
+        }
+
+        u32::from_str_radix(&digits, radix)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error(format!("Invalid character code '{}'", digits), start))
+    }
+
+    fn perl_class(&self, c: char) -> Option<CharSet> {
+        let ranges = match c.to_ascii_lowercase() {
+            'd' => vec![('0', '9')],
+            'w' => vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+            's' => vec![('\t', '\r'), (' ', ' ')],
+            'h' if self.dialect == Dialect::Pcre => {
+                vec![('\t', '\t'), (' ', ' '), ('\u{A0}', '\u{A0}')]
+            }
+            'v' if self.dialect == Dialect::Pcre => {
+                vec![('\n', '\r'), ('\u{85}', '\u{85}'), ('\u{2028}', '\u{2029}')]
+            }
+            _ => return None,
+        };
+
+        let set = CharSet::from_ranges(ranges);
+        if c.is_ascii_uppercase() {
+            Some(set.complement())
+        } else {
+            Some(set)
+        }
+    }
+
+    fn parse_bracket_expression(&mut self, start: usize) -> DialectResult<RegexAST> {
+        let mut negated = false;
+        let mut set = CharSet::empty();
+
+        if self.peek() == Some('^') {
+            self.next();
+            negated = true;
+        }
+
+        // A leading ']' is a literal in both dialects
+        if self.peek() == Some(']') {
+            self.next();
+            set = set.union(&CharSet::single(']'));
+        }
+
+        loop {
+            let item_start = self.pos;
+
+            match self.peek() {
+                None => {
+                    return Err(self.error("Unterminated bracket expression".to_string(), start));
+                }
+                Some(']') => {
+                    self.next();
+                    break;
+                }
+                Some('[') if self.starts_with("[:") => {
+                    set = set.union(&self.parse_posix_class()?);
+                    continue;
+                }
+                Some('[') if self.dialect == Dialect::Rust => {
+                    self.next();
+                    return Err(self.unsupported("nested character class", item_start));
+                }
+                Some('&') | Some('~') | Some('-')
+                    if self.dialect == Dialect::Rust
+                        && self.peek_at(1) == self.peek()
+                        && self.peek_at(2) != Some(']') =>
+                {
+                    self.pos += 2;
+                    return Err(self.unsupported("character class set operation", item_start));
+                }
+                Some(_) => (),
+            }
+
+            set = set.union(&self.parse_class_item(item_start)?);
+        }
+
+        Ok(RegexAST::BracketExpression {
+            negated,
+            items: bracket_items(&set),
+        })
+    }
+
+    fn parse_posix_class(&mut self) -> DialectResult<CharSet> {
+        let start = self.pos;
+        self.pos += 2;
+
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.next();
+        }
+
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_alphabetic()) {
+            name.push(c);
+            self.next();
+        }
+
+        if !self.starts_with(":]") {
+            return Err(self.error("Malformed POSIX class".to_string(), start));
+        }
+        self.pos += 2;
+
+        let ranges = match name.as_str() {
+            "alnum" => vec![('0', '9'), ('A', 'Z'), ('a', 'z')],
+            "alpha" => vec![('A', 'Z'), ('a', 'z')],
+            "ascii" => vec![('\0', '\x7F')],
+            "blank" => vec![('\t', '\t'), (' ', ' ')],
+            "cntrl" => vec![('\0', '\x1F'), ('\x7F', '\x7F')],
+            "digit" => vec![('0', '9')],
+            "graph" => vec![('!', '~')],
+            "lower" => vec![('a', 'z')],
+            "print" => vec![(' ', '~')],
+            "punct" => vec![('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+            "space" => vec![('\t', '\r'), (' ', ' ')],
+            "upper" => vec![('A', 'Z')],
+            "word" => vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+            "xdigit" => vec![('0', '9'), ('A', 'F'), ('a', 'f')],
+            _ => return Err(self.unsupported("POSIX class", start)),
+        };
+
+        let set = CharSet::from_ranges(ranges);
+        if negated {
+            Ok(set.complement())
+        } else {
+            Ok(set)
+        }
+    }
+}
+
+impl Scanner for Translator {
+    fn chars(&self) -> &[char] {
+        &self.chars
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn seek(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    fn error(&self, msg: String, at: usize) -> TsegerError {
+        TsegerError::DialectError(format!("{} at {}", msg, at))
+    }
+
+    fn parse_class_atom(&mut self) -> DialectResult<ClassAtom> {
+        let start = self.pos;
+
+        match self.next() {
+            None => Err(self.error("Unterminated bracket expression".to_string(), start)),
+            Some('\\') => {
+                let c = match self.next() {
+                    None => return Err(self.error("Trailing backslash".to_string(), start)),
+                    Some(c) => c,
+                };
+
+                if let Some(class) = self.perl_class(c) {
+                    return Ok(ClassAtom::Set(class));
+                }
+
+                match c {
+                    'b' if self.dialect == Dialect::Pcre => Ok(ClassAtom::Char('\x08')),
+                    'p' | 'P' => {
+                        self.skip_property_name();
+                        Err(self.unsupported("Unicode property class", start))
+                    }
+                    _ => Ok(ClassAtom::Char(self.escaped_char(c, start)?)),
+                }
+            }
+            Some(c) => Ok(ClassAtom::Char(c)),
+        }
+    }
+}
+
+pub(crate) enum ClassAtom {
+    Char(char),
+    Set(CharSet),
+}
+
+/// Cursor over a pattern, the scanning shared by the dialect translator and
+/// the byte pattern parser.
+pub(crate) trait Scanner {
+    fn chars(&self) -> &[char];
+    fn pos(&self) -> usize;
+    fn seek(&mut self, pos: usize);
+    fn error(&self, msg: String, at: usize) -> TsegerError;
+    /// One member of a bracket expression, a char or a class like `\d`.
+    fn parse_class_atom(&mut self) -> Result<ClassAtom, TsegerError>;
+
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars().get(self.pos() + offset).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.seek(self.pos() + 1);
+        }
+        c
+    }
+
+    /// True for `{n}` `{n,}` `{,m}` `{n,m}` at `at`, at least one digit is required.
+    fn counted_repetition_at(&self, at: usize) -> bool {
+        let mut i = at + 1;
+        let mut digits = false;
+        let mut comma = false;
+
+        while let Some(&c) = self.chars().get(i) {
+            match c {
+                '0'..='9' => digits = true,
+                ',' if !comma => comma = true,
+                '}' => return digits,
+                _ => return false,
+            }
+            i += 1;
+        }
+
+        false
+    }
+
+    /// Bounds of the counted repetition at the cursor, `{,m}` starts at 0.
+    fn parse_repetition_bounds(&mut self) -> Result<(u32, Option<u32>), TsegerError> {
+        let start = self.pos();
+        if !self.counted_repetition_at(start) {
+            return Err(self.error("Invalid repetition".to_string(), start));
+        }
+
+        self.next();
+        let min = self.parse_number()?;
+        let max = if self.peek() == Some(',') {
+            self.next();
+            self.parse_number()?
+        } else {
+            min
+        };
+        self.next();
+
+        let min_value = min.unwrap_or(0);
+        if let Some(max_value) = max.filter(|max_value| min_value > *max_value) {
+            return Err(self.error(
+                format!("Invalid repetition range {{{},{}}}", min_value, max_value),
+                start,
+            ));
+        }
+
+        Ok((min_value, max))
+    }
+
+    fn parse_number(&mut self) -> Result<Option<u32>, TsegerError> {
+        let start = self.pos();
+        let mut digits = String::new();
+
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_digit()) {
+            digits.push(c);
+            self.next();
+        }
+
+        if digits.is_empty() {
+            return Ok(None);
+        }
+
+        digits
+            .parse::<u32>()
+            .map(Some)
+            .map_err(|_| self.error(format!("Repetition count '{}' is too large", digits), start))
+    }
+
+    /// A class atom, or a range `a-z` when a '-' and a second char follow.
+    fn parse_class_item(&mut self, item_start: usize) -> Result<CharSet, TsegerError> {
+        let first = self.parse_class_atom()?;
+        if self.peek() != Some('-') || self.peek_at(1).is_none_or(|c| c == ']') {
+            return Ok(match first {
+                ClassAtom::Set(class) => class,
+                ClassAtom::Char(c) => CharSet::single(c),
+            });
+        }
+
+        self.next();
+        let (ClassAtom::Char(first), ClassAtom::Char(last)) = (first, self.parse_class_atom()?)
+        else {
+            return Err(self.error(
+                "Character class can't be a range boundary".to_string(),
+                item_start,
+            ));
+        };
+
+        if first > last {
+            return Err(self.error(format!("Invalid range '{}-{}'", first, last), item_start));
+        }
+        Ok(CharSet::range(first, last))
+    }
+}
+
+pub(crate) fn bracket_items(set: &CharSet) -> Vec<BracketExpressionItem> {
+    set.ranges()
+        .iter()
+        .map(|&(from, to)| {
+            if from == to {
+                BracketExpressionItem::Char(from)
+            } else {
+                BracketExpressionItem::Range(from, to)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(items: &[(char, char)]) -> Vec<BracketExpressionItem> {
+        items
+            .iter()
+            .map(|&(from, to)| {
+                if from == to {
+                    BracketExpressionItem::Char(from)
+                } else {
+                    BracketExpressionItem::Range(from, to)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_groups_and_perl_classes() {
+        let t = translate(r"(?:ab|\d)(?P<year>\w)", Dialect::Rust).unwrap();
+
+        assert_eq!(
+            t.ast,
+            RegexAST::Concat(vec![
+                RegexAST::Group(Box::new(RegexAST::Alteration(vec![
+                    RegexAST::Concat(vec![RegexAST::Literal('a'), RegexAST::Literal('b')]),
+                    RegexAST::BracketExpression {
+                        negated: false,
+                        items: chars(&[('0', '9')]),
+                    },
+                ]))),
+                RegexAST::Group(Box::new(RegexAST::BracketExpression {
+                    negated: false,
+                    items: chars(&[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]),
+                })),
+            ])
+        );
+        assert!(t.dropped.is_empty());
+    }
+
+    #[test]
+    fn test_posix_classes_and_ranges() {
+        let t = translate(r"[^[:alpha:]_\d-]", Dialect::Pcre).unwrap();
+
+        assert_eq!(
+            t.ast,
+            RegexAST::BracketExpression {
+                negated: true,
+                items: chars(&[('-', '-'), ('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_negated_perl_class_is_complemented() {
+        let t = translate(r"[\D]", Dialect::Rust).unwrap();
+
+        assert_eq!(
+            t.ast,
+            RegexAST::BracketExpression {
+                negated: false,
+                items: chars(&[('\0', '/'), (':', '\u{D7FF}'), ('\u{E000}', '\u{10FFFF}')]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_laziness_and_anchors_are_dropped() {
+        let t = translate(r"^\ba+?b{2,3}?$", Dialect::Pcre).unwrap();
+
+        assert_eq!(
+            t.ast,
+            RegexAST::Concat(vec![
+                RegexAST::Plus(Box::new(RegexAST::Literal('a'))),
+                RegexAST::Repetition {
+                    node: Box::new(RegexAST::Literal('b')),
+                    min: 2,
+                    max: Some(3),
+                },
+            ])
+        );
+        assert_eq!(
+            t.dropped
+                .iter()
+                .map(|d| (d.kind, d.text.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (DroppedKind::Anchor, "^"),
+                (DroppedKind::Anchor, "\\b"),
+                (DroppedKind::Laziness, "+?"),
+                (DroppedKind::Laziness, "{2,3}?"),
+                (DroppedKind::Anchor, "$"),
+            ]
+        );
+        assert_eq!(t.dropped[2].position, Position { start: 4, end: 5 });
+    }
+
+    #[test]
+    fn test_escapes() {
+        let t = translate(r"\x41\x{1F600}é\.", Dialect::Rust).unwrap();
+        assert_eq!(
+            t.ast,
+            RegexAST::Concat(vec![
+                RegexAST::Literal('A'),
+                RegexAST::Literal('\u{1F600}'),
+                RegexAST::Literal('é'),
+                RegexAST::Literal('.'),
+            ])
+        );
+
+        let t = translate(r"\Qa.b\E{2}", Dialect::Pcre).unwrap();
+        assert_eq!(
+            t.ast,
+            RegexAST::Repetition {
+                node: Box::new(RegexAST::Group(Box::new(RegexAST::Concat(vec![
+                    RegexAST::Literal('a'),
+                    RegexAST::Literal('.'),
+                    RegexAST::Literal('b'),
+                ])))),
+                min: 2,
+                max: Some(2),
+            }
+        );
+    }
+
+    #[test]
+    fn test_dialect_differences() {
+        assert_eq!(
+            translate("a{x", Dialect::Pcre).unwrap().ast,
+            RegexAST::Concat(vec![
+                RegexAST::Literal('a'),
+                RegexAST::Literal('{'),
+                RegexAST::Literal('x'),
+            ])
+        );
+        assert!(translate("a{x", Dialect::Rust).is_err());
+        assert!(translate("a{}", Dialect::Rust).is_err());
+        assert!(translate(r"\Qa\E", Dialect::Rust).is_err());
+        assert!(translate(r"[a&&b]", Dialect::Rust).is_err());
+        assert!(translate(r"[a&&b]", Dialect::Pcre).is_ok());
+    }
+
+    #[test]
+    fn test_class_range_boundaries() {
+        for dialect in [Dialect::Pcre, Dialect::Rust] {
+            assert!(translate(r"[\w-z]", dialect).is_err());
+            assert!(translate(r"[a-\d]", dialect).is_err());
+            assert!(translate(r"[\d-]", dialect).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_unsupported_constructs() {
+        for (pattern, construct) in [
+            (r"(a)\1", "backreference"),
+            (r"a(?=b)", "lookaround"),
+            (r"(?<!a)b", "lookaround"),
+            (r"(?i)abc", "inline flags"),
+            (r"a*+", "possessive quantifier"),
+            (r"(?>a)", "atomic group"),
+            (r"\p{Greek}", "Unicode property class"),
+        ] {
+            match translate(pattern, Dialect::Pcre) {
+                Err(TsegerError::DialectError(msg)) => {
+                    assert!(msg.contains(construct), "{}: {}", pattern, msg)
+                }
+                other => panic!("{} should be rejected, got {:?}", pattern, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use hmac::Hmac;
+use hmac::Mac;
+use sha2::Sha256;
+
+use crate::common::TsegerError;
+use crate::dfa::Dfa;
+use crate::rank::Ranker;
+
+/*
+* Format-preserving pseudonymization over a regular language:
+*
+* - the input is ranked among the matches of its own length
+* - the rank is permuted with a keyed Feistel network over [0, count), values that
+*   fall outside the domain are walked through the permutation again (cycle walking)
+* - the permuted rank is unranked back into a match of the same length
+*
+* Round function is HMAC-SHA256, so the mapping is stable across runs and platforms.
+* Like every permutation it can have fixed points, a language with a single match of
+* some length always maps that match onto itself.
+*/
+
+const ROUNDS: u8 = 10;
+const DOMAIN_TAG: &[u8] = b"tseger-fpe-v1";
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub struct Pseudonymizer {
+    ranker: Ranker,
+    mac: HmacSha256,
+}
+
+impl Pseudonymizer {
+    pub fn new(dfa: Dfa, key: &[u8]) -> Self {
+        Self {
+            ranker: Ranker::new(dfa),
+            mac: HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length"),
+        }
+    }
+
+    pub fn pseudonymize(&self, input: &str) -> Result<String, TsegerError> {
+        self.map(input, true)
+    }
+
+    pub fn reveal(&self, pseudonym: &str) -> Result<String, TsegerError> {
+        self.map(pseudonym, false)
+    }
+
+    fn map(&self, input: &str, forward: bool) -> Result<String, TsegerError> {
+        let len = input.chars().count();
+        let rank = self.ranker.rank(input)?;
+        let domain = self.ranker.count(len)?;
+
+        let feistel = Feistel {
+            mac: &self.mac,
+            domain,
+            tweak: len as u64,
+            half_bits: half_bits(domain),
+        };
+
+        let mut value = rank;
+        loop {
+            value = if forward {
+                feistel.encrypt(value)
+            } else {
+                feistel.decrypt(value)
+            };
+            if value < domain {
+                break;
+            }
+        }
+
+        self.ranker.unrank(len, value)
+    }
+}
+
+// Balanced Feistel network over 2 * half_bits bits
+struct Feistel<'a> {
+    mac: &'a HmacSha256,
+    domain: u128,
+    tweak: u64,
+    half_bits: u32,
+}
+
+impl Feistel<'_> {
+    fn mask(&self) -> u128 {
+        (1u128 << self.half_bits) - 1
+    }
+
+    fn split(&self, value: u128) -> (u128, u128) {
+        (value >> self.half_bits, value & self.mask())
+    }
+
+    fn join(&self, left: u128, right: u128) -> u128 {
+        (left << self.half_bits) | right
+    }
+
+    fn encrypt(&self, value: u128) -> u128 {
+        let (mut left, mut right) = self.split(value);
+
+        for round in 0..ROUNDS {
+            let next = left ^ self.round(round, right);
+            left = right;
+            right = next;
+        }
+
+        self.join(left, right)
+    }
+
+    fn decrypt(&self, value: u128) -> u128 {
+        let (mut left, mut right) = self.split(value);
+
+        for round in (0..ROUNDS).rev() {
+            let previous = right ^ self.round(round, left);
+            right = left;
+            left = previous;
+        }
+
+        self.join(left, right)
+    }
+
+    fn round(&self, round: u8, half: u128) -> u128 {
+        let mut mac = self.mac.clone();
+        mac.update(DOMAIN_TAG);
+        mac.update(&self.domain.to_be_bytes());
+        mac.update(&self.tweak.to_be_bytes());
+        mac.update(&[round]);
+        mac.update(&half.to_be_bytes());
+
+        let digest = mac.finalize().into_bytes();
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&digest[..16]);
+
+        u128::from_be_bytes(bytes) & self.mask()
+    }
+}
+
+// Half of the smallest even bit width that covers the domain
+fn half_bits(domain: u128) -> u32 {
+    let bits = 128 - domain.saturating_sub(1).leading_zeros();
+    bits.div_ceil(2).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip_preserves_format() {
+        let fpe = Pseudonymizer::new(Dfa::compile("[A-Z]{2}-[0-9]{4}").unwrap(), b"secret key");
+
+        for id in ["AB-1234", "ZZ-9999", "AA-0000"] {
+            let pseudonym = fpe.pseudonymize(id).unwrap();
+
+            assert!(fpe.ranker.dfa().is_match(&pseudonym));
+            assert_ne!(pseudonym, id);
+            assert_eq!(fpe.reveal(&pseudonym).unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn test_mapping_is_keyed_and_stable() {
+        let dfa = Dfa::compile("user_[a-z]{6}[0-9]{0,3}").unwrap();
+        let first = Pseudonymizer::new(dfa.clone(), b"key one");
+        let again = Pseudonymizer::new(dfa.clone(), b"key one");
+        let other = Pseudonymizer::new(dfa, b"key two");
+
+        let input = "user_mimiko42";
+        assert_eq!(
+            first.pseudonymize(input).unwrap(),
+            again.pseudonymize(input).unwrap()
+        );
+        assert_ne!(
+            first.pseudonymize(input).unwrap(),
+            other.pseudonymize(input).unwrap()
+        );
+        assert_eq!(first.pseudonymize(input).unwrap().len(), input.len());
+    }
+
+    #[test]
+    fn test_permutation_over_small_domain() {
+        let dfa = Dfa::compile("[a-c]{2}").unwrap();
+        let fpe = Pseudonymizer::new(dfa, b"k");
+        let ranker = Ranker::new(Dfa::compile("[a-c]{2}").unwrap());
+
+        let outputs: HashSet<String> = (0..9)
+            .map(|i| fpe.pseudonymize(&ranker.unrank(2, i).unwrap()).unwrap())
+            .collect();
+
+        assert_eq!(outputs.len(), 9);
+    }
+
+    #[test]
+    fn test_input_must_match() {
+        let fpe = Pseudonymizer::new(Dfa::compile("[0-9]{4}").unwrap(), b"k");
+
+        assert!(fpe.pseudonymize("12a4").is_err());
+        assert!(fpe.reveal("123").is_err());
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+
+use crate::common::TsegerError;
+
+/*
+* Character frequency tables for weighted sampling:
+*
+* - english() / german()  letter frequencies in running text, in percent
+* - learn(corpus)         relative frequencies of every character in the corpus
+*
+* A sampler takes one table for the whole pattern, it weighs the characters of
+* every range against each other but never one range or branch against another.
+* Characters missing from a table weigh as much as an average character of the
+* table, so classes the table doesn't cover (digits for a letter table) stay
+* uniform; a table learned from lowercase text leaves [A-Z] uniform the same way.
+* Built-in tables are case-insensitive.
+*/
+
+const ENGLISH: [(char, f64); 26] = [
+    ('a', 8.167),
+    ('b', 1.492),
+    ('c', 2.782),
+    ('d', 4.253),
+    ('e', 12.702),
+    ('f', 2.228),
+    ('g', 2.015),
+    ('h', 6.094),
+    ('i', 6.966),
+    ('j', 0.153),
+    ('k', 0.772),
+    ('l', 4.025),
+    ('m', 2.406),
+    ('n', 6.749),
+    ('o', 7.507),
+    ('p', 1.929),
+    ('q', 0.095),
+    ('r', 5.987),
+    ('s', 6.327),
+    ('t', 9.056),
+    ('u', 2.758),
+    ('v', 0.978),
+    ('w', 2.360),
+    ('x', 0.150),
+    ('y', 1.974),
+    ('z', 0.074),
+];
+
+const GERMAN: [(char, f64); 30] = [
+    ('a', 6.516),
+    ('b', 1.886),
+    ('c', 2.732),
+    ('d', 5.076),
+    ('e', 16.396),
+    ('f', 1.656),
+    ('g', 3.009),
+    ('h', 4.577),
+    ('i', 6.550),
+    ('j', 0.268),
+    ('k', 1.417),
+    ('l', 3.437),
+    ('m', 2.534),
+    ('n', 9.776),
+    ('o', 2.594),
+    ('p', 0.670),
+    ('q', 0.018),
+    ('r', 7.003),
+    ('s', 7.270),
+    ('t', 6.154),
+    ('u', 4.166),
+    ('v', 0.846),
+    ('w', 1.921),
+    ('x', 0.034),
+    ('y', 0.039),
+    ('z', 1.134),
+    ('ä', 0.578),
+    ('ö', 0.443),
+    ('ü', 0.995),
+    ('ß', 0.307),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrequencyTable {
+    weights: BTreeMap<char, f64>,
+    default: f64,
+}
+
+impl FrequencyTable {
+    pub fn english() -> Self {
+        Self::case_insensitive(&ENGLISH)
+    }
+
+    pub fn german() -> Self {
+        Self::case_insensitive(&GERMAN)
+    }
+
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "english" => Some(Self::english()),
+            "german" => Some(Self::german()),
+            _ => None,
+        }
+    }
+
+    /// Frequencies of every character in the corpus, case-sensitive.
+    pub fn learn(corpus: &str) -> Result<Self, TsegerError> {
+        let mut counts: BTreeMap<char, f64> = BTreeMap::new();
+        for c in corpus.chars().filter(|c| !c.is_whitespace()) {
+            *counts.entry(c).or_default() += 1.0;
+        }
+
+        Self::from_weights(counts)
+    }
+
+    /// Table from raw weights, they don't need to add up to anything.
+    pub fn from_weights(
+        weights: impl IntoIterator<Item = (char, f64)>,
+    ) -> Result<Self, TsegerError> {
+        let weights: BTreeMap<char, f64> = weights.into_iter().collect();
+
+        if weights.is_empty() {
+            return Err(TsegerError::GeneratorError(
+                "Frequency table has no characters".to_string(),
+            ));
+        }
+        if let Some((c, w)) = weights.iter().find(|(_, w)| !w.is_finite() || **w <= 0.0) {
+            return Err(TsegerError::GeneratorError(format!(
+                "Frequency of {:?} must be positive, got {}",
+                c, w
+            )));
+        }
+
+        let default = weights.values().sum::<f64>() / weights.len() as f64;
+        Ok(Self { weights, default })
+    }
+
+    pub fn weight(&self, c: char) -> f64 {
+        self.weights.get(&c).copied().unwrap_or(self.default)
+    }
+
+    /// Weight of characters the table doesn't list.
+    pub fn default_weight(&self) -> f64 {
+        self.default
+    }
+
+    /// Listed characters in `from..=to`, with their weights.
+    pub fn listed(&self, from: char, to: char) -> impl Iterator<Item = (char, f64)> + '_ {
+        self.weights.range(from..=to).map(|(&c, &w)| (c, w))
+    }
+
+    fn case_insensitive(letters: &[(char, f64)]) -> Self {
+        let upper = letters.iter().filter_map(|&(c, w)| {
+            let mut upper = c.to_uppercase();
+            match (upper.next(), upper.next()) {
+                (Some(u), None) if u != c => Some((u, w)),
+                _ => None,
+            }
+        });
+
+        Self::from_weights(letters.iter().copied().chain(upper)).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_tables() {
+        let english = FrequencyTable::english();
+
+        assert!(english.weight('e') > english.weight('z'));
+        assert_eq!(english.weight('E'), english.weight('e'));
+        assert_eq!(english.weight('7'), english.default_weight());
+        assert!(FrequencyTable::german().weight('ß') > 0.0);
+        assert_eq!(
+            FrequencyTable::german().weight('ẞ'),
+            FrequencyTable::german().default_weight()
+        );
+    }
+
+    #[test]
+    fn test_learned_table() {
+        let table = FrequencyTable::learn("aaab ba\n").unwrap();
+
+        assert_eq!(table.weight('a'), 4.0);
+        assert_eq!(table.weight('b'), 2.0);
+        assert_eq!(table.default_weight(), 3.0);
+        assert_eq!(table.listed('a', 'a').count(), 1);
+
+        assert!(FrequencyTable::learn("  ").is_err());
+        assert!(FrequencyTable::from_weights([('a', 0.0)]).is_err());
+    }
+}
@@ -0,0 +1,747 @@
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+use rand::Rng;
+use rand::seq::IndexedRandom;
+
+use crate::charset::CharSet;
+use crate::common::TsegerError;
+use crate::dfa::Dfa;
+use crate::frequency::FrequencyTable;
+use crate::nfa::StateId;
+
+/*
+* String generation from a compiled pattern:
+*
+* - Sampler       random matches, length is uniform over the feasible lengths and
+*                 the string is uniform among the matches of that length; a
+*                 frequency table only changes which character a range gives,
+*                 branches and lengths are picked as without it
+* - Enumerator    every match in shortlex order (length first, then by scalar value)
+* - coverage      short matches that walk every transition, hitting both ends of
+*                 every character range
+* - NegativeSampler  near misses, random matches with one mutation that breaks them
+*
+* Infinite languages are cut at max_len characters.
+*/
+
+const NEGATIVE_ATTEMPTS: usize = 1000;
+
+type Piece = (char, char, StateId);
+
+// Shared tables, pieces are transitions split into ranges and sorted by character
+struct Tables {
+    pieces: Vec<Vec<Piece>>,
+    // feasible[r][state] - some match of exactly r characters starts in state
+    feasible: Vec<Vec<bool>>,
+}
+
+impl Tables {
+    fn new(dfa: &Dfa, max_len: usize) -> Self {
+        let pieces: Vec<Vec<Piece>> = dfa
+            .states()
+            .iter()
+            .map(|state| {
+                let mut pieces: Vec<Piece> = state
+                    .transitions
+                    .iter()
+                    .flat_map(|(set, target)| {
+                        set.ranges().iter().map(|&(from, to)| (from, to, *target))
+                    })
+                    .collect();
+                pieces.sort_unstable();
+                pieces
+            })
+            .collect();
+
+        let mut feasible = vec![dfa.states().iter().map(|s| s.accepting).collect::<Vec<_>>()];
+        for r in 1..=max_len {
+            let previous = &feasible[r - 1];
+            let row = pieces
+                .iter()
+                .map(|pieces| pieces.iter().any(|&(_, _, target)| previous[target]))
+                .collect();
+            feasible.push(row);
+        }
+
+        Self { pieces, feasible }
+    }
+
+    fn lengths(&self, start: StateId) -> Vec<usize> {
+        (0..self.feasible.len())
+            .filter(|&len| self.feasible[len][start])
+            .collect()
+    }
+}
+
+fn piece_size(&(from, to, _): &Piece) -> u32 {
+    to as u32 - from as u32 + 1
+}
+
+fn piece_char(&(from, _, _): &Piece, offset: u32) -> char {
+    // Pieces never span surrogates
+    char::from_u32(from as u32 + offset).unwrap()
+}
+
+pub struct Sampler {
+    start: StateId,
+    tables: Tables,
+    lengths: Vec<usize>,
+    // masses[state][piece] - total frequency of the characters in a piece, empty when unweighted
+    masses: Vec<Vec<f64>>,
+    frequencies: Option<FrequencyTable>,
+    // weights[r][state] - matches of r characters from state, scaled so each row peaks at 1.0
+    weights: Vec<Vec<f64>>,
+    count: Option<u128>,
+}
+
+impl Sampler {
+    pub fn new(dfa: &Dfa, max_len: usize) -> Result<Self, TsegerError> {
+        Self::build(dfa, max_len, None)
+    }
+
+    /// Characters are drawn in proportion to their frequency instead of uniformly.
+    /// The one table covers every character range of the pattern, within each
+    /// range, so which branch and which length is taken stays as with new().
+    pub fn weighted(
+        dfa: &Dfa,
+        max_len: usize,
+        frequencies: FrequencyTable,
+    ) -> Result<Self, TsegerError> {
+        Self::build(dfa, max_len, Some(frequencies))
+    }
+
+    fn build(
+        dfa: &Dfa,
+        max_len: usize,
+        frequencies: Option<FrequencyTable>,
+    ) -> Result<Self, TsegerError> {
+        let tables = Tables::new(dfa, max_len);
+        let lengths = tables.lengths(dfa.start());
+
+        if lengths.is_empty() {
+            return Err(TsegerError::GeneratorError(format!(
+                "Pattern has no matches of at most {} characters",
+                max_len
+            )));
+        }
+
+        let masses: Vec<Vec<f64>> = match &frequencies {
+            Some(frequencies) => tables
+                .pieces
+                .iter()
+                .map(|pieces| {
+                    pieces
+                        .iter()
+                        .map(|piece| piece_mass(piece, frequencies))
+                        .collect()
+                })
+                .collect(),
+            None => vec![],
+        };
+
+        let mut weights = vec![
+            dfa.states()
+                .iter()
+                .map(|s| f64::from(u8::from(s.accepting)))
+                .collect::<Vec<f64>>(),
+        ];
+        for r in 1..=max_len {
+            let previous = &weights[r - 1];
+            let mut row: Vec<f64> = tables
+                .pieces
+                .iter()
+                .map(|pieces| {
+                    pieces
+                        .iter()
+                        .map(|piece| f64::from(piece_size(piece)) * previous[piece.2])
+                        .sum()
+                })
+                .collect();
+
+            let peak = row.iter().cloned().fold(0.0, f64::max);
+            if peak > 0.0 {
+                row.iter_mut().for_each(|w| *w /= peak);
+            }
+            weights.push(row);
+        }
+
+        Ok(Self {
+            start: dfa.start(),
+            count: count_matches(&tables, dfa),
+            tables,
+            lengths,
+            masses,
+            frequencies,
+            weights,
+        })
+    }
+
+    pub fn lengths(&self) -> &[usize] {
+        &self.lengths
+    }
+
+    /// Matches of at most max_len characters, None if there are more than u128::MAX.
+    pub fn count(&self) -> Option<u128> {
+        self.count
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        let mut result = String::new();
+        self.sample_into(rng, &mut result);
+        result
+    }
+
+    /// Appends a random match to `out`, allocation free once `out` has grown.
+    pub fn sample_into<R: Rng + ?Sized>(&self, rng: &mut R, out: &mut String) {
+        let len = *self.lengths.choose(rng).unwrap();
+        let found = self.sample_len_into(len, rng, out);
+        debug_assert!(found, "lengths only holds feasible lengths");
+    }
+
+    /// Random match of exactly `len` characters, None if there is none. Unweighted
+    /// samplers pick uniformly among all such matches.
+    pub fn sample_len<R: Rng + ?Sized>(&self, len: usize, rng: &mut R) -> Option<String> {
+        let mut result = String::with_capacity(len);
+        self.sample_len_into(len, rng, &mut result)
+            .then_some(result)
+    }
+
+    /// sample_len appending to `out`, false and nothing appended if there is no match.
+    pub fn sample_len_into<R: Rng + ?Sized>(
+        &self,
+        len: usize,
+        rng: &mut R,
+        out: &mut String,
+    ) -> bool {
+        if len >= self.tables.feasible.len() || !self.tables.feasible[len][self.start] {
+            return false;
+        }
+
+        let mut state = self.start;
+        for position in 0..len {
+            let index = self.pick_piece(state, len - position - 1, rng);
+            let piece = &self.tables.pieces[state][index];
+            out.push(self.piece_sample(state, index, rng));
+            state = piece.2;
+        }
+
+        true
+    }
+
+    // Weighted choice among the pieces that still reach a match in `remaining`
+    // characters, two passes over them instead of collecting the candidates
+    fn pick_piece<R: Rng + ?Sized>(&self, state: StateId, remaining: usize, rng: &mut R) -> usize {
+        let pieces = &self.tables.pieces[state];
+        let feasible = &self.tables.feasible[remaining];
+        let weights = &self.weights[remaining];
+        let candidates = || (0..pieces.len()).filter(|&i| feasible[pieces[i].2]);
+        let weight = |i: usize| f64::from(piece_size(&pieces[i])) * weights[pieces[i].2];
+
+        // Weights of tiny branches can underflow to zero, fall back to a uniform choice
+        let total: f64 = candidates().map(weight).sum();
+        if !(total > 0.0 && total.is_finite()) {
+            let n = candidates().count();
+            return candidates().nth(rng.random_range(0..n)).unwrap();
+        }
+
+        let mut target = rng.random_range(0.0..total);
+        let mut last = None;
+        for i in candidates() {
+            let w = weight(i);
+            if target < w {
+                return i;
+            }
+            target -= w;
+            if w > 0.0 {
+                last = Some(i);
+            }
+        }
+
+        // Only reachable through rounding
+        last.unwrap()
+    }
+
+    fn piece_sample<R: Rng + ?Sized>(&self, state: StateId, index: usize, rng: &mut R) -> char {
+        let piece = &self.tables.pieces[state][index];
+        let Some(frequencies) = &self.frequencies else {
+            return piece_char(piece, rng.random_range(0..piece_size(piece)));
+        };
+
+        // Listed characters carry their own weight, the rest share the default one
+        let mut target = rng.random_range(0.0..self.masses[state][index]);
+        for (c, weight) in frequencies.listed(piece.0, piece.1) {
+            if target < weight {
+                return c;
+            }
+            target -= weight;
+        }
+
+        let listed: Vec<char> = frequencies
+            .listed(piece.0, piece.1)
+            .map(|(c, _)| c)
+            .collect();
+        if listed.len() as u32 == piece_size(piece) {
+            // Only reachable through rounding
+            return *listed.last().unwrap();
+        }
+        loop {
+            let c = piece_char(piece, rng.random_range(0..piece_size(piece)));
+            if listed.binary_search(&c).is_err() {
+                return c;
+            }
+        }
+    }
+}
+
+fn count_matches(tables: &Tables, dfa: &Dfa) -> Option<u128> {
+    let mut row: Vec<Option<u128>> = dfa
+        .states()
+        .iter()
+        .map(|s| Some(u128::from(s.accepting)))
+        .collect();
+    let mut total = row[dfa.start()];
+
+    for _ in 1..tables.feasible.len() {
+        // A state that overflows makes every state leading to it overflow too
+        row = tables
+            .pieces
+            .iter()
+            .map(|pieces| {
+                pieces.iter().try_fold(0u128, |sum, piece| {
+                    sum.checked_add(u128::from(piece_size(piece)).checked_mul(row[piece.2]?)?)
+                })
+            })
+            .collect();
+        total = total?.checked_add(row[dfa.start()]?);
+    }
+
+    total
+}
+
+fn piece_mass(piece: &Piece, frequencies: &FrequencyTable) -> f64 {
+    let size = f64::from(piece_size(piece));
+
+    let (count, total) = frequencies
+        .listed(piece.0, piece.1)
+        .fold((0.0, 0.0), |(count, total), (_, w)| {
+            (count + 1.0, total + w)
+        });
+
+    (size - count) * frequencies.default_weight() + total
+}
+
+/// Every match up to `max_len` characters, in shortlex order.
+pub struct Enumerator {
+    start: StateId,
+    tables: Tables,
+    lengths: VecDeque<usize>,
+    len: usize,
+    // Current string as (state before the char, piece index, offset in piece)
+    path: Vec<(StateId, usize, u32)>,
+    fresh: bool,
+}
+
+impl Enumerator {
+    pub fn new(dfa: &Dfa, max_len: usize) -> Self {
+        let tables = Tables::new(dfa, max_len);
+        let lengths = tables.lengths(dfa.start()).into();
+
+        Self {
+            start: dfa.start(),
+            tables,
+            lengths,
+            len: 0,
+            path: Vec::new(),
+            fresh: true,
+        }
+    }
+
+    // Extends the path with the smallest feasible characters up to the current length
+    fn descend(&mut self) {
+        while self.path.len() < self.len {
+            let state = match self.path.last() {
+                None => self.start,
+                Some(&(state, piece, _)) => self.tables.pieces[state][piece].2,
+            };
+            let remaining = self.len - self.path.len() - 1;
+            let piece = self.first_piece(state, remaining, 0).unwrap();
+            self.path.push((state, piece, 0));
+        }
+    }
+
+    fn first_piece(&self, state: StateId, remaining: usize, from: usize) -> Option<usize> {
+        (from..self.tables.pieces[state].len())
+            .find(|&i| self.tables.feasible[remaining][self.tables.pieces[state][i].2])
+    }
+
+    fn advance(&mut self) -> bool {
+        while let Some((state, piece, offset)) = self.path.pop() {
+            let remaining = self.len - self.path.len() - 1;
+
+            if offset + 1 < piece_size(&self.tables.pieces[state][piece]) {
+                self.path.push((state, piece, offset + 1));
+                self.descend();
+                return true;
+            }
+            if let Some(next) = self.first_piece(state, remaining, piece + 1) {
+                self.path.push((state, next, 0));
+                self.descend();
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn current(&self) -> String {
+        self.path
+            .iter()
+            .map(|&(state, piece, offset)| piece_char(&self.tables.pieces[state][piece], offset))
+            .collect()
+    }
+}
+
+impl Iterator for Enumerator {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if !self.fresh && self.advance() {
+            return Some(self.current());
+        }
+
+        self.len = self.lengths.pop_front()?;
+        self.fresh = false;
+        self.path.clear();
+        self.descend();
+
+        Some(self.current())
+    }
+}
+
+/// Short matches that together use every transition, with both ends of every range.
+pub fn coverage(dfa: &Dfa) -> Vec<String> {
+    let states = dfa.states();
+
+    // Shortest prefix reaching every state
+    let mut prefixes: Vec<Option<String>> = vec![None; states.len()];
+    prefixes[dfa.start()] = Some(String::new());
+    let mut queue = VecDeque::from([dfa.start()]);
+    while let Some(state) = queue.pop_front() {
+        for (set, target) in &states[state].transitions {
+            if prefixes[*target].is_none() {
+                let mut prefix = prefixes[state].clone().unwrap();
+                prefix.push(set.first().unwrap());
+                prefixes[*target] = Some(prefix);
+                queue.push_back(*target);
+            }
+        }
+    }
+
+    // Shortest suffix from every state to an accepting one, built backwards
+    let mut suffixes: Vec<Option<String>> = states
+        .iter()
+        .map(|s| s.accepting.then(String::new))
+        .collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (state, dfa_state) in states.iter().enumerate() {
+            for (set, target) in &dfa_state.transitions {
+                if let Some(suffix) = &suffixes[*target] {
+                    let candidate_len = suffix.chars().count() + 1;
+                    let better = suffixes[state]
+                        .as_ref()
+                        .is_none_or(|current| candidate_len < current.chars().count());
+                    if better {
+                        suffixes[state] = Some(format!("{}{}", set.first().unwrap(), suffix));
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+    let mut push = |s: String| {
+        if seen.insert(s.clone()) {
+            result.push(s);
+        }
+    };
+
+    if let Some(shortest) = &suffixes[dfa.start()] {
+        push(shortest.clone());
+    }
+
+    for (state, dfa_state) in states.iter().enumerate() {
+        let Some(prefix) = &prefixes[state] else {
+            continue;
+        };
+        for (set, target) in &dfa_state.transitions {
+            let suffix = suffixes[*target].as_ref().unwrap();
+            for &(from, to) in set.ranges() {
+                push(format!("{}{}{}", prefix, from, suffix));
+                push(format!("{}{}{}", prefix, to, suffix));
+            }
+        }
+    }
+
+    result
+}
+
+/// Matches that are simpler than `input`, most promising first: the shortest match,
+/// then `input` with chunks removed, then with single characters lowered. Every
+/// candidate is shorter, or as long and lexicographically smaller.
+pub fn shrink_candidates(dfa: &Dfa, input: &str) -> Vec<String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+    let mut push = |candidate: String| {
+        if dfa.is_match(&candidate) && seen.insert(candidate.clone()) {
+            result.push(candidate);
+        }
+    };
+
+    if let Some(shortest) = Enumerator::new(dfa, chars.len()).next()
+        && shortest.chars().count() < chars.len()
+    {
+        push(shortest);
+    }
+
+    let mut chunk = chars.len() / 2;
+    while chunk > 0 {
+        for start in 0..=chars.len() - chunk {
+            push(
+                chars[..start]
+                    .iter()
+                    .chain(&chars[start + chunk..])
+                    .collect(),
+            );
+        }
+        chunk /= 2;
+    }
+
+    let mut state = dfa.start();
+    for (i, &c) in chars.iter().enumerate() {
+        for (set, _) in &dfa.state(state).transitions {
+            let smallest = set.first().unwrap();
+            if smallest < c {
+                let mut lowered = chars.clone();
+                lowered[i] = smallest;
+                push(lowered.into_iter().collect());
+            }
+        }
+        match dfa.next(state, c) {
+            Some(next) => state = next,
+            None => break,
+        }
+    }
+
+    result
+}
+
+/// Near misses: matches with a single edit that makes them fail.
+pub struct NegativeSampler<'a> {
+    dfa: &'a Dfa,
+    sampler: Sampler,
+    // Characters mutations may introduce, e.g. 0x00-0xFF for byte patterns
+    alphabet: CharSet,
+}
+
+impl<'a> NegativeSampler<'a> {
+    pub fn new(dfa: &'a Dfa, max_len: usize) -> Result<Self, TsegerError> {
+        Ok(Self {
+            dfa,
+            sampler: Sampler::new(dfa, max_len)?,
+            alphabet: CharSet::any(),
+        })
+    }
+
+    pub fn alphabet(mut self, alphabet: CharSet) -> Self {
+        self.alphabet = alphabet;
+        self
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<String, TsegerError> {
+        for _ in 0..NEGATIVE_ATTEMPTS {
+            let mut chars: Vec<char> = self.sampler.sample(rng).chars().collect();
+
+            match rng.random_range(0..4) {
+                0 if !chars.is_empty() => {
+                    let position = rng.random_range(0..chars.len());
+                    match self.foreign_char(&chars[..position], rng) {
+                        Some(c) => chars[position] = c,
+                        None => continue,
+                    }
+                }
+                1 if !chars.is_empty() => {
+                    chars.remove(rng.random_range(0..chars.len()));
+                }
+                2 => {
+                    let position = rng.random_range(0..=chars.len());
+                    chars.insert(position, printable(rng));
+                }
+                _ => {
+                    let position = rng.random_range(0..=chars.len());
+                    chars.truncate(position);
+                }
+            }
+
+            let candidate: String = chars.into_iter().collect();
+            if !self.dfa.is_match(&candidate) {
+                return Ok(candidate);
+            }
+        }
+
+        Err(TsegerError::GeneratorError(format!(
+            "No non-matching string found in {} attempts, the pattern matches (almost) everything",
+            NEGATIVE_ATTEMPTS
+        )))
+    }
+
+    // Character that can't follow the prefix, readable ones preferred
+    fn foreign_char<R: Rng + ?Sized>(&self, prefix: &[char], rng: &mut R) -> Option<char> {
+        let mut state = self.dfa.start();
+        for &c in prefix {
+            state = self.dfa.next(state, c)?;
+        }
+
+        let allowed = self
+            .dfa
+            .state(state)
+            .transitions
+            .iter()
+            .fold(CharSet::empty(), |set, (transition, _)| {
+                set.union(transition)
+            });
+        let foreign = self.alphabet.difference(&allowed);
+        let readable = foreign.intersection(&CharSet::range(' ', '~'));
+        let pool = if readable.is_empty() {
+            foreign
+        } else {
+            readable
+        };
+
+        if pool.is_empty() {
+            return None;
+        }
+        pool.nth(rng.random_range(0..pool.len()))
+    }
+}
+
+fn printable<R: Rng + ?Sized>(rng: &mut R) -> char {
+    rng.random_range(' '..='~')
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+
+    #[test]
+    fn test_random_samples_match() {
+        let dfa = Dfa::compile("[A-Z]{2}-[0-9]{4}|x+").unwrap();
+        let sampler = Sampler::new(&dfa, 8).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+
+        assert_eq!(sampler.lengths(), &[1, 2, 3, 4, 5, 6, 7, 8]);
+        for _ in 0..200 {
+            assert!(dfa.is_match(&sampler.sample(&mut rng)));
+        }
+        assert_eq!(sampler.sample_len(3, &mut rng), Some("xxx".to_string()));
+        assert!(sampler.sample_len(9, &mut rng).is_none());
+    }
+
+    #[test]
+    fn test_sampling_is_uniform_within_a_length() {
+        let dfa = Dfa::compile("a[0-9]|[bc]").unwrap();
+        let sampler = Sampler::new(&dfa, 2).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let samples: HashSet<String> = (0..500)
+            .filter_map(|_| sampler.sample_len(2, &mut rng))
+            .collect();
+        assert_eq!(samples.len(), 10);
+    }
+
+    #[test]
+    fn test_weighted_sampling_follows_frequencies() {
+        let dfa = Dfa::compile("[a-z]{6}|[0-9]").unwrap();
+        let sampler = Sampler::weighted(&dfa, 6, FrequencyTable::english()).unwrap();
+        let mut rng = StdRng::seed_from_u64(11);
+
+        let text: String = (0..300)
+            .filter_map(|_| sampler.sample_len(6, &mut rng))
+            .collect();
+        let count = |c| text.chars().filter(|&x| x == c).count();
+        assert!(count('e') > 5 * count('k'));
+        assert!(count('z') < count('t'));
+
+        // Classes the table doesn't cover stay uniform
+        let digits: HashSet<String> = (0..300)
+            .filter_map(|_| sampler.sample_len(1, &mut rng))
+            .collect();
+        assert_eq!(digits.len(), 10);
+
+        // Frequencies act within a range, 'x' is rare but its branch is not
+        let dfa = Dfa::compile("x[a-j]|y[0-9]").unwrap();
+        let sampler = Sampler::weighted(&dfa, 2, FrequencyTable::english()).unwrap();
+        let xs = (0..2000)
+            .filter(|_| sampler.sample(&mut rng).starts_with('x'))
+            .count();
+        assert!((900..1100).contains(&xs), "{}", xs);
+    }
+
+    #[test]
+    fn test_empty_language_is_rejected() {
+        assert!(Sampler::new(&Dfa::compile("a{5}").unwrap(), 4).is_err());
+    }
+
+    #[test]
+    fn test_enumeration_is_shortlex() {
+        let dfa = Dfa::compile("[ab]{1,2}|c*").unwrap();
+        let all: Vec<String> = Enumerator::new(&dfa, 3).collect();
+
+        assert_eq!(
+            all,
+            vec!["", "a", "b", "c", "aa", "ab", "ba", "bb", "cc", "ccc"]
+        );
+    }
+
+    #[test]
+    fn test_coverage_hits_range_ends() {
+        let dfa = Dfa::compile("[a-c]x|[0-9]").unwrap();
+
+        assert_eq!(coverage(&dfa), vec!["0", "9", "ax", "cx"]);
+    }
+
+    #[test]
+    fn test_shrink_candidates_are_simpler_matches() {
+        let dfa = Dfa::compile("[a-z]+-[0-9]{2}").unwrap();
+        let candidates = shrink_candidates(&dfa, "hello-42");
+
+        assert_eq!(candidates[0], "a-00");
+        assert!(candidates.contains(&"hllo-42".to_string()));
+        assert!(candidates.contains(&"aello-42".to_string()));
+        assert!(candidates.contains(&"hello-02".to_string()));
+        for candidate in &candidates {
+            assert!(dfa.is_match(candidate));
+            assert!(candidate.len() < 8 || candidate.as_str() < "hello-42");
+        }
+        assert!(shrink_candidates(&dfa, "a-00").is_empty());
+    }
+
+    #[test]
+    fn test_negative_samples_never_match() {
+        let dfa = Dfa::compile("[0-9]{3}-[0-9]{2}").unwrap();
+        let negative = NegativeSampler::new(&dfa, 8).unwrap();
+        let mut rng = StdRng::seed_from_u64(3);
+
+        for _ in 0..100 {
+            assert!(!dfa.is_match(&negative.sample(&mut rng).unwrap()));
+        }
+    }
+}
@@ -0,0 +1,561 @@
+use std::collections::HashSet;
+
+use rand::Rng;
+use rand::RngCore;
+
+use crate::dawg::Wordlist;
+use crate::generate::Sampler;
+use crate::grammar::GrammarGenerator;
+
+/*
+* Common interface of everything that produces strings:
+*
+* - next_into() appends the next value to a buffer the caller reuses, false
+*   once the generator is exhausted, the RNG is passed in on every call
+* - size_hint() is how many values are left, like Iterator::size_hint
+* - cardinality() is an upper bound on the distinct values ever produced
+*
+* Adapters combine generators the way iterator adapters do:
+*
+*   Choice::new(["alice", "bob"])
+*       .zip(Choice::new(["@"]))
+*       .zip(Sampler::new(&Dfa::compile("[a-z]{3,8}\\.com")?, 16)?)
+*       .unique()
+*       .take(100)
+*
+* filter and unique give up after MAX_REJECTIONS values in a row are thrown
+* away, so a predicate nothing passes ends the stream instead of hanging.
+*/
+
+pub const MAX_REJECTIONS: usize = 10_000;
+
+/// Upper bound on the number of distinct values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cardinality {
+    Finite(u128),
+    Infinite,
+}
+
+impl Cardinality {
+    pub fn is_finite(self) -> bool {
+        matches!(self, Self::Finite(_))
+    }
+
+    pub fn min(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Finite(a), Self::Finite(b)) => Self::Finite(a.min(b)),
+            (Self::Finite(a), Self::Infinite) | (Self::Infinite, Self::Finite(a)) => {
+                Self::Finite(a)
+            }
+            (Self::Infinite, Self::Infinite) => Self::Infinite,
+        }
+    }
+
+    /// Counts past u128::MAX are as good as infinite.
+    pub fn plus(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Finite(a), Self::Finite(b)) => {
+                a.checked_add(b).map_or(Self::Infinite, Self::Finite)
+            }
+            _ => Self::Infinite,
+        }
+    }
+
+    pub fn times(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Finite(0), _) | (_, Self::Finite(0)) => Self::Finite(0),
+            (Self::Finite(a), Self::Finite(b)) => {
+                a.checked_mul(b).map_or(Self::Infinite, Self::Finite)
+            }
+            _ => Self::Infinite,
+        }
+    }
+
+    fn from_count(count: Option<u128>) -> Self {
+        count.map_or(Self::Infinite, Self::Finite)
+    }
+}
+
+pub trait Generator {
+    /// Appends the next value to `out`, false if there are no more values.
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool;
+
+    fn cardinality(&self) -> Cardinality;
+
+    /// Bounds on the values left, (0, None) when the generator can't tell.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, None)
+    }
+
+    fn next_string(&mut self, rng: &mut dyn RngCore) -> Option<String> {
+        let mut out = String::new();
+        self.next_into(rng, &mut out).then_some(out)
+    }
+
+    fn take(self, n: usize) -> Take<Self>
+    where
+        Self: Sized,
+    {
+        Take {
+            inner: self,
+            limit: n,
+            remaining: n,
+        }
+    }
+
+    fn unique(self) -> Unique<Self>
+    where
+        Self: Sized,
+    {
+        Unique {
+            inner: self,
+            seen: HashSet::new(),
+            scratch: String::new(),
+        }
+    }
+
+    fn map<F: FnMut(&str) -> String>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+    {
+        Map {
+            inner: self,
+            f,
+            scratch: String::new(),
+        }
+    }
+
+    fn filter<P: FnMut(&str) -> bool>(self, predicate: P) -> Filter<Self, P>
+    where
+        Self: Sized,
+    {
+        Filter {
+            inner: self,
+            predicate,
+        }
+    }
+
+    /// One value of each generator, concatenated.
+    fn zip<G: Generator>(self, other: G) -> Zip<Self, G>
+    where
+        Self: Sized,
+    {
+        Zip {
+            first: self,
+            second: other,
+        }
+    }
+
+    /// Values of both generators in turn, the rest of the other one once one runs out.
+    fn interleave<G: Generator>(self, other: G) -> Interleave<Self, G>
+    where
+        Self: Sized,
+    {
+        Interleave {
+            first: self,
+            second: other,
+            second_next: false,
+        }
+    }
+
+    fn iter<'r>(self, rng: &'r mut dyn RngCore) -> Iter<'r, Self>
+    where
+        Self: Sized,
+    {
+        Iter {
+            generator: self,
+            rng,
+        }
+    }
+}
+
+impl<G: Generator + ?Sized> Generator for Box<G> {
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        (**self).next_into(rng, out)
+    }
+
+    fn cardinality(&self) -> Cardinality {
+        (**self).cardinality()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (**self).size_hint()
+    }
+}
+
+/// Picks one of a fixed list of strings, all equally likely.
+#[derive(Debug, Clone)]
+pub struct Choice {
+    items: Vec<String>,
+}
+
+impl Choice {
+    pub fn new<S: Into<String>>(items: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            items: items.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Generator for Choice {
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        if self.items.is_empty() {
+            return false;
+        }
+        out.push_str(&self.items[rng.random_range(0..self.items.len())]);
+        true
+    }
+
+    fn cardinality(&self) -> Cardinality {
+        let distinct: HashSet<&String> = self.items.iter().collect();
+        Cardinality::Finite(distinct.len() as u128)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.items.is_empty() {
+            true => (0, Some(0)),
+            false => (0, None),
+        }
+    }
+}
+
+impl Generator for Sampler {
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        self.sample_into(rng, out);
+        true
+    }
+
+    fn cardinality(&self) -> Cardinality {
+        Cardinality::from_count(self.count())
+    }
+}
+
+impl Generator for Wordlist {
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        match self.sample(rng) {
+            Some(word) => {
+                out.push_str(&word);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn cardinality(&self) -> Cardinality {
+        Cardinality::Finite(self.count())
+    }
+}
+
+impl Generator for GrammarGenerator<'_> {
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        out.push_str(&self.generate(rng));
+        true
+    }
+
+    fn cardinality(&self) -> Cardinality {
+        Cardinality::Infinite
+    }
+}
+
+pub struct Take<G> {
+    inner: G,
+    limit: usize,
+    remaining: usize,
+}
+
+impl<G: Generator> Generator for Take<G> {
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        if self.remaining == 0 || !self.inner.next_into(rng, out) {
+            return false;
+        }
+        self.remaining -= 1;
+        true
+    }
+
+    fn cardinality(&self) -> Cardinality {
+        self.inner
+            .cardinality()
+            .min(Cardinality::Finite(self.limit as u128))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (low, high) = self.inner.size_hint();
+        (
+            low.min(self.remaining),
+            Some(high.map_or(self.remaining, |high| high.min(self.remaining))),
+        )
+    }
+}
+
+pub struct Unique<G> {
+    inner: G,
+    seen: HashSet<String>,
+    scratch: String,
+}
+
+impl<G: Generator> Generator for Unique<G> {
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        if let Cardinality::Finite(n) = self.inner.cardinality()
+            && self.seen.len() as u128 >= n
+        {
+            return false;
+        }
+
+        for _ in 0..MAX_REJECTIONS {
+            self.scratch.clear();
+            if !self.inner.next_into(rng, &mut self.scratch) {
+                return false;
+            }
+            if !self.seen.contains(&self.scratch) {
+                self.seen.insert(self.scratch.clone());
+                out.push_str(&self.scratch);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn cardinality(&self) -> Cardinality {
+        self.inner.cardinality()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let high = match self.inner.cardinality() {
+            Cardinality::Finite(n) => {
+                usize::try_from(n.saturating_sub(self.seen.len() as u128)).ok()
+            }
+            Cardinality::Infinite => None,
+        };
+        (0, high)
+    }
+}
+
+pub struct Map<G, F> {
+    inner: G,
+    f: F,
+    scratch: String,
+}
+
+impl<G: Generator, F: FnMut(&str) -> String> Generator for Map<G, F> {
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        self.scratch.clear();
+        if !self.inner.next_into(rng, &mut self.scratch) {
+            return false;
+        }
+        out.push_str(&(self.f)(&self.scratch));
+        true
+    }
+
+    // Mapping can merge values but never split them
+    fn cardinality(&self) -> Cardinality {
+        self.inner.cardinality()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+pub struct Filter<G, P> {
+    inner: G,
+    predicate: P,
+}
+
+impl<G: Generator, P: FnMut(&str) -> bool> Generator for Filter<G, P> {
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        let start = out.len();
+
+        for _ in 0..MAX_REJECTIONS {
+            if !self.inner.next_into(rng, out) {
+                return false;
+            }
+            if (self.predicate)(&out[start..]) {
+                return true;
+            }
+            out.truncate(start);
+        }
+
+        false
+    }
+
+    fn cardinality(&self) -> Cardinality {
+        self.inner.cardinality()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
+
+pub struct Zip<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: Generator, B: Generator> Generator for Zip<A, B> {
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        let start = out.len();
+        if self.first.next_into(rng, out) && self.second.next_into(rng, out) {
+            return true;
+        }
+        out.truncate(start);
+        false
+    }
+
+    fn cardinality(&self) -> Cardinality {
+        self.first.cardinality().times(self.second.cardinality())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (first_low, first_high) = self.first.size_hint();
+        let (second_low, second_high) = self.second.size_hint();
+        let high = match (first_high, second_high) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (high, None) | (None, high) => high,
+        };
+        (first_low.min(second_low), high)
+    }
+}
+
+pub struct Interleave<A, B> {
+    first: A,
+    second: B,
+    second_next: bool,
+}
+
+impl<A: Generator, B: Generator> Generator for Interleave<A, B> {
+    fn next_into(&mut self, rng: &mut dyn RngCore, out: &mut String) -> bool {
+        self.second_next = !self.second_next;
+        match self.second_next {
+            false => self.second.next_into(rng, out) || self.first.next_into(rng, out),
+            true => self.first.next_into(rng, out) || self.second.next_into(rng, out),
+        }
+    }
+
+    fn cardinality(&self) -> Cardinality {
+        self.first.cardinality().plus(self.second.cardinality())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (first_low, first_high) = self.first.size_hint();
+        let (second_low, second_high) = self.second.size_hint();
+        let high = match (first_high, second_high) {
+            (Some(a), Some(b)) => a.checked_add(b),
+            _ => None,
+        };
+        let low = first_low
+            .checked_add(second_low)
+            .unwrap_or(first_low.max(second_low));
+        (low, high)
+    }
+}
+
+/// Iterator over the values of a generator, see Generator::iter.
+pub struct Iter<'r, G> {
+    generator: G,
+    rng: &'r mut dyn RngCore,
+}
+
+impl<G: Generator> Iterator for Iter<'_, G> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.generator.next_string(self.rng)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.generator.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dfa::Dfa;
+    use crate::random;
+
+    fn sampler(pattern: &str) -> Sampler {
+        Sampler::new(&Dfa::compile(pattern).unwrap(), 16).unwrap()
+    }
+
+    #[test]
+    fn test_cardinality_of_combined_generators() {
+        assert_eq!(sampler("[a-c]{2}").cardinality(), Cardinality::Finite(9));
+        assert_eq!(sampler("a*").cardinality(), Cardinality::Finite(17));
+        assert_eq!(sampler(".{16}").cardinality(), Cardinality::Infinite);
+
+        let emails = Choice::new(["alice", "bob", "bob"])
+            .zip(Choice::new(["@"]))
+            .zip(sampler("[xy]\\.com"));
+        assert_eq!(emails.cardinality(), Cardinality::Finite(4));
+        assert_eq!(
+            emails.interleave(sampler("[0-9]")).cardinality(),
+            Cardinality::Finite(14)
+        );
+        assert_eq!(
+            sampler("[a-z]+").take(3).cardinality(),
+            Cardinality::Finite(3)
+        );
+    }
+
+    #[test]
+    fn test_adapters() {
+        let mut rng = random::seeded(3);
+
+        let values: Vec<String> = sampler("[a-c]{2}").unique().iter(&mut rng).collect();
+        assert_eq!(values.len(), 9);
+        assert_eq!(values.iter().collect::<HashSet<_>>().len(), 9);
+
+        let values: Vec<String> = Choice::new(["a", "b"])
+            .interleave(Choice::new(["1"]).take(2))
+            .map(str::to_uppercase)
+            .take(6)
+            .iter(&mut rng)
+            .collect();
+        assert_eq!(values.len(), 6);
+        assert_eq!(values[1], "1");
+        assert_eq!(values[3], "1");
+        assert!(values[4..].iter().all(|v| v == "A" || v == "B"));
+
+        let mut even = sampler("[0-9]{1,3}").filter(|v| v.parse::<u32>().unwrap() % 2 == 0);
+        let mut out = String::from("> ");
+        assert!(even.next_into(&mut rng, &mut out));
+        assert!(out[2..].parse::<u32>().unwrap() % 2 == 0);
+
+        // Nothing passes, the stream ends instead of hanging
+        let mut none = sampler("a").filter(|v| v == "b");
+        assert_eq!(none.next_string(&mut rng), None);
+
+        // Generators that run out collect without reserving for values that never come
+        let words = Wordlist::from_words(&["red", "green"]).unwrap();
+        assert_eq!(words.size_hint(), (0, None));
+        let values: Vec<String> = words
+            .take(3)
+            .interleave(Choice::new(["blue"]).take(2))
+            .iter(&mut rng)
+            .collect();
+        assert_eq!(values.len(), 5);
+    }
+
+    #[test]
+    fn test_boxed_generators_mix() {
+        let mut rng = random::seeded(8);
+        let wordlist = Wordlist::from_words(&["red", "green"]).unwrap();
+
+        let generators: Vec<Box<dyn Generator>> = vec![
+            Box::new(wordlist),
+            Box::new(sampler("x[0-9a-f]{6}")),
+            Box::new(Choice::new(Vec::<String>::new())),
+        ];
+        let mut combined = generators
+            .into_iter()
+            .reduce(|a, b| Box::new(a.interleave(b)))
+            .unwrap();
+
+        for _ in 0..4 {
+            let value = combined.next_string(&mut rng).unwrap();
+            assert!(value == "red" || value == "green" || value.starts_with('x'));
+        }
+    }
+}
@@ -0,0 +1,675 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+use rand::seq::IndexedRandom;
+
+use crate::common::TsegerError;
+use crate::dfa::Dfa;
+use crate::generate::Sampler;
+
+/*
+* Context-free grammars in EBNF, with tseger regexes as terminals:
+*
+*   expr   = term , { ( "+" | "-" ) , term } ;
+*   term   = factor , { ( "*" | "/" ) , factor } ;
+*   factor = number | "(" , expr , ")" ;
+*   number = /[1-9][0-9]{0,3}/ ;
+*
+* - name = ... ;      production, `::=` works too, the first one is the start
+* - "..." '...'       literal terminals, with \n \t \\ \" \' escapes
+* - /.../             regex terminal in tseger syntax, \/ for a slash
+* - a , b   a b       concatenation, the comma is optional
+* - a | b             alternatives
+* - [ a ]  { a }      optional part and repetition (zero or more)
+* - ( a )             grouping
+* - (* ... *)         comments
+*
+* Generation is bounded by derivation depth and output size. Alternatives that
+* can't finish within the remaining depth are never picked, and once the output
+* reaches max_size every choice takes the shallowest way out.
+*/
+
+const DEFAULT_MAX_DEPTH: usize = 32;
+const DEFAULT_MAX_SIZE: usize = 1024;
+const DEFAULT_MAX_REPEAT: usize = 4;
+const DEFAULT_TERMINAL_LENGTH: usize = 16;
+
+type GrammarResult<T> = Result<T, TsegerError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Literal(String),
+    Regex(usize),
+    Rule(usize),
+    Sequence(Vec<Expr>),
+    Choice(Vec<Expr>),
+    Optional(Box<Expr>),
+    Repeat(Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    names: Vec<String>,
+    rules: Vec<Expr>,
+    regexes: Vec<Dfa>,
+    // min_depth[rule] - fewest nested rule expansions that derive a terminal string
+    min_depth: Vec<usize>,
+}
+
+impl Grammar {
+    pub fn parse(source: &str) -> GrammarResult<Self> {
+        let mut parser = GrammarParser {
+            chars: source.chars().collect(),
+            pos: 0,
+            names: Vec::new(),
+            index: HashMap::new(),
+            rules: Vec::new(),
+            regexes: Vec::new(),
+            references: Vec::new(),
+        };
+        parser.parse_grammar()?;
+
+        let GrammarParser {
+            names,
+            rules,
+            regexes,
+            references,
+            ..
+        } = parser;
+
+        for (name, at) in references {
+            if rules[name].is_none() {
+                return Err(TsegerError::GrammarError(format!(
+                    "Undefined rule '{}' at {}",
+                    names[name], at
+                )));
+            }
+        }
+
+        let rules: Vec<Expr> = rules.into_iter().map(Option::unwrap).collect();
+        let min_depth = min_depths(&rules)
+            .into_iter()
+            .zip(&names)
+            .map(|(depth, name)| {
+                depth.ok_or_else(|| {
+                    TsegerError::GrammarError(format!(
+                        "Rule '{}' never derives a finite string",
+                        name
+                    ))
+                })
+            })
+            .collect::<GrammarResult<Vec<usize>>>()?;
+
+        Ok(Self {
+            names,
+            rules,
+            regexes,
+            min_depth,
+        })
+    }
+
+    pub fn rules(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn start(&self) -> &str {
+        &self.names[0]
+    }
+}
+
+// Fixpoint over the rules, None means no finite derivation (yet)
+fn min_depths(rules: &[Expr]) -> Vec<Option<usize>> {
+    let mut depths = vec![None; rules.len()];
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+        for (i, rule) in rules.iter().enumerate() {
+            let depth = expr_depth(rule, &depths);
+            if depth.is_some() && (depths[i].is_none() || depth < depths[i]) {
+                depths[i] = depth;
+                changed = true;
+            }
+        }
+    }
+
+    depths
+}
+
+fn expr_depth(expr: &Expr, depths: &[Option<usize>]) -> Option<usize> {
+    match expr {
+        Expr::Literal(_) | Expr::Regex(_) => Some(0),
+        Expr::Rule(rule) => depths[*rule].map(|depth| depth + 1),
+        Expr::Sequence(items) => items
+            .iter()
+            .try_fold(0, |max, item| expr_depth(item, depths).map(|d| d.max(max))),
+        Expr::Choice(branches) => branches.iter().filter_map(|b| expr_depth(b, depths)).min(),
+        Expr::Optional(_) | Expr::Repeat(_) => Some(0),
+    }
+}
+
+pub struct GrammarGenerator<'a> {
+    grammar: &'a Grammar,
+    start: usize,
+    depths: Vec<Option<usize>>,
+    samplers: Vec<Sampler>,
+    max_depth: usize,
+    max_size: usize,
+    max_repeat: usize,
+}
+
+impl<'a> GrammarGenerator<'a> {
+    pub fn new(grammar: &'a Grammar) -> GrammarResult<Self> {
+        let samplers = grammar
+            .regexes
+            .iter()
+            .map(|dfa| Sampler::new(dfa, DEFAULT_TERMINAL_LENGTH))
+            .collect::<GrammarResult<Vec<Sampler>>>()?;
+
+        let generator = Self {
+            grammar,
+            start: 0,
+            depths: grammar.min_depth.iter().copied().map(Some).collect(),
+            samplers,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_size: DEFAULT_MAX_SIZE,
+            max_repeat: DEFAULT_MAX_REPEAT,
+        };
+        generator.check_depth()?;
+
+        Ok(generator)
+    }
+
+    /// Derives from `rule` instead of the first production.
+    pub fn start(mut self, rule: &str) -> GrammarResult<Self> {
+        self.start = self
+            .grammar
+            .names
+            .iter()
+            .position(|name| name == rule)
+            .ok_or_else(|| TsegerError::GrammarError(format!("Unknown rule '{}'", rule)))?;
+        self.check_depth()?;
+        Ok(self)
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> GrammarResult<Self> {
+        self.max_depth = max_depth;
+        self.check_depth()?;
+        Ok(self)
+    }
+
+    /// Soft limit on output characters, past it generation closes what is open.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Upper bound on iterations of `{ ... }`.
+    pub fn max_repeat(mut self, max_repeat: usize) -> Self {
+        self.max_repeat = max_repeat;
+        self
+    }
+
+    /// Longest match sampled for regex terminals.
+    pub fn terminal_length(mut self, max_len: usize) -> GrammarResult<Self> {
+        self.samplers = self
+            .grammar
+            .regexes
+            .iter()
+            .map(|dfa| Sampler::new(dfa, max_len))
+            .collect::<GrammarResult<Vec<Sampler>>>()?;
+        Ok(self)
+    }
+
+    pub fn generate<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        let mut out = Output::default();
+        self.expand(&Expr::Rule(self.start), self.max_depth, &mut out, rng);
+        out.text
+    }
+
+    fn check_depth(&self) -> GrammarResult<()> {
+        let needed = self.grammar.min_depth[self.start] + 1;
+        if needed > self.max_depth {
+            return Err(TsegerError::GrammarError(format!(
+                "Rule '{}' needs a derivation depth of at least {}, the limit is {}",
+                self.grammar.names[self.start], needed, self.max_depth
+            )));
+        }
+        Ok(())
+    }
+
+    fn fits(&self, expr: &Expr, depth: usize) -> bool {
+        expr_depth(expr, &self.depths).is_some_and(|needed| needed <= depth)
+    }
+
+    fn expand<R: Rng + ?Sized>(&self, expr: &Expr, depth: usize, out: &mut Output, rng: &mut R) {
+        let closing = out.chars >= self.max_size;
+
+        match expr {
+            Expr::Literal(text) => out.push(text),
+            Expr::Regex(regex) => out.push(&self.samplers[*regex].sample(rng)),
+            Expr::Rule(rule) => self.expand(&self.grammar.rules[*rule], depth - 1, out, rng),
+            Expr::Sequence(items) => {
+                for item in items {
+                    self.expand(item, depth, out, rng);
+                }
+            }
+            Expr::Choice(branches) => {
+                let branch = if closing {
+                    branches
+                        .iter()
+                        .min_by_key(|b| expr_depth(b, &self.depths).unwrap_or(usize::MAX))
+                } else {
+                    let fitting: Vec<&Expr> =
+                        branches.iter().filter(|b| self.fits(b, depth)).collect();
+                    fitting.choose(rng).copied()
+                };
+                // The depth check up front guarantees a fitting branch
+                self.expand(branch.unwrap(), depth, out, rng);
+            }
+            Expr::Optional(inner) => {
+                if !closing && self.fits(inner, depth) && rng.random_bool(0.5) {
+                    self.expand(inner, depth, out, rng);
+                }
+            }
+            Expr::Repeat(inner) => {
+                if closing || !self.fits(inner, depth) {
+                    return;
+                }
+                for _ in 0..rng.random_range(0..=self.max_repeat) {
+                    if out.chars >= self.max_size {
+                        break;
+                    }
+                    self.expand(inner, depth, out, rng);
+                }
+            }
+        }
+    }
+}
+
+// Generated text with its length in chars, which is what max_size counts
+#[derive(Default)]
+struct Output {
+    text: String,
+    chars: usize,
+}
+
+impl Output {
+    fn push(&mut self, s: &str) {
+        self.text.push_str(s);
+        self.chars += s.chars().count();
+    }
+}
+
+struct GrammarParser {
+    chars: Vec<char>,
+    pos: usize,
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    rules: Vec<Option<Expr>>,
+    regexes: Vec<Dfa>,
+    // Every rule reference with its position, checked once all rules are known
+    references: Vec<(usize, usize)>,
+}
+
+impl GrammarParser {
+    fn error(&self, msg: String, at: usize) -> TsegerError {
+        TsegerError::GrammarError(format!("{} at {}", msg, at))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn skip_trivia(&mut self) -> GrammarResult<()> {
+        loop {
+            if self.peek().is_some_and(char::is_whitespace) {
+                self.next();
+            } else if self.starts_with("(*") {
+                let start = self.pos;
+                self.pos += 2;
+                while !self.starts_with("*)") {
+                    if self.next().is_none() {
+                        return Err(self.error("Unterminated comment".to_string(), start));
+                    }
+                }
+                self.pos += 2;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> GrammarResult<()> {
+        self.skip_trivia()?;
+        if !self.starts_with(token) {
+            return Err(self.error(format!("Expected '{}'", token), self.pos));
+        }
+        self.pos += token.chars().count();
+        Ok(())
+    }
+
+    fn rule_id(&mut self, name: String) -> usize {
+        match self.index.get(&name) {
+            Some(&id) => id,
+            None => {
+                self.names.push(name.clone());
+                self.rules.push(None);
+                self.index.insert(name, self.names.len() - 1);
+                self.names.len() - 1
+            }
+        }
+    }
+
+    fn parse_grammar(&mut self) -> GrammarResult<()> {
+        self.skip_trivia()?;
+        if self.peek().is_none() {
+            return Err(self.error("Grammar has no rules".to_string(), 0));
+        }
+
+        while self.peek().is_some() {
+            let at = self.pos;
+            let name = self.parse_identifier()?;
+            let id = self.rule_id(name);
+            self.skip_trivia()?;
+            if self.starts_with("::=") {
+                self.pos += 3;
+            } else {
+                self.expect("=")?;
+            }
+
+            let body = self.parse_choice()?;
+            self.expect(";")?;
+
+            if self.rules[id].is_some() {
+                return Err(self.error(format!("Rule '{}' is defined twice", self.names[id]), at));
+            }
+            self.rules[id] = Some(body);
+            self.skip_trivia()?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_identifier(&mut self) -> GrammarResult<String> {
+        self.skip_trivia()?;
+        let start = self.pos;
+
+        let mut name = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_alphanumeric() || *c == '_' || (*c == '-' && !name.is_empty()))
+        {
+            name.push(c);
+            self.next();
+        }
+
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(self.error("Expected a rule name".to_string(), start));
+        }
+        Ok(name)
+    }
+
+    fn parse_choice(&mut self) -> GrammarResult<Expr> {
+        let mut branches = vec![self.parse_sequence()?];
+
+        loop {
+            self.skip_trivia()?;
+            if self.peek() != Some('|') {
+                break;
+            }
+            self.next();
+            branches.push(self.parse_sequence()?);
+        }
+
+        Ok(match branches.len() {
+            1 => branches.remove(0),
+            _ => Expr::Choice(branches),
+        })
+    }
+
+    fn parse_sequence(&mut self) -> GrammarResult<Expr> {
+        let mut items = Vec::new();
+
+        loop {
+            self.skip_trivia()?;
+            match self.peek() {
+                None | Some(';') | Some('|') | Some(')') | Some(']') | Some('}') => break,
+                Some(',') if !items.is_empty() => {
+                    self.next();
+                    self.skip_trivia()?;
+                }
+                _ => (),
+            }
+            items.push(self.parse_item()?);
+        }
+
+        if items.is_empty() {
+            return Err(self.error(
+                "Expected a terminal, a rule or a group".to_string(),
+                self.pos,
+            ));
+        }
+
+        Ok(match items.len() {
+            1 => items.remove(0),
+            _ => Expr::Sequence(items),
+        })
+    }
+
+    fn parse_item(&mut self) -> GrammarResult<Expr> {
+        let start = self.pos;
+
+        match self.peek() {
+            Some('"') | Some('\'') => self.parse_literal(),
+            Some('/') => self.parse_regex(),
+            Some('(') => {
+                self.next();
+                let inner = self.parse_choice()?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            Some('[') => {
+                self.next();
+                let inner = self.parse_choice()?;
+                self.expect("]")?;
+                Ok(Expr::Optional(Box::new(inner)))
+            }
+            Some('{') => {
+                self.next();
+                let inner = self.parse_choice()?;
+                self.expect("}")?;
+                Ok(Expr::Repeat(Box::new(inner)))
+            }
+            _ => {
+                let name = self.parse_identifier()?;
+                let id = self.rule_id(name);
+                self.references.push((id, start));
+                Ok(Expr::Rule(id))
+            }
+        }
+    }
+
+    fn parse_literal(&mut self) -> GrammarResult<Expr> {
+        let start = self.pos;
+        let quote = self.next().unwrap();
+        let mut text = String::new();
+
+        loop {
+            match self.next() {
+                None => return Err(self.error("Unterminated string".to_string(), start)),
+                Some(c) if c == quote => break,
+                Some('\\') => match self.next() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('r') => text.push('\r'),
+                    Some(c @ ('\\' | '"' | '\'')) => text.push(c),
+                    _ => return Err(self.error("Unknown escape".to_string(), self.pos - 1)),
+                },
+                Some(c) => text.push(c),
+            }
+        }
+
+        if text.is_empty() {
+            return Err(self.error("Empty string terminal".to_string(), start));
+        }
+        Ok(Expr::Literal(text))
+    }
+
+    fn parse_regex(&mut self) -> GrammarResult<Expr> {
+        let start = self.pos;
+        self.next();
+        let mut pattern = String::new();
+
+        loop {
+            match self.next() {
+                None => return Err(self.error("Unterminated regex".to_string(), start)),
+                Some('/') => break,
+                Some('\\') if self.peek() == Some('/') => {
+                    self.next();
+                    pattern.push('/');
+                }
+                Some(c) => pattern.push(c),
+            }
+        }
+
+        let dfa = Dfa::compile(&pattern)
+            .map_err(|e| self.error(format!("Invalid regex /{}/: {}", pattern, e), start))?;
+        if dfa.is_empty() {
+            return Err(self.error(format!("Regex /{}/ matches nothing", pattern), start));
+        }
+
+        self.regexes.push(dfa);
+        Ok(Expr::Regex(self.regexes.len() - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+
+    const ARITHMETIC: &str = r#"
+        (* integer arithmetic *)
+        expr   = term , { ( "+" | "-" ) , term } ;
+        term   = factor , { ( "*" | "/" ) , factor } ;
+        factor = number | "(" , expr , ")" ;
+        number = /[1-9][0-9]{0,3}/ ;
+    "#;
+
+    fn balanced(s: &str) -> bool {
+        let mut open = 0i32;
+        for c in s.chars() {
+            match c {
+                '(' => open += 1,
+                ')' => open -= 1,
+                _ => (),
+            }
+            if open < 0 {
+                return false;
+            }
+        }
+        open == 0
+    }
+
+    #[test]
+    fn test_arithmetic_expressions() {
+        let grammar = Grammar::parse(ARITHMETIC).unwrap();
+        let generator = GrammarGenerator::new(&grammar)
+            .unwrap()
+            .max_depth(12)
+            .unwrap();
+        let mut rng = StdRng::seed_from_u64(9);
+
+        assert_eq!(grammar.start(), "expr");
+        for _ in 0..200 {
+            let expr = generator.generate(&mut rng);
+            assert!(balanced(&expr), "{}", expr);
+            assert!(expr.chars().next().unwrap().is_ascii_digit() || expr.starts_with('('));
+        }
+    }
+
+    #[test]
+    fn test_size_limit_closes_nesting() {
+        let grammar =
+            Grammar::parse("list ::= '[' [ item { ',' item } ] ']' ; item = list | /[a-z]/ ;")
+                .unwrap();
+        let generator = GrammarGenerator::new(&grammar).unwrap().max_size(20);
+        let mut rng = StdRng::seed_from_u64(4);
+
+        for _ in 0..100 {
+            let list = generator.generate(&mut rng);
+            assert!(list.starts_with('[') && list.ends_with(']'));
+            assert!(list.len() < 200, "{}", list);
+        }
+
+        // The limit counts chars, two-byte items don't close the list any sooner
+        let accented =
+            Grammar::parse("list ::= '[' [ item { ',' item } ] ']' ; item = list | 'é' ;").unwrap();
+        let plain =
+            Grammar::parse("list ::= '[' [ item { ',' item } ] ']' ; item = list | 'e' ;").unwrap();
+        let accented = GrammarGenerator::new(&accented).unwrap().max_size(20);
+        let plain = GrammarGenerator::new(&plain).unwrap().max_size(20);
+        for seed in 0..50 {
+            assert_eq!(
+                accented
+                    .generate(&mut StdRng::seed_from_u64(seed))
+                    .replace('é', "e"),
+                plain.generate(&mut StdRng::seed_from_u64(seed))
+            );
+        }
+    }
+
+    #[test]
+    fn test_depth_is_checked_up_front() {
+        let grammar = Grammar::parse("a = b ; b = c ; c = 'x' ;").unwrap();
+
+        assert!(
+            GrammarGenerator::new(&grammar)
+                .unwrap()
+                .max_depth(3)
+                .is_ok()
+        );
+        assert!(
+            GrammarGenerator::new(&grammar)
+                .unwrap()
+                .max_depth(2)
+                .is_err()
+        );
+        assert!(
+            GrammarGenerator::new(&grammar)
+                .unwrap()
+                .start("c")
+                .unwrap()
+                .max_depth(1)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_invalid_grammars() {
+        assert!(Grammar::parse("").is_err());
+        assert!(Grammar::parse("a = b ;").is_err());
+        assert!(Grammar::parse("a = 'x' ; a = 'y' ;").is_err());
+        assert!(Grammar::parse("a = 'x' , a ;").is_err());
+        assert!(Grammar::parse("a = /[/ ;").is_err());
+        assert!(Grammar::parse("a = 'x' (* open").is_err());
+        assert!(Grammar::parse("a = 'x'").is_err());
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use crate::common::TsegerError;
+use crate::parser::BracketExpressionItem;
+use crate::parser::RegexAST;
+
+/*
+* Pattern inference from sample strings:
+*
+* - samples are split into runs of ASCII uppercase, lowercase and digits, every
+*   other character is a token on its own
+* - samples with the same run layout are merged, runs become classes with
+*   observed length ranges, e.g. AB-1234 and XY-0042 give [A-Z]{2}-[0-9]{4}
+* - runs with the same value in every sample stay literal
+* - each distinct layout is an alternative, too many alternatives collapse
+*   into a single class of every observed character
+* - ] \\ ^ and - are kept out of classes, they are alternatives of their own
+*/
+
+const DEFAULT_MAX_ALTERNATIVES: usize = 4;
+// Can't be written as is inside a bracket expression
+const BRACKET_SPECIALS: [char; 4] = [']', '\\', '^', '-'];
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+enum TokenKind {
+    Upper,
+    Lower,
+    Digit,
+    Other,
+}
+
+impl TokenKind {
+    fn of(c: char) -> Self {
+        match c {
+            'A'..='Z' => TokenKind::Upper,
+            'a'..='z' => TokenKind::Lower,
+            '0'..='9' => TokenKind::Digit,
+            _ => TokenKind::Other,
+        }
+    }
+
+    fn range(&self) -> Option<(char, char)> {
+        match self {
+            TokenKind::Upper => Some(('A', 'Z')),
+            TokenKind::Lower => Some(('a', 'z')),
+            TokenKind::Digit => Some(('0', '9')),
+            TokenKind::Other => None,
+        }
+    }
+}
+
+pub struct Inferrer {
+    max_alternatives: usize,
+}
+
+impl Default for Inferrer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Inferrer {
+    pub fn new() -> Self {
+        Self {
+            max_alternatives: DEFAULT_MAX_ALTERNATIVES,
+        }
+    }
+
+    pub fn max_alternatives(mut self, max_alternatives: usize) -> Self {
+        self.max_alternatives = max_alternatives.max(1);
+        self
+    }
+
+    pub fn infer<S: AsRef<str>>(&self, samples: &[S]) -> Result<RegexAST, TsegerError> {
+        if samples.is_empty() {
+            return Err(TsegerError::InferenceError(
+                "At least one sample is needed".to_string(),
+            ));
+        }
+
+        let mut has_empty = false;
+        let mut layouts: Vec<Vec<TokenKind>> = Vec::new();
+        let mut groups: HashMap<Vec<TokenKind>, Vec<Vec<String>>> = HashMap::new();
+
+        for sample in samples {
+            let tokens = tokenize(sample.as_ref());
+            if tokens.is_empty() {
+                has_empty = true;
+                continue;
+            }
+
+            let layout: Vec<TokenKind> = tokens.iter().map(|(kind, _)| *kind).collect();
+            if !groups.contains_key(&layout) {
+                layouts.push(layout.clone());
+            }
+            groups
+                .entry(layout)
+                .or_default()
+                .push(tokens.into_iter().map(|(_, value)| value).collect());
+        }
+
+        let ast = if layouts.is_empty() {
+            RegexAST::Concat(vec![])
+        } else if layouts.len() > self.max_alternatives {
+            collapse(samples)
+        } else {
+            // Most common layouts go first
+            layouts.sort_by_key(|layout| std::cmp::Reverse(groups[layout].len()));
+
+            let mut branches: Vec<RegexAST> = Vec::new();
+            for layout in &layouts {
+                let branch = merge_layout(layout, &groups[layout], samples.len());
+                if !branches.contains(&branch) {
+                    branches.push(branch);
+                }
+            }
+
+            match branches.len() {
+                1 => branches.remove(0),
+                _ => RegexAST::Alteration(branches),
+            }
+        };
+
+        if has_empty && !layouts.is_empty() {
+            Ok(RegexAST::Question(Box::new(RegexAST::Group(Box::new(ast)))))
+        } else {
+            Ok(ast)
+        }
+    }
+}
+
+fn tokenize(sample: &str) -> Vec<(TokenKind, String)> {
+    let mut tokens: Vec<(TokenKind, String)> = Vec::new();
+
+    for c in sample.chars() {
+        let kind = TokenKind::of(c);
+        match tokens.last_mut() {
+            Some((last, value)) if *last == kind && kind != TokenKind::Other => value.push(c),
+            _ => tokens.push((kind, c.to_string())),
+        }
+    }
+
+    tokens
+}
+
+// Every column of a layout is merged into a literal, a class run or a set of single characters
+fn merge_layout(layout: &[TokenKind], rows: &[Vec<String>], total: usize) -> RegexAST {
+    let mut nodes = Vec::new();
+
+    for (column, kind) in layout.iter().enumerate() {
+        let values: BTreeSet<&str> = rows.iter().map(|row| row[column].as_str()).collect();
+
+        // A single sample says nothing about which runs are fixed, only separators stay literal
+        let fixed = values.len() == 1 && (total > 1 || *kind == TokenKind::Other);
+
+        if fixed {
+            nodes.extend(values.first().unwrap().chars().map(RegexAST::Literal));
+            continue;
+        }
+
+        match kind.range() {
+            Some((from, to)) => {
+                let min = values.iter().map(|v| v.chars().count()).min().unwrap() as u32;
+                let max = values.iter().map(|v| v.chars().count()).max().unwrap() as u32;
+                nodes.push(repeat(
+                    RegexAST::BracketExpression {
+                        negated: false,
+                        items: vec![BracketExpressionItem::Range(from, to)],
+                    },
+                    min,
+                    max,
+                ));
+            }
+            None => nodes.push(class_of(values.iter().flat_map(|v| v.chars()))),
+        }
+    }
+
+    match nodes.len() {
+        1 => nodes.remove(0),
+        _ => RegexAST::Concat(nodes),
+    }
+}
+
+// Fallback for heterogeneous samples: one class of everything seen, with the observed length range
+fn collapse<S: AsRef<str>>(samples: &[S]) -> RegexAST {
+    let mut kinds = BTreeSet::new();
+    let mut others = BTreeSet::new();
+
+    for c in samples.iter().flat_map(|s| s.as_ref().chars()) {
+        match TokenKind::of(c) {
+            TokenKind::Other => {
+                others.insert(c);
+            }
+            kind => {
+                kinds.insert(kind.range().unwrap());
+            }
+        }
+    }
+
+    let lengths = samples.iter().map(|s| s.as_ref().chars().count() as u32);
+    let min = lengths.clone().min().unwrap();
+    let max = lengths.max().unwrap();
+
+    repeat(set_of(kinds, others), min, max)
+}
+
+fn class_of(chars: impl Iterator<Item = char>) -> RegexAST {
+    set_of(BTreeSet::new(), chars.collect())
+}
+
+// A class of the ranges and chars, special chars become literal alternatives next to it
+fn set_of(ranges: BTreeSet<(char, char)>, chars: BTreeSet<char>) -> RegexAST {
+    let (specials, chars): (Vec<char>, Vec<char>) = chars
+        .into_iter()
+        .partition(|c| BRACKET_SPECIALS.contains(c));
+
+    let mut items: Vec<BracketExpressionItem> = ranges
+        .into_iter()
+        .map(|(from, to)| BracketExpressionItem::Range(from, to))
+        .collect();
+    items.extend(chars.into_iter().map(BracketExpressionItem::Char));
+
+    let mut branches = match items.as_slice() {
+        [] => vec![],
+        [BracketExpressionItem::Char(c)] => vec![RegexAST::Literal(*c)],
+        _ => vec![RegexAST::BracketExpression {
+            negated: false,
+            items,
+        }],
+    };
+    branches.extend(specials.into_iter().map(RegexAST::Literal));
+
+    match branches.len() {
+        1 => branches.remove(0),
+        _ => RegexAST::Alteration(branches),
+    }
+}
+
+fn repeat(node: RegexAST, min: u32, max: u32) -> RegexAST {
+    if min == 1 && max == 1 {
+        node
+    } else {
+        RegexAST::Repetition {
+            node: Box::new(node),
+            min,
+            max: Some(max),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dfa::Dfa;
+
+    #[test]
+    fn test_plate_numbers() {
+        let ast = Inferrer::new()
+            .infer(&["AB-1234", "XY-0042", "KL-9001"])
+            .unwrap();
+
+        assert_eq!(ast.to_string(), "[A-Z]{2}-[0-9]{4}");
+    }
+
+    #[test]
+    fn test_fixed_literals_and_length_ranges() {
+        let ast = Inferrer::new()
+            .infer(&["ID_7", "ID_42", "ID_1337"])
+            .unwrap();
+
+        assert_eq!(ast.to_string(), "ID_[0-9]{1,4}");
+    }
+
+    #[test]
+    fn test_single_sample_is_generalised() {
+        let ast = Inferrer::new().infer(&["Berlin 10115"]).unwrap();
+
+        assert_eq!(ast.to_string(), "[A-Z][a-z]{5} [0-9]{5}");
+    }
+
+    #[test]
+    fn test_layouts_become_alternatives() {
+        let ast = Inferrer::new()
+            .infer(&["12:30", "09:15", "noon", ""])
+            .unwrap();
+
+        assert_eq!(ast.to_string(), "([0-9]{2}:[0-9]{2}|noon)?");
+    }
+
+    #[test]
+    fn test_too_many_layouts_collapse() {
+        let ast = Inferrer::new()
+            .max_alternatives(2)
+            .infer(&["a1", "B-2", "c_d", "9"])
+            .unwrap();
+
+        assert_eq!(ast.to_string(), "([0-9A-Za-z_]|-){1,3}");
+    }
+
+    #[test]
+    fn test_punctuation_stays_matched() {
+        let ast = Inferrer::new().infer(&["x]y", "x^y"]).unwrap();
+        assert_eq!(ast.to_string(), "x(\\]|\\^)y");
+
+        let punctuation: Vec<char> = (' '..='~').filter(|c| c.is_ascii_punctuation()).collect();
+        for width in 1..=4 {
+            let samples: Vec<String> = punctuation
+                .chunks(width)
+                .map(|chunk| format!("a{}1", chunk.iter().collect::<String>()))
+                .collect();
+
+            for inferrer in [Inferrer::new().max_alternatives(100), Inferrer::new()] {
+                let pattern = inferrer.infer(&samples).unwrap().to_string();
+                let dfa = Dfa::compile(&pattern).unwrap();
+                for sample in &samples {
+                    assert!(
+                        dfa.is_match(sample),
+                        "{} doesn't match {:?}",
+                        pattern,
+                        sample
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_no_samples() {
+        let samples: [&str; 0] = [];
+        assert!(Inferrer::new().infer(&samples).is_err());
+    }
+}
@@ -0,0 +1,435 @@
+use std::cell::RefCell;
+use std::cell::RefMut;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use rand::Rng;
+
+use crate::charset::CharSet;
+use crate::common::TsegerError;
+use crate::dfa;
+use crate::nfa::Nfa;
+use crate::nfa::StateId;
+use crate::parser::Parser;
+use crate::parser::RegexAST;
+
+/*
+* DFA built on demand from the NFA, for patterns whose full DFA blows up:
+*
+* - states are NFA subsets, created the first time matching or sampling
+*   reaches them and kept in a cache of at most `capacity` states
+* - a full cache is cleared and refilled from the current state, so old
+*   states are evicted wholesale (as RE2 does)
+* - a call that clears the cache more than `max_clears` times is thrashing,
+*   it finishes by simulating the NFA directly, one subset at a time, which
+*   needs no cache at all
+*
+* Sampling walks the automaton towards a random target length, it only
+* takes steps after which an accepting state stays within reach. Matches
+* are valid but, unlike Sampler, not uniformly distributed.
+*/
+
+const DEFAULT_CAPACITY: usize = 10_000;
+const DEFAULT_MAX_CLEARS: usize = 3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// States in the cache right now
+    pub states: usize,
+    /// Times the cache ran full and was emptied
+    pub clears: usize,
+    /// Calls that gave up on the cache and simulated the NFA
+    pub fallbacks: usize,
+}
+
+#[derive(Debug)]
+struct CachedState {
+    subset: BTreeSet<StateId>,
+    accepting: bool,
+    distance: usize,
+    // Filled the first time the state is left
+    transitions: Option<Vec<(CharSet, StateId)>>,
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+    states: Vec<CachedState>,
+    index: HashMap<BTreeSet<StateId>, StateId>,
+    stats: CacheStats,
+}
+
+// Position of a walk, a cached state or a bare subset once the cache is given up
+#[derive(Debug, Clone)]
+enum Cursor {
+    Cached(StateId),
+    Simulated(BTreeSet<StateId>),
+}
+
+pub struct LazyDfa {
+    nfa: Nfa,
+    // distances[s] - fewest characters from NFA state s to acceptance
+    distances: Vec<usize>,
+    capacity: usize,
+    max_clears: usize,
+    cache: RefCell<Cache>,
+}
+
+impl LazyDfa {
+    pub fn compile(pattern: &str) -> Result<Self, TsegerError> {
+        Self::from_ast(&Parser::new().parse(pattern)?)
+    }
+
+    pub fn from_ast(ast: &RegexAST) -> Result<Self, TsegerError> {
+        Ok(Self::from_nfa(Nfa::from_ast(ast)?))
+    }
+
+    pub fn from_nfa(nfa: Nfa) -> Self {
+        let distances = distances(&nfa);
+
+        Self {
+            nfa,
+            distances,
+            capacity: DEFAULT_CAPACITY,
+            max_clears: DEFAULT_MAX_CLEARS,
+            cache: RefCell::new(Cache::default()),
+        }
+    }
+
+    /// Most states kept at once, at least 2.
+    pub fn capacity(mut self, states: usize) -> Self {
+        self.capacity = states.max(2);
+        self.cache = RefCell::new(Cache::default());
+        self
+    }
+
+    /// Cache clears a single call may cause before it falls back to NFA simulation.
+    pub fn max_clears(mut self, clears: usize) -> Self {
+        self.max_clears = clears;
+        self
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let cache = self.cache.borrow();
+        CacheStats {
+            states: cache.states.len(),
+            ..cache.stats
+        }
+    }
+
+    pub fn is_match(&self, input: &str) -> bool {
+        let mut walk = Walk::new(self);
+        let mut cursor = walk.start();
+
+        for c in input.chars() {
+            match walk.step(&mut cursor, c) {
+                Some(next) => cursor = next,
+                None => return false,
+            }
+        }
+
+        walk.accepting(&cursor)
+    }
+
+    /// Random match of at most `max_len` characters.
+    pub fn sample<R: Rng + ?Sized>(
+        &self,
+        max_len: usize,
+        rng: &mut R,
+    ) -> Result<String, TsegerError> {
+        let mut walk = Walk::new(self);
+        let mut cursor = walk.start();
+
+        let shortest = walk.distance(&cursor);
+        if shortest > max_len {
+            return Err(TsegerError::GeneratorError(format!(
+                "Pattern has no matches of at most {} characters",
+                max_len
+            )));
+        }
+
+        let len = rng.random_range(shortest..=max_len);
+        let mut result = String::with_capacity(len);
+
+        for remaining in (0..len).rev() {
+            let candidates: Vec<(CharSet, Cursor)> = walk
+                .successors(&mut cursor)
+                .into_iter()
+                .filter(|(_, target)| walk.distance(target) <= remaining)
+                .collect();
+
+            // Nothing left within the target length, the state is accepting then
+            if candidates.is_empty() {
+                break;
+            }
+
+            let total: u32 = candidates.iter().map(|(set, _)| set.len()).sum();
+            let mut offset = rng.random_range(0..total);
+            for (set, target) in candidates {
+                if offset < set.len() {
+                    result.push(set.nth(offset).unwrap());
+                    cursor = target;
+                    break;
+                }
+                offset -= set.len();
+            }
+        }
+
+        debug_assert!(walk.accepting(&cursor));
+        Ok(result)
+    }
+
+    fn subset_distance(&self, subset: &BTreeSet<StateId>) -> usize {
+        subset
+            .iter()
+            .map(|&s| self.distances[s])
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
+    fn moves(&self, subset: &BTreeSet<StateId>) -> Vec<(CharSet, BTreeSet<StateId>)> {
+        let moves: Vec<&(CharSet, StateId)> = subset
+            .iter()
+            .flat_map(|&s| self.nfa.states()[s].transitions.iter())
+            .collect();
+
+        dfa::split_moves(&moves)
+            .into_iter()
+            .map(|(set, targets)| (set, self.nfa.epsilon_closure(targets)))
+            .collect()
+    }
+}
+
+// One call's view of the cache, counts its clears to notice thrashing
+struct Walk<'a> {
+    dfa: &'a LazyDfa,
+    cache: RefMut<'a, Cache>,
+    clears: usize,
+}
+
+impl<'a> Walk<'a> {
+    fn new(dfa: &'a LazyDfa) -> Self {
+        Self {
+            dfa,
+            cache: dfa.cache.borrow_mut(),
+            clears: 0,
+        }
+    }
+
+    fn start(&mut self) -> Cursor {
+        let subset = self.dfa.nfa.epsilon_closure([self.dfa.nfa.start()]);
+        if self.cache.states.len() >= self.dfa.capacity && !self.cache.index.contains_key(&subset) {
+            self.clear();
+        }
+        Cursor::Cached(self.insert(subset))
+    }
+
+    fn accepting(&self, cursor: &Cursor) -> bool {
+        match cursor {
+            Cursor::Cached(id) => self.cache.states[*id].accepting,
+            Cursor::Simulated(subset) => subset.contains(&self.dfa.nfa.accept()),
+        }
+    }
+
+    fn distance(&self, cursor: &Cursor) -> usize {
+        match cursor {
+            Cursor::Cached(id) => self.cache.states[*id].distance,
+            Cursor::Simulated(subset) => self.dfa.subset_distance(subset),
+        }
+    }
+
+    fn step(&mut self, cursor: &mut Cursor, c: char) -> Option<Cursor> {
+        if let Cursor::Simulated(subset) = cursor {
+            let targets: Vec<StateId> = subset
+                .iter()
+                .flat_map(|&s| self.dfa.nfa.states()[s].transitions.iter())
+                .filter(|(set, _)| set.contains(c))
+                .map(|&(_, target)| target)
+                .collect();
+
+            return (!targets.is_empty())
+                .then(|| Cursor::Simulated(self.dfa.nfa.epsilon_closure(targets)));
+        }
+
+        self.successors(cursor)
+            .into_iter()
+            .find(|(set, _)| set.contains(c))
+            .map(|(_, target)| target)
+    }
+
+    // Outgoing transitions, may move `cursor` if the cache has to be cleared
+    fn successors(&mut self, cursor: &mut Cursor) -> Vec<(CharSet, Cursor)> {
+        let id = match cursor {
+            Cursor::Simulated(subset) => {
+                return self
+                    .dfa
+                    .moves(subset)
+                    .into_iter()
+                    .map(|(set, target)| (set, Cursor::Simulated(target)))
+                    .collect();
+            }
+            Cursor::Cached(id) => *id,
+        };
+
+        if let Some(transitions) = &self.cache.states[id].transitions {
+            return cached(transitions);
+        }
+
+        let subset = self.cache.states[id].subset.clone();
+        let moves = self.dfa.moves(&subset);
+        let new = moves
+            .iter()
+            .map(|(_, target)| target)
+            .filter(|target| !self.cache.index.contains_key(*target))
+            .collect::<BTreeSet<_>>()
+            .len();
+
+        let mut id = id;
+        if self.cache.states.len() + new > self.dfa.capacity {
+            // Everything but the current state gets evicted
+            self.clear();
+            if self.clears > self.dfa.max_clears || moves.len() + 1 > self.dfa.capacity {
+                self.cache.stats.fallbacks += 1;
+                *cursor = Cursor::Simulated(subset);
+                return self.successors(cursor);
+            }
+            id = self.insert(subset);
+            *cursor = Cursor::Cached(id);
+        }
+
+        let transitions: Vec<(CharSet, StateId)> = moves
+            .into_iter()
+            .map(|(set, target)| (set, self.insert(target)))
+            .collect();
+        let result = cached(&transitions);
+        self.cache.states[id].transitions = Some(transitions);
+        result
+    }
+
+    fn insert(&mut self, subset: BTreeSet<StateId>) -> StateId {
+        if let Some(&id) = self.cache.index.get(&subset) {
+            return id;
+        }
+
+        let id = self.cache.states.len();
+        self.cache.states.push(CachedState {
+            accepting: subset.contains(&self.dfa.nfa.accept()),
+            distance: self.dfa.subset_distance(&subset),
+            subset: subset.clone(),
+            transitions: None,
+        });
+        self.cache.index.insert(subset, id);
+        id
+    }
+
+    fn clear(&mut self) {
+        self.cache.states.clear();
+        self.cache.index.clear();
+        self.cache.stats.clears += 1;
+        self.clears += 1;
+    }
+}
+
+fn cached(transitions: &[(CharSet, StateId)]) -> Vec<(CharSet, Cursor)> {
+    transitions
+        .iter()
+        .map(|(set, target)| (set.clone(), Cursor::Cached(*target)))
+        .collect()
+}
+
+// Epsilon moves cost nothing and character moves one, so a 0-1 BFS backwards from acceptance
+fn distances(nfa: &Nfa) -> Vec<usize> {
+    let mut reverse: Vec<Vec<(StateId, usize)>> = vec![Vec::new(); nfa.states().len()];
+    for (from, state) in nfa.states().iter().enumerate() {
+        for &to in &state.epsilon {
+            reverse[to].push((from, 0));
+        }
+        for &(_, to) in &state.transitions {
+            reverse[to].push((from, 1));
+        }
+    }
+
+    let mut distances = vec![usize::MAX; nfa.states().len()];
+    distances[nfa.accept()] = 0;
+    let mut queue = VecDeque::from([nfa.accept()]);
+
+    while let Some(state) = queue.pop_front() {
+        for &(from, cost) in &reverse[state] {
+            let distance = distances[state] + cost;
+            if distance < distances[from] {
+                distances[from] = distance;
+                if cost == 0 {
+                    queue.push_front(from);
+                } else {
+                    queue.push_back(from);
+                }
+            }
+        }
+    }
+
+    distances
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+    use crate::dfa::Dfa;
+
+    #[test]
+    fn test_matches_like_the_full_dfa() {
+        let pattern = "(a|b)*a(a|b){3}";
+        let lazy = LazyDfa::compile(pattern).unwrap();
+        let dfa = Dfa::compile(pattern).unwrap();
+
+        for input in ["abbb", "aaaa", "babab", "bbbbbbb", "", "abab", "bbabba"] {
+            assert_eq!(lazy.is_match(input), dfa.is_match(input), "{}", input);
+        }
+        assert!(lazy.stats().states > 0);
+        assert_eq!(lazy.stats().clears, 0);
+    }
+
+    #[test]
+    fn test_samples_from_blown_up_pattern() {
+        let pattern = ".{0,100}[a-f0-9]{32}";
+        let lazy = LazyDfa::compile(pattern).unwrap().capacity(500);
+        let mut rng = StdRng::seed_from_u64(5);
+
+        for _ in 0..20 {
+            let sample = lazy.sample(140, &mut rng).unwrap();
+            let len = sample.chars().count();
+            assert!((32..=132).contains(&len), "{}", len);
+            assert!(lazy.is_match(&sample));
+            assert!(sample.chars().rev().take(32).all(|c| c.is_ascii_hexdigit()));
+        }
+        assert!(lazy.stats().states <= 500);
+
+        assert!(
+            LazyDfa::compile("a{5}")
+                .unwrap()
+                .sample(4, &mut rng)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_thrashing_falls_back_to_simulation() {
+        let pattern = "(a|b)*a(a|b){6}";
+        let lazy = LazyDfa::compile(pattern).unwrap().capacity(4).max_clears(1);
+        let dfa = Dfa::compile(pattern).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for input in ["abababab", "bbbbbbbbbbbbb", "aaaaaaa", "babbbbbb"] {
+            assert_eq!(lazy.is_match(input), dfa.is_match(input), "{}", input);
+        }
+        for _ in 0..10 {
+            assert!(dfa.is_match(&lazy.sample(20, &mut rng).unwrap()));
+        }
+
+        let stats = lazy.stats();
+        assert!(stats.states <= 4);
+        assert!(stats.fallbacks > 0, "{:?}", stats);
+    }
+}
@@ -17,6 +17,7 @@ use std::iter::Peekable;
 * - \* \\           escaping special characters
 * - \t \r \n        special whitespace characters
 * - ^               negation
+* - \d              shorthand for \p{digits}
 * - \p{digits}      character classes (TBD) (digits, emojis, uppercase_ascii, cyrilic, etc.)
 * - \xFFFF          Unicode scalar values support
 * - \x{FFF0,FFFF}   Unicode scalar value ranges
@@ -61,10 +62,10 @@ pub enum WhitespaceKind {
     Space,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct Position {
-    start: usize,
-    end: usize,
+    pub start: usize,
+    pub end: usize,
 }
 
 pub struct Lexer<'a> {
@@ -156,16 +157,25 @@ impl<'a> Lexer<'a> {
                                     end: i + 1,
                                 },
                             )),
+                            'd' => tokens.push((
+                                RegexAtom::CharClass("digits".to_string()),
+                                Position {
+                                    start: i,
+                                    end: i + 1,
+                                },
+                            )),
                             'x' => tokens.push(self.lex_unicode(&mut iter)?),
                             'p' => tokens.push(self.lex_char_classes(&mut iter)?),
-                            _ => todo!("This needs to be handled, unknown character after slash"),
+                            _ => {
+                                return Err(TsegerError::LexerError(format!(
+                                    "Unknown escape sequence '\\{}' at {}",
+                                    fc, i
+                                )));
+                            }
                         }
                     }
                 },
-                c if c.is_alphanumeric() => {
-                    tokens.push((RegexAtom::Literal(c), Position { start: i, end: i }))
-                }
-                _ => todo!("Case {} at position {} not covered yet", c, i),
+                _ => tokens.push((RegexAtom::Literal(c), Position { start: i, end: i })),
             }
         }
 
@@ -228,7 +238,9 @@ impl<'a> Lexer<'a> {
             }),
         };
 
+        // {n} is an exact count, {n,} is unbounded
         let end_range = match end_str.is_empty() {
+            true if first => start_range,
             true => Option::None,
             false => Option::Some(match u32::from_str_radix(end_str.as_str(), 10) {
                 Ok(n) => n,
@@ -282,7 +294,15 @@ impl<'a> Lexer<'a> {
             end = i;
             start = i;
             // TODO: Refactor this, looks ugly
-            let next = iter.peek().unwrap();
+            let next = match iter.peek() {
+                None => {
+                    return Err(TsegerError::LexerError(format!(
+                        "Unexpected end of range at {}, missing ']'",
+                        end
+                    )));
+                }
+                Some(next) => next,
+            };
 
             if next.1 == '-' {
                 let range_start = c;
@@ -290,6 +310,22 @@ impl<'a> Lexer<'a> {
                 iter.next();
                 end = end + 1;
 
+                // Dash right before ']' is a literal
+                if let Some((dash, _)) = iter.next_if(|(_, c)| *c == ']') {
+                    ranges.push(BracketExpression::Single((
+                        RegexAtom::Literal(c),
+                        Position { start, end: start },
+                    )));
+                    ranges.push(BracketExpression::Single((
+                        RegexAtom::Literal('-'),
+                        Position { start: end, end },
+                    )));
+                    return Ok((
+                        RegexAtom::BracketExpressions { ranges, negated },
+                        Position { start, end: dash },
+                    ));
+                }
+
                 let range_end = match iter.next() {
                     None => {
                         return Err(TsegerError::LexerError(format!(
@@ -388,10 +424,7 @@ impl<'a> Lexer<'a> {
         }
 
         Ok((
-            RegexAtom::Literal(
-                // TODO: These unwrap()s are unbearable
-                char::from_u32(u32::from_str_radix(uchr.as_str(), 16).unwrap()).unwrap(),
-            ),
+            RegexAtom::Literal(hex_to_char(&uchr, start)?),
             Position { start, end },
         ))
     }
@@ -450,11 +483,8 @@ impl<'a> Lexer<'a> {
                             )));
                         }
                         Some((s1, s2)) => {
-                            // TODO: These unwrap() calls look ugly as hell
-                            start_range =
-                                char::from_u32(u32::from_str_radix(s1, 16).unwrap()).unwrap();
-                            end_range =
-                                char::from_u32(u32::from_str_radix(s2, 16).unwrap()).unwrap();
+                            start_range = hex_to_char(s1, start)?;
+                            end_range = hex_to_char(s2, start)?;
 
                             return Ok((
                                 RegexAtom::Range(start_range, end_range),
@@ -579,6 +609,18 @@ impl<'a> Lexer<'a> {
     }
 }
 
+fn hex_to_char(hex: &str, position: usize) -> Result<char, TsegerError> {
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(|| {
+            TsegerError::LexerError(format!(
+                "Invalid Unicode scalar value '{}' at {}",
+                hex, position
+            ))
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,4 +1,25 @@
+#[cfg(feature = "quickcheck")]
+pub mod arbitrary;
+pub mod bytes;
+pub mod cache;
+pub mod charset;
 pub mod common;
+pub mod dawg;
+pub mod definitions;
+pub mod dfa;
+pub mod dialect;
+pub mod fpe;
+pub mod frequency;
+pub mod generate;
+pub mod generator;
+pub mod grammar;
+pub mod infer;
+pub mod lazy;
 pub mod lexer;
+pub mod nfa;
 pub mod parser;
+pub mod random;
+pub mod rank;
 pub mod sm;
+#[cfg(feature = "proptest")]
+pub mod strategy;
@@ -0,0 +1,386 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Parser as ClapParser;
+use clap::ValueEnum;
+
+use tseger::bytes;
+use tseger::cache::DfaCache;
+use tseger::common::TsegerError;
+use tseger::dawg::Wordlist;
+use tseger::definitions::Definitions;
+use tseger::dfa::Dfa;
+use tseger::dialect;
+use tseger::dialect::Dialect;
+use tseger::frequency::FrequencyTable;
+use tseger::generate;
+use tseger::generate::Enumerator;
+use tseger::generate::NegativeSampler;
+use tseger::generate::Sampler;
+use tseger::lazy::LazyDfa;
+use tseger::parser::RegexAST;
+use tseger::random;
+
+// Consecutive duplicates after which --unique assumes the language is used up
+const MAX_DUPLICATES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Mode {
+    /// Random matches
+    Random,
+    /// All matches, shortest first
+    Enumerate,
+    /// Short matches that use every transition of the automaton
+    Coverage,
+    /// Near misses that don't match
+    Negative,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Syntax {
+    Tseger,
+    Pcre,
+    Rust,
+    /// Byte patterns, matches are written as raw bytes
+    Bytes,
+}
+
+/// Generates strings from a regular expression.
+#[derive(Debug, ClapParser)]
+#[command(name = "tseger", version)]
+struct Args {
+    pattern: String,
+
+    /// Number of strings to print
+    #[arg(short = 'n', long, default_value_t = 10)]
+    count: usize,
+
+    /// Seed for reproducible output
+    #[arg(short, long)]
+    seed: Option<u64>,
+
+    /// Never print the same string twice
+    #[arg(short, long)]
+    unique: bool,
+
+    #[arg(short, long, value_enum, default_value_t = Mode::Random)]
+    mode: Mode,
+
+    /// Printed after every string, understands \n, \t, \r, \0 and \\
+    #[arg(short, long, default_value = "\\n")]
+    delimiter: String,
+
+    /// Longest string considered for unbounded patterns
+    #[arg(short = 'l', long, default_value_t = 32)]
+    max_length: usize,
+
+    /// Only strings of this length, e.g. `12`, `8..64` or `8..`
+    #[arg(long, value_parser = parse_length)]
+    length: Option<(usize, Option<usize>)>,
+
+    /// Character frequencies for random mode, `english`, `german` or a corpus file,
+    /// applied within every character range of the pattern
+    #[arg(short, long, value_name = "TABLE")]
+    frequencies: Option<String>,
+
+    /// Named sub-pattern used as #{name}, e.g. `octet=25[0-5]|2[0-4]\d|1?\d?\d`
+    #[arg(short = 'D', long = "define", value_name = "NAME=PATTERN")]
+    defines: Vec<String>,
+
+    /// File with `define name = pattern` lines
+    #[arg(long, value_name = "FILE")]
+    definitions: Option<PathBuf>,
+
+    /// Only words from this file (one per line) that match the pattern,
+    /// random mode picks every word with the same probability
+    #[arg(short, long, value_name = "FILE")]
+    wordlist: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = Syntax::Tseger)]
+    syntax: Syntax,
+
+    /// Build automaton states only as random generation reaches them, with
+    /// bounded memory, for patterns whose full automaton is too large
+    #[arg(long)]
+    lazy: bool,
+
+    /// Reuse compiled automata from $TSEGER_CACHE_DIR or ~/.cache/tseger,
+    /// not used together with definitions
+    #[arg(long)]
+    cache: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<(), TsegerError> {
+    let mut rng = match args.seed {
+        Some(seed) => random::seeded(seed),
+        None => random::from_entropy(),
+    };
+
+    if args.lazy {
+        if !matches!(args.mode, Mode::Random)
+            || args.length.is_some()
+            || args.frequencies.is_some()
+            || args.wordlist.is_some()
+        {
+            return Err(TsegerError::GeneratorError(
+                "--lazy only supports plain random generation".to_string(),
+            ));
+        }
+
+        let lazy = LazyDfa::from_ast(&parse(args)?)?;
+        let max_length = args.max_length;
+        let strings = std::iter::repeat_with(move || lazy.sample(max_length, &mut rng));
+        return write_strings(args, strings);
+    }
+
+    let mut dfa = compile(args)?;
+    let mut max_length = args.max_length;
+    if let Some((min, max)) = args.length {
+        dfa = dfa.restrict_length(min, max)?;
+        max_length = max.unwrap_or(max_length.max(min));
+    }
+
+    let wordlist = match &args.wordlist {
+        Some(path) => Some(wordlist(path)?.filter(&dfa)?),
+        None => None,
+    };
+    if let Some(wordlist) = &wordlist {
+        dfa = wordlist.dfa().clone();
+        max_length = wordlist.longest();
+    }
+
+    let strings: Box<dyn Iterator<Item = Result<String, TsegerError>>> = match args.mode {
+        Mode::Random if args.frequencies.is_none() && wordlist.is_some() => {
+            let wordlist = wordlist.unwrap();
+            if wordlist.is_empty() {
+                return Err(TsegerError::WordlistError(
+                    "No word in the list matches the pattern".to_string(),
+                ));
+            }
+            Box::new(std::iter::repeat_with(move || {
+                Ok(wordlist.sample(&mut rng).unwrap())
+            }))
+        }
+        Mode::Random => {
+            let sampler = match &args.frequencies {
+                Some(table) => Sampler::weighted(&dfa, max_length, frequencies(table)?)?,
+                None => Sampler::new(&dfa, max_length)?,
+            };
+            Box::new(std::iter::repeat_with(move || Ok(sampler.sample(&mut rng))))
+        }
+        Mode::Enumerate => Box::new(Enumerator::new(&dfa, max_length).map(Ok)),
+        Mode::Coverage => Box::new(generate::coverage(&dfa).into_iter().map(Ok)),
+        Mode::Negative => {
+            let mut negative = NegativeSampler::new(&dfa, max_length)?;
+            if let Syntax::Bytes = args.syntax {
+                negative = negative.alphabet(bytes::alphabet());
+            }
+            Box::new(std::iter::repeat_with(move || negative.sample(&mut rng)))
+        }
+    };
+
+    write_strings(args, strings)
+}
+
+fn write_strings(
+    args: &Args,
+    strings: impl Iterator<Item = Result<String, TsegerError>>,
+) -> Result<(), TsegerError> {
+    let delimiter = unescape(&args.delimiter);
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut seen = HashSet::new();
+    let mut duplicates = 0;
+    let mut printed = 0;
+
+    for string in strings {
+        if printed == args.count {
+            break;
+        }
+
+        let string = string?;
+        if args.unique && !seen.insert(string.clone()) {
+            duplicates += 1;
+            if duplicates == MAX_DUPLICATES {
+                let _ = out.flush();
+                eprintln!(
+                    "tseger: stopping after {} unique strings, the pattern seems to have no more",
+                    printed
+                );
+                break;
+            }
+            continue;
+        }
+        duplicates = 0;
+
+        // A closed pipe, e.g. `tseger ... | head`, just ends the output
+        let written = match args.syntax {
+            Syntax::Bytes => out
+                .write_all(&bytes::to_bytes(&string))
+                .and_then(|_| out.write_all(delimiter.as_bytes())),
+            _ => write!(out, "{}{}", string, delimiter),
+        };
+        if written.is_err() {
+            return Ok(());
+        }
+        printed += 1;
+    }
+
+    let _ = out.flush();
+    Ok(())
+}
+
+fn compile(args: &Args) -> Result<Dfa, TsegerError> {
+    let cache = DfaCache::default_dir()
+        .map(DfaCache::new)
+        .filter(|_| args.cache && args.defines.is_empty() && args.definitions.is_none());
+
+    match cache {
+        Some(cache) => {
+            let syntax = format!("{:?}", args.syntax).to_lowercase();
+            cache.get_or_compile(&syntax, &args.pattern, || compile_uncached(args))
+        }
+        None => compile_uncached(args),
+    }
+}
+
+fn compile_uncached(args: &Args) -> Result<Dfa, TsegerError> {
+    Dfa::from_ast(&parse(args)?)
+}
+
+fn parse(args: &Args) -> Result<RegexAST, TsegerError> {
+    Ok(match args.syntax {
+        Syntax::Tseger => definitions(args)?.parse_pattern(&args.pattern)?,
+        Syntax::Pcre => dialect::translate(&args.pattern, Dialect::Pcre)?.ast,
+        Syntax::Rust => dialect::translate(&args.pattern, Dialect::Rust)?.ast,
+        Syntax::Bytes => bytes::parse(&args.pattern)?,
+    })
+}
+
+fn frequencies(table: &str) -> Result<FrequencyTable, TsegerError> {
+    if let Some(builtin) = FrequencyTable::named(table) {
+        return Ok(builtin);
+    }
+
+    let corpus = fs::read_to_string(table).map_err(|e| {
+        TsegerError::GeneratorError(format!(
+            "'{}' is neither a built-in table nor a readable corpus: {}",
+            table, e
+        ))
+    })?;
+    FrequencyTable::learn(&corpus)
+}
+
+fn wordlist(path: &PathBuf) -> Result<Wordlist, TsegerError> {
+    let words = fs::read_to_string(path)
+        .map_err(|e| TsegerError::WordlistError(format!("Can't read {}: {}", path.display(), e)))?;
+    let words: Vec<&str> = words
+        .lines()
+        .map(str::trim_end)
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    Wordlist::from_words(&words)
+}
+
+fn definitions(args: &Args) -> Result<Definitions, TsegerError> {
+    let mut definitions = match &args.definitions {
+        Some(path) => Definitions::parse(&fs::read_to_string(path).map_err(|e| {
+            TsegerError::DefinitionError(format!("Can't read {}: {}", path.display(), e))
+        })?)?,
+        None => Definitions::new(),
+    };
+
+    for define in &args.defines {
+        let (name, pattern) = define.split_once('=').ok_or_else(|| {
+            TsegerError::DefinitionError(format!("Expected NAME=PATTERN, got '{}'", define))
+        })?;
+        definitions.define(name.trim(), pattern)?;
+    }
+
+    Ok(definitions)
+}
+
+fn parse_length(range: &str) -> Result<(usize, Option<usize>), String> {
+    let number = |s: &str| {
+        s.trim()
+            .parse::<usize>()
+            .map_err(|_| format!("'{}' is not a length", s))
+    };
+
+    match range.split_once("..") {
+        None => number(range).map(|len| (len, Some(len))),
+        Some((min, "")) => Ok((number(min)?, None)),
+        Some((min, max)) => {
+            let max = number(max.strip_prefix('=').unwrap_or(max))?;
+            Ok((number(min)?, Some(max)))
+        }
+    }
+}
+
+fn unescape(delimiter: &str) -> String {
+    let mut result = String::new();
+    let mut chars = delimiter.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            Some(other) => {
+                result.push('\\');
+                if other != '\\' {
+                    result.push(other);
+                }
+            }
+            None => result.push('\\'),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length_ranges() {
+        assert_eq!(parse_length("12"), Ok((12, Some(12))));
+        assert_eq!(parse_length("8..64"), Ok((8, Some(64))));
+        assert_eq!(parse_length("8..=64"), Ok((8, Some(64))));
+        assert_eq!(parse_length("8.."), Ok((8, None)));
+        assert!(parse_length("..8").is_err());
+        assert!(parse_length("a").is_err());
+    }
+
+    #[test]
+    fn test_delimiter_escapes() {
+        assert_eq!(unescape("\\n"), "\n");
+        assert_eq!(unescape(",\\t"), ",\t");
+        assert_eq!(unescape("\\\\"), "\\");
+        assert_eq!(unescape("\\q"), "\\q");
+        assert_eq!(unescape(";"), ";");
+    }
+}
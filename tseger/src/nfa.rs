@@ -0,0 +1,277 @@
+use std::collections::BTreeSet;
+
+use crate::charset::CharSet;
+use crate::common::TsegerError;
+use crate::dfa::Dfa;
+use crate::parser::BracketExpressionItem;
+use crate::parser::RegexAST;
+
+// Upper bound on states, repetitions like (a{1000}){1000} expand into copies
+const MAX_STATES: usize = 1_000_000;
+
+pub type StateId = usize;
+
+/// Thompson NFA with a single start and a single accepting state.
+#[derive(Debug, Clone)]
+pub struct Nfa {
+    states: Vec<NfaState>,
+    start: StateId,
+    accept: StateId,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NfaState {
+    pub epsilon: Vec<StateId>,
+    pub transitions: Vec<(CharSet, StateId)>,
+}
+
+impl Nfa {
+    pub fn from_ast(ast: &RegexAST) -> Result<Self, TsegerError> {
+        let mut nfa = Self {
+            states: Vec::new(),
+            start: 0,
+            accept: 0,
+        };
+
+        let (start, accept) = nfa.build(ast)?;
+        nfa.start = start;
+        nfa.accept = accept;
+
+        Ok(nfa)
+    }
+
+    /// Concatenation of the automata, their states are copied one for one.
+    pub fn from_dfas(parts: &[Dfa]) -> Result<Self, TsegerError> {
+        let mut nfa = Self {
+            states: Vec::new(),
+            start: 0,
+            accept: 0,
+        };
+
+        let (start, mut end) = nfa.empty()?;
+        for dfa in parts {
+            let offset = nfa.states.len();
+            for state in dfa.states() {
+                let id = nfa.add_state()?;
+                nfa.states[id].transitions = state
+                    .transitions
+                    .iter()
+                    .map(|(set, target)| (set.clone(), offset + target))
+                    .collect();
+            }
+
+            let exit = nfa.add_state()?;
+            for (id, state) in dfa.states().iter().enumerate() {
+                if state.accepting {
+                    nfa.add_epsilon(offset + id, exit);
+                }
+            }
+            nfa.add_epsilon(end, offset + dfa.start());
+            end = exit;
+        }
+
+        nfa.start = start;
+        nfa.accept = end;
+        Ok(nfa)
+    }
+
+    pub fn start(&self) -> StateId {
+        self.start
+    }
+
+    pub fn accept(&self) -> StateId {
+        self.accept
+    }
+
+    pub fn states(&self) -> &[NfaState] {
+        &self.states
+    }
+
+    pub fn epsilon_closure(&self, states: impl IntoIterator<Item = StateId>) -> BTreeSet<StateId> {
+        let mut closure = BTreeSet::new();
+        let mut stack: Vec<StateId> = states.into_iter().collect();
+
+        while let Some(state) = stack.pop() {
+            if closure.insert(state) {
+                stack.extend(self.states[state].epsilon.iter().copied());
+            }
+        }
+
+        closure
+    }
+
+    fn add_state(&mut self) -> Result<StateId, TsegerError> {
+        if self.states.len() >= MAX_STATES {
+            return Err(TsegerError::DFAError(format!(
+                "Pattern expands to more than {} NFA states",
+                MAX_STATES
+            )));
+        }
+
+        self.states.push(NfaState::default());
+        Ok(self.states.len() - 1)
+    }
+
+    fn add_epsilon(&mut self, from: StateId, to: StateId) {
+        self.states[from].epsilon.push(to);
+    }
+
+    fn symbol(&mut self, set: CharSet) -> Result<(StateId, StateId), TsegerError> {
+        let start = self.add_state()?;
+        let end = self.add_state()?;
+
+        // An empty set, e.g. a negated class of everything, leaves the fragment without a path
+        if !set.is_empty() {
+            self.states[start].transitions.push((set, end));
+        }
+
+        Ok((start, end))
+    }
+
+    fn empty(&mut self) -> Result<(StateId, StateId), TsegerError> {
+        let start = self.add_state()?;
+        let end = self.add_state()?;
+        self.add_epsilon(start, end);
+
+        Ok((start, end))
+    }
+
+    // Builds a fragment for the node and returns its entry and exit states
+    fn build(&mut self, ast: &RegexAST) -> Result<(StateId, StateId), TsegerError> {
+        match ast {
+            RegexAST::Literal(c) => self.symbol(CharSet::single(*c)),
+            RegexAST::Dot => self.symbol(CharSet::single('\n').complement()),
+            RegexAST::CharClass(name) => match CharSet::named(name) {
+                Some(set) => self.symbol(set),
+                None => Err(TsegerError::DFAError(format!(
+                    "Unknown character class \\p{{{}}}",
+                    name
+                ))),
+            },
+            RegexAST::BracketExpression { negated, items } => {
+                let set = bracket_set(items);
+                self.symbol(if *negated { set.complement() } else { set })
+            }
+            RegexAST::Variable(name) => Err(TsegerError::DFAError(format!(
+                "Unresolved variable #{{{}}}",
+                name
+            ))),
+            RegexAST::Group(node) => self.build(node),
+            RegexAST::Concat(nodes) => {
+                let (start, mut end) = self.empty()?;
+                for node in nodes {
+                    let (node_start, node_end) = self.build(node)?;
+                    self.add_epsilon(end, node_start);
+                    end = node_end;
+                }
+                Ok((start, end))
+            }
+            RegexAST::Alteration(branches) => {
+                let start = self.add_state()?;
+                let end = self.add_state()?;
+                for branch in branches {
+                    let (branch_start, branch_end) = self.build(branch)?;
+                    self.add_epsilon(start, branch_start);
+                    self.add_epsilon(branch_end, end);
+                }
+                Ok((start, end))
+            }
+            RegexAST::Star(node) => self.repeat(node, 0, None),
+            RegexAST::Plus(node) => self.repeat(node, 1, None),
+            RegexAST::Question(node) => self.repeat(node, 0, Some(1)),
+            RegexAST::Repetition { node, min, max } => self.repeat(node, *min, *max),
+        }
+    }
+
+    fn repeat(
+        &mut self,
+        node: &RegexAST,
+        min: u32,
+        max: Option<u32>,
+    ) -> Result<(StateId, StateId), TsegerError> {
+        let (start, mut end) = self.empty()?;
+
+        for _ in 0..min {
+            let (node_start, node_end) = self.build(node)?;
+            self.add_epsilon(end, node_start);
+            end = node_end;
+        }
+
+        match max {
+            None => {
+                let (node_start, node_end) = self.build(node)?;
+                let exit = self.add_state()?;
+                self.add_epsilon(end, node_start);
+                self.add_epsilon(end, exit);
+                self.add_epsilon(node_end, node_start);
+                self.add_epsilon(node_end, exit);
+                end = exit;
+            }
+            Some(max) => {
+                // Every optional copy can skip straight to the exit
+                let exit = self.add_state()?;
+                for _ in min..max {
+                    let (node_start, node_end) = self.build(node)?;
+                    self.add_epsilon(end, node_start);
+                    self.add_epsilon(end, exit);
+                    end = node_end;
+                }
+                self.add_epsilon(end, exit);
+                end = exit;
+            }
+        }
+
+        Ok((start, end))
+    }
+}
+
+pub fn bracket_set(items: &[BracketExpressionItem]) -> CharSet {
+    CharSet::from_ranges(items.iter().map(|item| match *item {
+        BracketExpressionItem::Char(c) => (c, c),
+        BracketExpressionItem::Range(from, to) => (from, to),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn accepts(nfa: &Nfa, input: &str) -> bool {
+        let mut current = nfa.epsilon_closure([nfa.start()]);
+
+        for c in input.chars() {
+            let next: Vec<StateId> = current
+                .iter()
+                .flat_map(|&s| nfa.states()[s].transitions.iter())
+                .filter(|(set, _)| set.contains(c))
+                .map(|&(_, to)| to)
+                .collect();
+            current = nfa.epsilon_closure(next);
+        }
+
+        current.contains(&nfa.accept())
+    }
+
+    #[test]
+    fn test_thompson_construction() {
+        let ast = Parser::new().parse("(ab|c){2,3}[^0-9]?").unwrap();
+        let nfa = Nfa::from_ast(&ast).unwrap();
+
+        assert!(accepts(&nfa, "abc"));
+        assert!(accepts(&nfa, "ccabx"));
+        assert!(accepts(&nfa, "ababab"));
+        assert!(!accepts(&nfa, "c"));
+        assert!(!accepts(&nfa, "abab1"));
+        assert!(!accepts(&nfa, "ccccc"));
+    }
+
+    #[test]
+    fn test_unresolved_names() {
+        let parser = Parser::new();
+
+        assert!(Nfa::from_ast(&parser.parse("#{zip}").unwrap()).is_err());
+        assert!(Nfa::from_ast(&parser.parse(r"\p{pokemons}").unwrap()).is_err());
+        assert!(Nfa::from_ast(&parser.parse(r"\p{digits}").unwrap()).is_ok());
+    }
+}
@@ -1,16 +1,18 @@
 // TODO: Remove this when module is ready
 #![allow(dead_code)]
 
+use std::fmt;
 use std::iter::Peekable;
 
 use crate::common::TsegerError;
-use crate::lexer::Position;
+use crate::lexer::BracketExpression;
+use crate::lexer::Lexer;
 use crate::lexer::RegexAtom;
 use crate::lexer::WhitespaceKind;
 
 type ParserResult = Result<RegexAST, TsegerError>;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum RegexAST {
     Literal(char),
     Concat(Vec<RegexAST>),
@@ -33,19 +35,129 @@ pub enum RegexAST {
     Dot,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum BracketExpressionItem {
     Char(char),
     Range(char, char),
 }
 
+// Renders the AST back into tseger syntax
+impl fmt::Display for RegexAST {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegexAST::Literal(c) => write_literal(f, *c),
+            RegexAST::Concat(nodes) => nodes.iter().try_for_each(|node| match node {
+                RegexAST::Alteration(_) => write!(f, "({})", node),
+                _ => write!(f, "{}", node),
+            }),
+            RegexAST::Alteration(branches) => {
+                for (i, branch) in branches.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "|")?;
+                    }
+                    write!(f, "{}", branch)?;
+                }
+                Ok(())
+            }
+            RegexAST::Group(node) => write!(f, "({})", node),
+            RegexAST::CharClass(name) => write!(f, "\\p{{{}}}", name),
+            RegexAST::BracketExpression { negated, items } => {
+                write!(f, "[{}", if *negated { "^" } else { "" })?;
+                let mut dash = false;
+                for item in items {
+                    match item {
+                        // Goes last so it can't be read as a range
+                        BracketExpressionItem::Char('-') => dash = true,
+                        BracketExpressionItem::Char(c) => write!(f, "{}", c)?,
+                        BracketExpressionItem::Range(from, to) => write!(f, "{}-{}", from, to)?,
+                    }
+                }
+                write!(f, "{}]", if dash { "-" } else { "" })
+            }
+            RegexAST::Variable(name) => write!(f, "#{{{}}}", name),
+            RegexAST::Repetition { node, min, max } => {
+                write_quantified(f, node)?;
+                match max {
+                    Some(max) if max == min => write!(f, "{{{}}}", min),
+                    Some(max) => write!(f, "{{{},{}}}", min, max),
+                    None => write!(f, "{{{},}}", min),
+                }
+            }
+            RegexAST::Star(node) => {
+                write_quantified(f, node)?;
+                write!(f, "*")
+            }
+            RegexAST::Plus(node) => {
+                write_quantified(f, node)?;
+                write!(f, "+")
+            }
+            RegexAST::Question(node) => {
+                write_quantified(f, node)?;
+                write!(f, "?")
+            }
+            RegexAST::Dot => write!(f, "."),
+        }
+    }
+}
+
+fn write_literal(f: &mut fmt::Formatter<'_>, c: char) -> fmt::Result {
+    match c {
+        '(' | ')' | '{' | '}' | '[' | ']' | '.' | '*' | '?' | '+' | '^' | '|' | '\\' | '#' => {
+            write!(f, "\\{}", c)
+        }
+        '\n' => write!(f, "\\n"),
+        '\t' => write!(f, "\\t"),
+        '\r' => write!(f, "\\r"),
+        // Single value range, \xFFFF would swallow hex digits that follow it
+        c if c.is_control() => write!(f, "\\x{{{:X},{:X}}}", c as u32, c as u32),
+        c => write!(f, "{}", c),
+    }
+}
+
+fn write_quantified(f: &mut fmt::Formatter<'_>, node: &RegexAST) -> fmt::Result {
+    match node {
+        RegexAST::Concat(_)
+        | RegexAST::Alteration(_)
+        | RegexAST::Repetition { .. }
+        | RegexAST::Star(_)
+        | RegexAST::Plus(_)
+        | RegexAST::Question(_) => write!(f, "({})", node),
+        _ => write!(f, "{}", node),
+    }
+}
+
 pub struct Parser {}
 
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Parser {
     pub fn new() -> Self {
         Self {}
     }
 
+    pub fn parse(&self, regex: &str) -> ParserResult {
+        let mut tokens = Lexer::new(regex)
+            .lex()?
+            .into_iter()
+            .map(|(atom, _)| atom)
+            .filter(|atom| *atom != RegexAtom::EOF)
+            .peekable();
+
+        let ast = self.parse_expression(&mut tokens)?;
+
+        match tokens.next() {
+            None => Ok(ast),
+            Some(atom) => Err(TsegerError::ParserError(format!(
+                "Unexpected token {:?}",
+                atom
+            ))),
+        }
+    }
+
     pub fn parse_expression(
         &self,
         tokens: &mut Peekable<impl Iterator<Item = RegexAtom>>,
@@ -87,7 +199,7 @@ impl Parser {
         }
 
         match nodes.len() {
-            0 => Err(TsegerError::ParserError(format!("Expected expression"))),
+            0 => Err(TsegerError::ParserError("Expected expression".to_string())),
             1 => Ok(nodes.remove(0)),
             _ => Ok(RegexAST::Concat(nodes)),
         }
@@ -114,9 +226,21 @@ impl Parser {
                     node = RegexAST::Question(Box::new(node));
                 }
                 Some(RegexAtom::Repetition(from, to)) => {
+                    let min = from.unwrap_or(0);
+                    let max = *to;
+
+                    if max.is_some_and(|max| max < min) {
+                        return Err(TsegerError::ParserError(format!(
+                            "Invalid repetition {{{},{}}}",
+                            min,
+                            max.unwrap()
+                        )));
+                    }
+
+                    tokens.next();
                     node = RegexAST::Repetition {
-                        min: from.unwrap_or(0),
-                        max: *to,
+                        min,
+                        max,
                         node: Box::new(node),
                     }
                 }
@@ -133,6 +257,10 @@ impl Parser {
     ) -> Result<RegexAST, TsegerError> {
         match tokens.next() {
             Some(RegexAtom::Literal(c)) => Ok(RegexAST::Literal(c)),
+            Some(RegexAtom::Range(from, to)) => Ok(RegexAST::BracketExpression {
+                negated: false,
+                items: vec![bracket_range(from, to)?],
+            }),
             Some(RegexAtom::QuantWildcard) => Ok(RegexAST::Dot),
             Some(RegexAtom::Whitespace(kind)) => Ok(RegexAST::Literal(match kind {
                 WhitespaceKind::Space => ' ',
@@ -140,17 +268,61 @@ impl Parser {
                 WhitespaceKind::NewLine => '\n',
                 WhitespaceKind::CR => '\r',
             })),
-            Some(RegexAtom::LParen) => self.parse_expression(tokens),
-            _ => Err(TsegerError::ParserError(format!("unexpected token"))),
+            Some(RegexAtom::LParen) => {
+                let node = self.parse_expression(tokens)?;
+                match tokens.next() {
+                    Some(RegexAtom::RParen) => Ok(RegexAST::Group(Box::new(node))),
+                    _ => Err(TsegerError::ParserError("Missing ')'".to_string())),
+                }
+            }
+            Some(RegexAtom::BracketExpressions { negated, ranges }) => {
+                Ok(RegexAST::BracketExpression {
+                    negated,
+                    items: ranges
+                        .into_iter()
+                        .map(|item| match item {
+                            BracketExpression::Single((RegexAtom::Literal(c), _)) => {
+                                Ok(BracketExpressionItem::Char(c))
+                            }
+                            BracketExpression::Ranged((RegexAtom::Range(from, to), _)) => {
+                                bracket_range(from, to)
+                            }
+                            other => Err(TsegerError::ParserError(format!(
+                                "Unexpected bracket expression item {:?}",
+                                other
+                            ))),
+                        })
+                        .collect::<Result<_, _>>()?,
+                })
+            }
+            Some(RegexAtom::CharClass(name)) => Ok(RegexAST::CharClass(name)),
+            Some(RegexAtom::Variable(name)) => Ok(RegexAST::Variable(name)),
+            None => Err(TsegerError::ParserError(
+                "Unexpected end of expression".to_string(),
+            )),
+            Some(atom) => Err(TsegerError::ParserError(format!(
+                "Unexpected token {:?}",
+                atom
+            ))),
         }
     }
 }
 
+fn bracket_range(from: char, to: char) -> Result<BracketExpressionItem, TsegerError> {
+    if from > to {
+        return Err(TsegerError::ParserError(format!(
+            "Invalid range '{}-{}'",
+            from, to
+        )));
+    }
+
+    Ok(BracketExpressionItem::Range(from, to))
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
-    use crate::lexer::Lexer;
 
     #[test]
     fn test_alteration_1() {
@@ -170,4 +342,65 @@ mod tests {
             ])
         );
     }
+
+    #[test]
+    fn test_groups_and_repetitions() {
+        let result = Parser::new().parse("(ab|c){2}[^x-z_]+").unwrap();
+
+        assert_eq!(
+            result,
+            RegexAST::Concat(vec![
+                RegexAST::Repetition {
+                    node: Box::new(RegexAST::Group(Box::new(RegexAST::Alteration(vec![
+                        RegexAST::Concat(vec![RegexAST::Literal('a'), RegexAST::Literal('b')]),
+                        RegexAST::Literal('c'),
+                    ])))),
+                    min: 2,
+                    max: Some(2),
+                },
+                RegexAST::Plus(Box::new(RegexAST::BracketExpression {
+                    negated: true,
+                    items: vec![
+                        BracketExpressionItem::Range('x', 'z'),
+                        BracketExpressionItem::Char('_'),
+                    ],
+                })),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parser = Parser::new();
+
+        assert!(parser.parse("(ab").is_err());
+        assert!(parser.parse("ab)").is_err());
+        assert!(parser.parse("a{5,2}").is_err());
+        assert!(parser.parse("[z-a]").is_err());
+        assert!(parser.parse("*a").is_err());
+    }
+
+    #[test]
+    fn test_display_round_trip_syntax() {
+        let ast = RegexAST::Concat(vec![
+            RegexAST::Repetition {
+                node: Box::new(RegexAST::BracketExpression {
+                    negated: false,
+                    items: vec![BracketExpressionItem::Range('A', 'Z')],
+                }),
+                min: 2,
+                max: Some(2),
+            },
+            RegexAST::Literal('.'),
+            RegexAST::Alteration(vec![RegexAST::Literal('a'), RegexAST::Dot]),
+            RegexAST::Star(Box::new(RegexAST::Concat(vec![
+                RegexAST::Literal('b'),
+                RegexAST::Literal('\u{1}'),
+            ]))),
+            RegexAST::Variable("var".to_string()),
+        ]);
+
+        assert_eq!(ast.to_string(), "[A-Z]{2}\\.(a|.)(b\\x{1,1})*#{var}");
+        assert!(Parser::new().parse(&ast.to_string()).is_ok());
+    }
 }
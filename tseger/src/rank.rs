@@ -0,0 +1,207 @@
+use std::cell::RefCell;
+
+use crate::common::TsegerError;
+use crate::dfa::Dfa;
+use crate::nfa::StateId;
+
+/*
+* Ranking of a regular language, length by length:
+*
+* - matches of the same length are ordered lexicographically by scalar value
+* - rank() maps a match to its index in that order, unrank() does the reverse
+* - counts are exact, lengths whose count doesn't fit into u128 are rejected
+*/
+
+pub struct Ranker {
+    dfa: Dfa,
+    // Transitions of every state flattened into ranges, sorted by character
+    pieces: Vec<Vec<(char, char, StateId)>>,
+    // counts[len][state] - matches of length len, starting from state
+    counts: RefCell<Vec<Vec<u128>>>,
+}
+
+impl Ranker {
+    pub fn new(dfa: Dfa) -> Self {
+        let pieces = dfa
+            .states()
+            .iter()
+            .map(|state| {
+                let mut pieces: Vec<(char, char, StateId)> = state
+                    .transitions
+                    .iter()
+                    .flat_map(|(set, target)| {
+                        set.ranges().iter().map(|&(from, to)| (from, to, *target))
+                    })
+                    .collect();
+                pieces.sort_unstable();
+                pieces
+            })
+            .collect();
+
+        let accepting = dfa
+            .states()
+            .iter()
+            .map(|state| u128::from(state.accepting))
+            .collect();
+
+        Self {
+            dfa,
+            pieces,
+            counts: RefCell::new(vec![accepting]),
+        }
+    }
+
+    pub fn dfa(&self) -> &Dfa {
+        &self.dfa
+    }
+
+    /// Number of matches with exactly `len` characters.
+    pub fn count(&self, len: usize) -> Result<u128, TsegerError> {
+        self.count_from(self.dfa.start(), len)
+    }
+
+    pub fn count_from(&self, state: StateId, len: usize) -> Result<u128, TsegerError> {
+        self.extend(len)?;
+        Ok(self.counts.borrow()[len][state])
+    }
+
+    /// Index of `input` among the matches of the same length.
+    pub fn rank(&self, input: &str) -> Result<u128, TsegerError> {
+        let len = input.chars().count();
+        self.extend(len)?;
+        let counts = self.counts.borrow();
+
+        let mut state = self.dfa.start();
+        let mut rank = 0u128;
+
+        for (i, c) in input.chars().enumerate() {
+            let remaining = &counts[len - i - 1];
+
+            for (set, target) in &self.dfa.state(state).transitions {
+                // Can't overflow, the total is bounded by the count for this length
+                rank += u128::from(set.count_below(c)) * remaining[*target];
+            }
+
+            state = self.dfa.next(state, c).ok_or_else(|| no_match(input))?;
+        }
+
+        if !self.dfa.state(state).accepting {
+            return Err(no_match(input));
+        }
+
+        Ok(rank)
+    }
+
+    /// Match of length `len` at position `index` in lexicographic order.
+    pub fn unrank(&self, len: usize, mut index: u128) -> Result<String, TsegerError> {
+        let total = self.count(len)?;
+        if index >= total {
+            return Err(TsegerError::RankError(format!(
+                "Index {} is out of range, there are {} matches of length {}",
+                index, total, len
+            )));
+        }
+
+        let counts = self.counts.borrow();
+        let mut state = self.dfa.start();
+        let mut result = String::with_capacity(len);
+
+        for position in 0..len {
+            let remaining = &counts[len - position - 1];
+
+            for &(from, to, target) in &self.pieces[state] {
+                let per_char = remaining[target];
+                if per_char == 0 {
+                    continue;
+                }
+
+                let block = u128::from(to as u32 - from as u32 + 1) * per_char;
+                if index < block {
+                    // Pieces never span surrogates, so the offset is always a valid char
+                    let offset = (index / per_char) as u32;
+                    result.push(char::from_u32(from as u32 + offset).unwrap());
+                    index %= per_char;
+                    state = target;
+                    break;
+                }
+                index -= block;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn extend(&self, len: usize) -> Result<(), TsegerError> {
+        let mut counts = self.counts.borrow_mut();
+
+        while counts.len() <= len {
+            let previous = counts.last().unwrap();
+            let mut next = Vec::with_capacity(previous.len());
+
+            for transitions in &self.pieces {
+                let mut total = 0u128;
+                for &(from, to, target) in transitions {
+                    total = u128::from(to as u32 - from as u32 + 1)
+                        .checked_mul(previous[target])
+                        .and_then(|n| n.checked_add(total))
+                        .ok_or_else(|| {
+                            TsegerError::RankError(format!(
+                                "Number of matches of length {} doesn't fit into 128 bits",
+                                counts.len()
+                            ))
+                        })?;
+                }
+                next.push(total);
+            }
+
+            counts.push(next);
+        }
+
+        Ok(())
+    }
+}
+
+fn no_match(input: &str) -> TsegerError {
+    TsegerError::RankError(format!("'{}' doesn't match the pattern", input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts() {
+        let ranker = Ranker::new(Dfa::compile("[a-c]{2}|x*").unwrap());
+
+        assert_eq!(ranker.count(0).unwrap(), 1);
+        assert_eq!(ranker.count(1).unwrap(), 1);
+        assert_eq!(ranker.count(2).unwrap(), 10);
+        assert_eq!(ranker.count(3).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_rank_unrank_follow_lexicographic_order() {
+        let ranker = Ranker::new(Dfa::compile("[a-c]{2}|x*").unwrap());
+
+        let all: Vec<String> = (0..10).map(|i| ranker.unrank(2, i).unwrap()).collect();
+        assert_eq!(
+            all,
+            vec!["aa", "ab", "ac", "ba", "bb", "bc", "ca", "cb", "cc", "xx"]
+        );
+
+        for (i, s) in all.iter().enumerate() {
+            assert_eq!(ranker.rank(s).unwrap(), i as u128);
+        }
+
+        assert!(ranker.unrank(2, 10).is_err());
+        assert!(ranker.rank("ad").is_err());
+    }
+
+    #[test]
+    fn test_overflow_is_reported() {
+        let ranker = Ranker::new(Dfa::compile(".*").unwrap());
+
+        assert!(ranker.count(6).is_ok());
+        assert!(ranker.count(7).is_err());
+    }
+}
@@ -61,8 +61,7 @@ pub struct State {
 }
 
 impl State {
-    fn new() -> Self {
-        let mut rng = rand::rng();
+    fn new<R: Rng + ?Sized>(rng: &mut R) -> Self {
         Self {
             id: StateId(rng.random::<u16>()),
             transitions: HashMap::new(),
@@ -89,12 +88,13 @@ mod tests {
 
     #[test]
     fn test_sm_1() {
+        let mut rng = crate::random::seeded(1);
         let mut sm = StateMachine::new();
-        let mut s1 = State::new();
+        let mut s1 = State::new(&mut rng);
         let s1_id = s1.id;
-        let mut s2 = State::new();
+        let mut s2 = State::new(&mut rng);
         let s2_id = s2.id;
-        let s3 = State::new();
+        let s3 = State::new(&mut rng);
         let s3_id = s3.id;
 
         s1.add_transition(Symbol('a'), s2_id);
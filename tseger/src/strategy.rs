@@ -0,0 +1,179 @@
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+
+use proptest::arbitrary::any;
+use proptest::strategy::NewTree;
+use proptest::strategy::Strategy;
+use proptest::strategy::ValueTree;
+use proptest::test_runner::TestRunner;
+
+use crate::common::TsegerError;
+use crate::dfa::Dfa;
+use crate::generate;
+use crate::generate::Sampler;
+use crate::random;
+
+/*
+* proptest support, behind the `proptest` feature:
+*
+*   proptest! {
+*       #[test]
+*       fn parses(id in RegexStrategy::new("[A-Z]{2}-[0-9]{4}").unwrap()) { ... }
+*   }
+*
+* Values are uniform samples of the pattern. Failing values shrink through
+* generate::shrink_candidates, toward shorter and lexicographically smaller
+* matches, so every shrunk value still matches.
+*/
+
+const DEFAULT_MAX_LEN: usize = 32;
+
+#[derive(Clone)]
+pub struct RegexStrategy {
+    dfa: Arc<Dfa>,
+    sampler: Arc<Sampler>,
+}
+
+impl RegexStrategy {
+    pub fn new(pattern: &str) -> Result<Self, TsegerError> {
+        Self::from_dfa(Dfa::compile(pattern)?, DEFAULT_MAX_LEN)
+    }
+
+    /// Strategy over the matches of `dfa` with at most `max_len` characters.
+    pub fn from_dfa(dfa: Dfa, max_len: usize) -> Result<Self, TsegerError> {
+        let sampler = Sampler::new(&dfa, max_len)?;
+        Ok(Self {
+            dfa: Arc::new(dfa),
+            sampler: Arc::new(sampler),
+        })
+    }
+}
+
+impl fmt::Debug for RegexStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegexStrategy")
+            .field("lengths", &self.sampler.lengths())
+            .finish()
+    }
+}
+
+impl Strategy for RegexStrategy {
+    type Tree = MatchTree;
+    type Value = String;
+
+    fn new_tree(&self, runner: &mut TestRunner) -> NewTree<Self> {
+        // Seeding through a u64 keeps us independent of proptest's rand version
+        let seed = any::<u64>().new_tree(runner)?.current();
+        let value = self.sampler.sample(&mut random::seeded(seed));
+
+        Ok(MatchTree::new(self.dfa.clone(), value))
+    }
+}
+
+pub struct MatchTree {
+    dfa: Arc<Dfa>,
+    current: String,
+    candidates: Vec<String>,
+    next: usize,
+    // State before the last simplify(), complicate() goes back to it
+    previous: Option<(String, Vec<String>, usize)>,
+}
+
+impl MatchTree {
+    fn new(dfa: Arc<Dfa>, value: String) -> Self {
+        Self {
+            candidates: generate::shrink_candidates(&dfa, &value),
+            dfa,
+            current: value,
+            next: 0,
+            previous: None,
+        }
+    }
+}
+
+impl fmt::Debug for MatchTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MatchTree")
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+impl ValueTree for MatchTree {
+    type Value = String;
+
+    fn current(&self) -> String {
+        self.current.clone()
+    }
+
+    fn simplify(&mut self) -> bool {
+        let Some(candidate) = self.candidates.get(self.next).cloned() else {
+            return false;
+        };
+
+        let candidates = generate::shrink_candidates(&self.dfa, &candidate);
+        self.previous = Some((
+            mem::replace(&mut self.current, candidate),
+            mem::replace(&mut self.candidates, candidates),
+            self.next + 1,
+        ));
+        self.next = 0;
+
+        true
+    }
+
+    fn complicate(&mut self) -> bool {
+        match self.previous.take() {
+            Some((current, candidates, next)) => {
+                self.current = current;
+                self.candidates = candidates;
+                self.next = next;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::test_runner::Config;
+    use proptest::test_runner::TestCaseError;
+
+    use super::*;
+
+    #[test]
+    fn test_values_match() {
+        let strategy = RegexStrategy::new("[a-z]{1,8}@[a-z]{2,5}\\.com").unwrap();
+        let dfa = Dfa::compile("[a-z]{1,8}@[a-z]{2,5}\\.com").unwrap();
+
+        TestRunner::default()
+            .run(&strategy, |email| {
+                assert!(dfa.is_match(&email));
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_shrinks_to_minimal_failure() {
+        let strategy = RegexStrategy::new("[a-z]{1,10}[0-9]{1,3}").unwrap();
+
+        let config = Config {
+            failure_persistence: None,
+            ..Config::default()
+        };
+        let result = TestRunner::new(config).run(&strategy, |value| {
+            if value.len() >= 4 {
+                return Err(TestCaseError::fail("too long"));
+            }
+            Ok(())
+        });
+
+        match result {
+            Err(proptest::test_runner::TestError::Fail(_, value)) => assert_eq!(value, "a000"),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}